@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS nutrient_measurements
+(
+    id            INTEGER PRIMARY KEY NOT NULL,
+    measure_time  INTEGER             NOT NULL,
+    label         TEXT                NOT NULL,
+    ph            REAL,
+    conductivity  REAL,
+    temperature   REAL
+);
@@ -0,0 +1,11 @@
+ALTER TABLE light_measurements ADD COLUMN ppfd REAL;
+ALTER TABLE light_measurements ADD COLUMN f1 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f2 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f3 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f4 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f5 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f6 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f7 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN f8 INTEGER;
+ALTER TABLE light_measurements ADD COLUMN clear INTEGER;
+ALTER TABLE light_measurements ADD COLUMN nir INTEGER;
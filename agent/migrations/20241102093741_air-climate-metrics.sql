@@ -0,0 +1,3 @@
+ALTER TABLE air_measurements ADD COLUMN vpd REAL;
+ALTER TABLE air_measurements ADD COLUMN dew_point REAL;
+ALTER TABLE air_measurements ADD COLUMN absolute_humidity REAL;
@@ -0,0 +1,8 @@
+ALTER TABLE air_measurements ADD COLUMN iaq REAL;
+
+CREATE TABLE IF NOT EXISTS gas_baselines
+(
+    label         TEXT PRIMARY KEY    NOT NULL,
+    resistance    REAL                NOT NULL,
+    update_time   INTEGER             NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE water_level_measurements ADD COLUMN range_status TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE water_level_measurements ADD COLUMN fill_level REAL;
+ALTER TABLE water_level_measurements ADD COLUMN volume REAL;
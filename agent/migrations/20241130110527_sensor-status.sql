@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS sensor_status
+(
+    kind                    TEXT        NOT NULL,
+    label                   TEXT        NOT NULL,
+    failures                INTEGER     NOT NULL,
+    retries                 INTEGER     NOT NULL,
+    reinits                 INTEGER     NOT NULL,
+    consecutive_failures    INTEGER     NOT NULL,
+    last_error              TEXT,
+    last_failure_time       INTEGER,
+    PRIMARY KEY (kind, label)
+);
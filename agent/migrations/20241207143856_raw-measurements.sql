@@ -0,0 +1,13 @@
+ALTER TABLE air_measurements ADD COLUMN raw_temperature REAL;
+ALTER TABLE air_measurements ADD COLUMN raw_humidity REAL;
+ALTER TABLE air_measurements ADD COLUMN raw_pressure REAL;
+ALTER TABLE air_measurements ADD COLUMN raw_resistance REAL;
+
+ALTER TABLE light_measurements ADD COLUMN raw_illuminance REAL;
+ALTER TABLE light_measurements ADD COLUMN raw_ppfd REAL;
+
+ALTER TABLE water_level_measurements ADD COLUMN raw_distance INTEGER;
+
+ALTER TABLE nutrient_measurements ADD COLUMN raw_ph REAL;
+ALTER TABLE nutrient_measurements ADD COLUMN raw_conductivity REAL;
+ALTER TABLE nutrient_measurements ADD COLUMN raw_temperature REAL;
@@ -0,0 +1,9 @@
+-- measure times were stored in seconds, larger values are milliseconds already
+UPDATE air_measurements SET measure_time = measure_time * 1000 WHERE measure_time < 100000000000;
+UPDATE light_measurements SET measure_time = measure_time * 1000 WHERE measure_time < 100000000000;
+UPDATE water_level_measurements SET measure_time = measure_time * 1000 WHERE measure_time < 100000000000;
+UPDATE nutrient_measurements SET measure_time = measure_time * 1000 WHERE measure_time < 100000000000;
+
+-- the same applies to the other timestamps of the agent
+UPDATE sensor_status SET last_failure_time = last_failure_time * 1000 WHERE last_failure_time < 100000000000;
+UPDATE gas_baselines SET update_time = update_time * 1000 WHERE update_time < 100000000000;
@@ -0,0 +1 @@
+ALTER TABLE sensor_status ADD COLUMN present BOOLEAN NOT NULL DEFAULT TRUE;
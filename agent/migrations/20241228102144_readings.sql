@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS readings
+(
+    id              INTEGER PRIMARY KEY NOT NULL,
+    measure_time    INTEGER             NOT NULL,
+    label           TEXT                NOT NULL,
+    model           TEXT                NOT NULL,
+    quantity        TEXT                NOT NULL,
+    unit            TEXT                NOT NULL,
+    value           REAL,
+    raw_value       REAL
+);
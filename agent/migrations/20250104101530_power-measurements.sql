@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS power_measurements
+(
+    id            INTEGER PRIMARY KEY NOT NULL,
+    measure_time  INTEGER             NOT NULL,
+    label         TEXT                NOT NULL,
+    controller    TEXT,
+    load_active   BOOLEAN,
+    voltage       REAL,
+    current       REAL,
+    power         REAL,
+    raw_voltage   REAL,
+    raw_current   REAL,
+    raw_power     REAL
+);
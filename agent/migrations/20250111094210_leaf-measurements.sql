@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS leaf_measurements
+(
+    id                       INTEGER PRIMARY KEY NOT NULL,
+    measure_time             INTEGER             NOT NULL,
+    label                    TEXT                NOT NULL,
+    object_temperature       REAL,
+    ambient_temperature      REAL,
+    raw_object_temperature   REAL,
+    raw_ambient_temperature  REAL
+);
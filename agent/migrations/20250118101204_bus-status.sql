@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS bus_status
+(
+    path                TEXT PRIMARY KEY    NOT NULL,
+    transactions        INTEGER             NOT NULL,
+    errors              INTEGER             NOT NULL,
+    nacks               INTEGER             NOT NULL,
+    timeouts            INTEGER             NOT NULL,
+    arbitration_lost    INTEGER             NOT NULL,
+    update_time         INTEGER             NOT NULL
+);
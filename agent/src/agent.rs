@@ -1,20 +1,29 @@
-use std::env;
+use std::{collections::HashMap, env, time::Duration};
 
 use crate::{
     air_manager::AirManager,
     config::Config,
     control::Controller,
     datastore::DataStore,
+    generic_sampler::GenericSampler,
+    leaf_sampler::LeafSampler,
     light_sampler::LightSampler,
+    measure::{BusStatus, ControllerKind, I2cBus},
+    nutrient_sampler::NutrientSampler,
+    power_sampler::PowerSampler,
     water_level_manager::WaterLevelManager,
 };
 use anyhow::{Context, Result};
+use chrono::Utc;
 use tokio::{
     signal::unix::{signal, SignalKind},
     task::{spawn_blocking, JoinSet},
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{debug_span, info, Instrument as _};
+use tracing::{debug_span, info, warn, Instrument as _};
+
+/// How often the statistics of the I2C buses are stored.
+const BUS_STATUS_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct Agent {
@@ -55,6 +64,32 @@ impl Agent {
         Ok(dir)
     }
 
+    /// Stores the statistics of all I2C buses periodically and once more on shutdown, so that
+    /// a flaky bus can be noticed while the agent runs.
+    async fn record_bus_status(store: DataStore, cancel_token: CancellationToken) -> Result<()> {
+        let mut interval = tokio::time::interval(BUS_STATUS_INTERVAL);
+        loop {
+            let cancelled = tokio::select! {
+                _ = cancel_token.cancelled() => true,
+                _ = interval.tick() => false,
+            };
+
+            let update_time = Utc::now().timestamp_millis();
+            for bus in I2cBus::all() {
+                // the statistics are not worth stopping the controllers for
+                if let Err(err) = store
+                    .update_bus_status(&BusStatus::new(bus.path(), bus.stats(), update_time))
+                    .await
+                {
+                    warn!("{err:#}");
+                }
+            }
+            if cancelled {
+                return Ok(());
+            }
+        }
+    }
+
     pub async fn run(self) -> Result<()> {
         let mut sigint =
             signal(SignalKind::interrupt()).context("Failed to register SIGINT handler")?;
@@ -95,15 +130,59 @@ impl Agent {
         .await
         .context("Failed to initilaize light sampler")?;
 
+        let nutrient_sampler = NutrientSampler::new(
+            &self.config.nutrient.sample,
+            &self.config.i2c_path,
+            store.clone(),
+        )
+        .await
+        .context("Failed to initialize nutrient sampler")?;
+
+        let generic_sampler = GenericSampler::new(
+            &self.config.generic.sample,
+            &self.config.i2c_path,
+            &self.config.gpio_path,
+            store.clone(),
+        )
+        .await
+        .context("Failed to initialize generic sampler")?;
+
+        let leaf_sampler = LeafSampler::new(
+            &self.config.leaf.sample,
+            &self.config.i2c_path,
+            store.clone(),
+        )
+        .await
+        .context("Failed to initialize leaf sampler")?;
+
         let water_level_manager = WaterLevelManager::new(
             &self.config.water_level,
-            store,
+            store.clone(),
             &self.config.i2c_path,
             &self.config.gpio_path,
         )
         .await
         .context("Failed to initialize water level manager")?;
 
+        let controllers = HashMap::from([
+            (ControllerKind::Air, air_manager.controller_state()),
+            (ControllerKind::AirPump, air_pump_controller.state()),
+            (ControllerKind::Fan, fan_controller.state()),
+            (ControllerKind::Light, light_controller.state()),
+            (
+                ControllerKind::WaterLevel,
+                water_level_manager.controller_state(),
+            ),
+        ]);
+        let power_sampler = PowerSampler::new(
+            &self.config.power.sample,
+            &self.config.i2c_path,
+            controllers,
+            store.clone(),
+        )
+        .await
+        .context("Failed to initialize power sampler")?;
+
         let cancel_token = CancellationToken::new();
         let mut set = JoinSet::new();
         set.spawn(
@@ -131,11 +210,35 @@ impl Agent {
                 .run(cancel_token.clone())
                 .instrument(debug_span!("light sampler")),
         );
+        set.spawn(
+            nutrient_sampler
+                .run(cancel_token.clone())
+                .instrument(debug_span!("nutrient sampler")),
+        );
+        set.spawn(
+            generic_sampler
+                .run(cancel_token.clone())
+                .instrument(debug_span!("generic sampler")),
+        );
         set.spawn(
             water_level_manager
                 .run(cancel_token.clone())
                 .instrument(debug_span!("water level manager")),
         );
+        set.spawn(
+            power_sampler
+                .run(cancel_token.clone())
+                .instrument(debug_span!("power sampler")),
+        );
+        set.spawn(
+            leaf_sampler
+                .run(cancel_token.clone())
+                .instrument(debug_span!("leaf sampler")),
+        );
+        set.spawn(
+            Self::record_bus_status(store, cancel_token.clone())
+                .instrument(debug_span!("bus status")),
+        );
 
         loop {
             tokio::select! {
@@ -154,6 +257,9 @@ impl Agent {
                                 .context("Failed to run task")?;
                         },
                         None => {
+                            for bus in I2cBus::all() {
+                                info!("I2C bus {:?}: {}", bus.path(), bus.stats());
+                            }
                             info!("All tasks terminated successfully");
                             return Ok(());
                         }
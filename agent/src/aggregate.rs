@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use tracing::debug;
+
+use crate::{
+    calibration::Calibration,
+    config::aggregate::{AggregateMethod, LimitConfig},
+    measure::Quantities,
+};
+
+const MILLIS_PER_MIN: f64 = 60_000.;
+
+/// Combines the values of multiple readings, ignoring missing ones.
+pub fn combine(method: &AggregateMethod, values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+
+    match method {
+        AggregateMethod::Median => {
+            let mid = values.len() / 2;
+            if values.len() % 2 == 0 {
+                Some((values[mid - 1] + values[mid]) / 2.)
+            } else {
+                Some(values[mid])
+            }
+        }
+        AggregateMethod::TrimmedMean(fraction) => {
+            // always keep at least one value
+            let trim = (values.len() as f64 * fraction.clamp(0., 0.5)) as usize;
+            let trim = trim.min((values.len() - 1) / 2);
+            let kept = &values[trim..values.len() - trim];
+            Some(kept.iter().sum::<f64>() / kept.len() as f64)
+        }
+    }
+}
+
+/// Combines multiple readings of a sensor into one. The reading with the most values, and the
+/// latest of those, is used as the base, e.g. for the measure time.
+pub fn combine_readings<T: Quantities>(method: &AggregateMethod, readings: Vec<T>) -> Option<T> {
+    if readings.len() < 2 {
+        return readings.into_iter().next();
+    }
+
+    let names = readings[0].quantity_names();
+    let mut values = names
+        .iter()
+        .map(|name| {
+            let mut values = readings
+                .iter()
+                .filter_map(|r| r.quantity(name))
+                .collect::<Vec<_>>();
+            (*name, combine(method, &mut values))
+        })
+        .collect::<Vec<_>>();
+
+    let mut combined = readings
+        .into_iter()
+        .enumerate()
+        .max_by_key(|(i, r)| {
+            let count = names
+                .iter()
+                .filter(|name| r.quantity(name).is_some())
+                .count();
+            (count, *i)
+        })
+        .map(|(_, r)| r)?;
+    for (name, value) in values.drain(..) {
+        combined.set_quantity(name, value);
+    }
+
+    Some(combined)
+}
+
+/// Discards implausible values of a single sensor.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    limits: Vec<(&'static str, LimitConfig)>,
+    /// The time in milliseconds and value of the last plausible value of each quantity.
+    last: HashMap<&'static str, (i64, f64)>,
+}
+
+impl Limits {
+    pub fn new<T: Quantities>(config: &HashMap<String, LimitConfig>) -> Result<Self> {
+        Self::with_quantities(T::QUANTITIES, config)
+    }
+
+    /// Creates the limits of quantities that depend on the sensor model.
+    pub fn with_quantities(
+        quantities: &[&'static str],
+        config: &HashMap<String, LimitConfig>,
+    ) -> Result<Self> {
+        let mut limits = Vec::with_capacity(config.len());
+        for (name, limit) in config {
+            let Some(name) = quantities.iter().find(|q| *q == name) else {
+                bail!(
+                    "Unknown quantity {name:?}, expected one of {}",
+                    quantities.join(", ")
+                );
+            };
+            if let (Some(min), Some(max)) = (limit.min, limit.max) {
+                if min > max {
+                    bail!("The minimum of {name:?} must not be greater than the maximum");
+                }
+            }
+            if limit.max_rate_per_min.is_some_and(|rate| rate <= 0.) {
+                bail!("The maximum rate of change of {name:?} must be positive");
+            }
+
+            limits.push((*name, limit.clone()));
+        }
+
+        Ok(Self {
+            limits,
+            last: HashMap::new(),
+        })
+    }
+
+    /// Removes values of a single uncalibrated reading whose calibrated value is outside of
+    /// the minimum and maximum.
+    pub fn check_range<T: Quantities>(&self, reading: &mut T, calibration: &Calibration) {
+        for (name, limit) in &self.limits {
+            let Some(value) = reading.quantity(name).map(|v| calibration.apply(name, v)) else {
+                continue;
+            };
+            if limit.min.is_some_and(|min| value < min) || limit.max.is_some_and(|max| value > max)
+            {
+                debug!("Discarding implausible {name} of {value}");
+                reading.set_quantity(name, None);
+            }
+        }
+    }
+
+    /// Removes values that changed faster than the maximum rate since the last plausible
+    /// value, the time is given in milliseconds since unix epoch.
+    pub fn check_rate<T: Quantities>(&mut self, reading: &mut T, time: i64) {
+        for (name, limit) in &self.limits {
+            let Some(value) = reading.quantity(name) else {
+                continue;
+            };
+
+            if let (Some(rate), Some((last_time, last_value))) =
+                (limit.max_rate_per_min, self.last.get(name))
+            {
+                let minutes = (time - last_time) as f64 / MILLIS_PER_MIN;
+                if (value - last_value).abs() > rate * minutes {
+                    debug!("Discarding {name} of {value} that changed too fast from {last_value}");
+                    reading.set_quantity(name, None);
+                    continue;
+                }
+            }
+
+            self.last.insert(name, (time, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::WaterLevelMeasurement;
+
+    #[test]
+    fn combine_ok() {
+        let median = AggregateMethod::Median;
+        assert_eq!(combine(&median, &mut []), None);
+        assert_eq!(combine(&median, &mut [3., 1., 2.]), Some(2.));
+        assert_eq!(combine(&median, &mut [4., 1., 3., 2.]), Some(2.5));
+
+        let trimmed = AggregateMethod::TrimmedMean(0.2);
+        assert_eq!(combine(&trimmed, &mut [100., 1., 2., 3., -50.]), Some(2.));
+        assert_eq!(combine(&trimmed, &mut [1., 3.]), Some(2.));
+        assert_eq!(
+            combine(&AggregateMethod::TrimmedMean(0.5), &mut [1., 2., 30.]),
+            Some(2.)
+        );
+    }
+
+    #[test]
+    fn combine_readings_ok() {
+        let readings = vec![
+            WaterLevelMeasurement::new(1, "test".into()).distance(500),
+            WaterLevelMeasurement::new(2, "test".into()).distance(510),
+            WaterLevelMeasurement::new(3, "test".into()).distance(700),
+            WaterLevelMeasurement::new(4, "test".into()),
+        ];
+
+        let combined = combine_readings(&AggregateMethod::Median, readings).unwrap();
+        assert_eq!(combined.measure_time, 3);
+        assert_eq!(combined.distance, Some(510));
+    }
+
+    #[test]
+    fn limits_ok() {
+        let config = HashMap::from([(
+            "distance".to_owned(),
+            LimitConfig {
+                min: Some(100.),
+                max: Some(1000.),
+                max_rate_per_min: Some(10.),
+            },
+        )]);
+        let mut limits = Limits::new::<WaterLevelMeasurement>(&config).unwrap();
+
+        let mut reading = WaterLevelMeasurement::new(0, "test".into()).distance(50);
+        limits.check_range(&mut reading, &Calibration::default());
+        assert_eq!(reading.distance, None);
+
+        let mut reading = WaterLevelMeasurement::new(0, "test".into()).distance(500);
+        limits.check_range(&mut reading, &Calibration::default());
+        limits.check_rate(&mut reading, 0);
+        assert_eq!(reading.distance, Some(500));
+
+        // 30 mm within one minute
+        let mut reading = WaterLevelMeasurement::new(60, "test".into()).distance(530);
+        limits.check_rate(&mut reading, 60_000);
+        assert_eq!(reading.distance, None);
+
+        // 30 mm within three minutes
+        let mut reading = WaterLevelMeasurement::new(180, "test".into()).distance(530);
+        limits.check_rate(&mut reading, 180_000);
+        assert_eq!(reading.distance, Some(530));
+
+        let config = HashMap::from([("height".to_owned(), LimitConfig::default())]);
+        assert!(Limits::new::<WaterLevelMeasurement>(&config).is_err());
+    }
+}
@@ -1,14 +1,27 @@
 use crate::{
-    config::air::{AirConfig, AirSensorConfig, AirSensorModel},
+    config::{
+        air::{AirConfig, AirSensorConfig, AirSensorModel, IaqConfig},
+        i2c::sensor_address,
+    },
     control::Controller,
     datastore::DataStore,
-    measure::{bme680::Bme680, AirMeasurement},
-    sample::Sampler,
+    iaq::IaqEstimator,
+    measure::{
+        bme680::{Bme680, Oversampling},
+        AirMeasurement, SensorKind,
+    },
+    sample::{Sampler, Slot},
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use futures::future::join_all;
-use std::path::Path;
-use tokio::{sync::mpsc, task::JoinSet};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinSet,
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug_span, Instrument};
 
@@ -17,6 +30,8 @@ pub struct AirManager {
     receiver: mpsc::Receiver<Vec<AirMeasurement>>,
     sampler: Sampler<Bme680>,
     store: DataStore,
+    leaf_temperature_offset: f64,
+    iaq_estimators: HashMap<String, IaqEstimator>,
 }
 
 impl AirManager {
@@ -29,29 +44,50 @@ impl AirManager {
         let controller = Controller::new(&config.control, &gpio_path)
             .context("Failed to initialize air controller")?;
 
-        let sensors = join_all(
-            config
-                .sample
-                .sensors
-                .iter()
-                .map(|(label, config)| Self::init_sensor(config, label, i2c_path)),
-        )
-        .await
-        .into_iter()
-        .collect::<Result<Vec<Bme680>>>()?;
-
-        let (sender, receiver) = mpsc::channel(8);
-        let sampler = Sampler::new(config.sample.sample_rate_secs, sender, sensors)
-            .context("Failed to initialize air sampler")?;
+        Self::validate_iaq(&config.sample.iaq)?;
+        for (label, config) in &config.sample.sensors {
+            Self::validate_sensor(config, label)?;
+        }
+        let sensors = join_all(config.sample.sensors.iter().map(|(label, config)| {
+            let (config, sensor_label, i2c_path) =
+                (config.clone(), label.clone(), i2c_path.to_owned());
+            Slot::init(label.clone(), move || {
+                Self::init_sensor(config.clone(), sensor_label.clone(), i2c_path.clone())
+            })
+        }))
+        .await;
+
+        let mut iaq_estimators = HashMap::new();
+        for label in config.sample.sensors.keys() {
+            let baseline = store
+                .gas_baseline(label)
+                .await
+                .context("Failed to load gas baseline")?;
+            iaq_estimators.insert(
+                label.to_owned(),
+                IaqEstimator::new(config.sample.iaq.clone(), baseline),
+            );
+        }
+
+        let (sampler, receiver) =
+            Sampler::from_config(SensorKind::Air, &config.sample, sensors, store.clone())
+                .context("Failed to initialize air sampler")?;
 
         Ok(Self {
             controller,
             receiver,
             sampler,
             store,
+            leaf_temperature_offset: config.sample.leaf_temperature_offset,
+            iaq_estimators,
         })
     }
 
+    /// Returns the state of the air control pin.
+    pub fn controller_state(&self) -> watch::Receiver<Option<bool>> {
+        self.controller.state()
+    }
+
     pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
         let mut set = JoinSet::new();
         set.spawn(
@@ -77,8 +113,16 @@ impl AirManager {
                     }
                 }
                 Some(measurements) = self.receiver.recv() => {
+                    let mut processed = Vec::with_capacity(measurements.len());
+                    for measurement in measurements {
+                        let measurement =
+                            Self::estimate_iaq(&mut self.iaq_estimators, &self.store, measurement)
+                                .await
+                            .context("Failed to estimate indoor air quality")?;
+                        processed.push(measurement.derive_climate(self.leaf_temperature_offset));
+                    }
                     self.store
-                        .add_air_measurements(measurements)
+                        .add_air_measurements(processed)
                         .await
                         .context("Failed to store air measurements")?;
                 }
@@ -86,15 +130,113 @@ impl AirManager {
         }
     }
 
+    async fn estimate_iaq(
+        estimators: &mut HashMap<String, IaqEstimator>,
+        store: &DataStore,
+        measurement: AirMeasurement,
+    ) -> Result<AirMeasurement> {
+        let (Some(resistance), Some(humidity)) = (measurement.resistance, measurement.humidity)
+        else {
+            return Ok(measurement);
+        };
+        let Some(estimator) = estimators.get_mut(&measurement.label) else {
+            return Ok(measurement);
+        };
+
+        let previous_baseline = estimator.baseline();
+        let iaq = estimator.estimate(measurement.measure_time, resistance, humidity);
+        if let Some(baseline) = estimator
+            .baseline()
+            .filter(|b| Some(*b) != previous_baseline)
+        {
+            store
+                .set_gas_baseline(&measurement.label, baseline, measurement.measure_time)
+                .await?;
+        }
+
+        Ok(match iaq {
+            Some(iaq) => measurement.iaq(iaq),
+            None => measurement,
+        })
+    }
+
+    fn validate_iaq(config: &IaqConfig) -> Result<()> {
+        if !(config.humidity_baseline > 0. && config.humidity_baseline < 100.) {
+            bail!(
+                "Humidity baseline of the IAQ must be between 0 and 100, got {}",
+                config.humidity_baseline
+            );
+        }
+        if !(0. ..=1.).contains(&config.humidity_weighting) {
+            bail!(
+                "Humidity weighting of the IAQ must be between 0 and 1, got {}",
+                config.humidity_weighting
+            );
+        }
+
+        Ok(())
+    }
+
+    fn validate_sensor(config: &AirSensorConfig, label: &str) -> Result<()> {
+        match config.model {
+            AirSensorModel::Bme680 => {
+                if config.oversampling.temperature == Oversampling::Skipped {
+                    bail!("Temperature measurement of {label:?} cannot be skipped");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn init_sensor(
-        config: &AirSensorConfig,
-        label: &str,
-        i2c_path: impl AsRef<Path>,
+        config: AirSensorConfig,
+        label: String,
+        i2c_path: PathBuf,
     ) -> Result<Bme680> {
         match config.model {
-            AirSensorModel::Bme680 => Bme680::new(i2c_path, config.address, label.to_owned())
+            AirSensorModel::Bme680 => {
+                let sensor = Bme680::new(
+                    i2c_path,
+                    sensor_address(config.address, config.mux.as_ref()),
+                    label.clone(),
+                )
                 .await
-                .with_context(|| format!("Failed to initialize {:?} air sensor", label)),
+                .with_context(|| format!("Failed to initialize {:?} air sensor", label))?
+                .oversampling(config.oversampling)
+                .iir_filter(config.iir_filter)
+                .heater(config.heater);
+
+                Ok(sensor)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_iaq_ok() {
+        assert!(AirManager::validate_iaq(&IaqConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn validate_iaq_err() {
+        for humidity_baseline in [0., 100., f64::NAN] {
+            let config = IaqConfig {
+                humidity_baseline,
+                ..Default::default()
+            };
+            assert!(AirManager::validate_iaq(&config).is_err());
+        }
+        for humidity_weighting in [-0.1, 1.5, f64::NAN] {
+            let config = IaqConfig {
+                humidity_weighting,
+                ..Default::default()
+            };
+            assert!(AirManager::validate_iaq(&config).is_err());
         }
     }
 }
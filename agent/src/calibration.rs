@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::{config::calibration::CalibrationConfig, measure::Quantities};
+
+/// How the raw value of a single quantity is corrected.
+#[derive(Debug, Clone)]
+enum Correction {
+    Linear {
+        offset: f64,
+        scale: f64,
+    },
+    /// Pairs of raw and reference value, ordered by the raw value.
+    Table(Vec<(f64, f64)>),
+}
+
+impl Correction {
+    fn new(name: &str, config: &CalibrationConfig) -> Result<Self> {
+        match config {
+            CalibrationConfig::Linear { offset, scale } => {
+                if !offset.is_finite() || !scale.is_finite() || *scale == 0. {
+                    bail!("The scale of {name:?} must be finite and not zero");
+                }
+                Ok(Self::Linear {
+                    offset: *offset,
+                    scale: *scale,
+                })
+            }
+            CalibrationConfig::Table { points } => {
+                let mut points: Vec<_> = points.iter().map(|p| (p.raw, p.value)).collect();
+                points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                points.dedup_by(|a, b| a.0 == b.0);
+                if points.len() < 2 {
+                    bail!("The calibration table of {name:?} needs at least two distinct points");
+                }
+                Ok(Self::Table(points))
+            }
+        }
+    }
+
+    fn apply(&self, raw: f64) -> f64 {
+        match self {
+            Self::Linear { offset, scale } => raw * scale + offset,
+            Self::Table(points) => {
+                // the segment that contains the raw value, or the outermost one
+                let i = points
+                    .partition_point(|(r, _)| *r < raw)
+                    .clamp(1, points.len() - 1);
+                let (r0, v0) = points[i - 1];
+                let (r1, v1) = points[i];
+
+                v0 + (raw - r0) * (v1 - v0) / (r1 - r0)
+            }
+        }
+    }
+}
+
+/// Corrects the measured values of a single sensor, e.g. against a reference instrument.
+#[derive(Debug, Clone, Default)]
+pub struct Calibration {
+    corrections: Vec<(&'static str, Correction)>,
+}
+
+impl Calibration {
+    pub fn new<T: Quantities>(config: &HashMap<String, CalibrationConfig>) -> Result<Self> {
+        Self::with_quantities(T::CALIBRATABLE, config)
+    }
+
+    /// Creates the calibration of quantities that depend on the sensor model.
+    pub fn with_quantities(
+        quantities: &[&'static str],
+        config: &HashMap<String, CalibrationConfig>,
+    ) -> Result<Self> {
+        let mut corrections = Vec::with_capacity(config.len());
+        for (name, calibration) in config {
+            let Some(name) = quantities.iter().find(|q| *q == name) else {
+                bail!(
+                    "Quantity {name:?} cannot be calibrated, expected one of {}",
+                    quantities.join(", ")
+                );
+            };
+
+            corrections.push((*name, Correction::new(name, calibration)?));
+        }
+
+        Ok(Self { corrections })
+    }
+
+    /// Returns the corrected value of a quantity, values of quantities that are not calibrated
+    /// are returned unchanged.
+    pub fn apply(&self, name: &str, raw: f64) -> f64 {
+        self.corrections
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map_or(raw, |(_, correction)| correction.apply(raw))
+    }
+
+    /// Corrects the values of a reading and keeps the raw values of calibrated quantities.
+    pub fn calibrate<T: Quantities>(&self, reading: &mut T) {
+        for (name, correction) in &self.corrections {
+            let raw = reading.quantity(name);
+            reading.set_raw_quantity(name, raw);
+            reading.set_quantity(name, raw.map(|r| correction.apply(r)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::calibration::CalibrationPoint,
+        measure::{NutrientMeasurement, WaterLevelMeasurement},
+    };
+
+    #[test]
+    fn apply_ok() {
+        let config = HashMap::from([
+            (
+                "temperature".to_owned(),
+                CalibrationConfig::Linear {
+                    offset: -0.5,
+                    scale: 1.,
+                },
+            ),
+            (
+                "ph".to_owned(),
+                CalibrationConfig::Table {
+                    points: vec![
+                        CalibrationPoint {
+                            raw: 7.2,
+                            value: 7.,
+                        },
+                        CalibrationPoint {
+                            raw: 4.1,
+                            value: 4.,
+                        },
+                        CalibrationPoint {
+                            raw: 10.3,
+                            value: 10.,
+                        },
+                    ],
+                },
+            ),
+        ]);
+        let calibration = Calibration::new::<NutrientMeasurement>(&config).unwrap();
+
+        assert_eq!(calibration.apply("temperature", 21.), 20.5);
+        assert_eq!(calibration.apply("conductivity", 1200.), 1200.);
+        assert!((calibration.apply("ph", 7.2) - 7.).abs() < 1e-9);
+        assert!((calibration.apply("ph", 5.65) - 5.5).abs() < 1e-9);
+        assert!((calibration.apply("ph", 8.75) - 8.5).abs() < 1e-9);
+        // extrapolated
+        assert!((calibration.apply("ph", 3.1) - 3.032_258_065).abs() < 1e-6);
+        assert!((calibration.apply("ph", 11.3) - 10.967_741_935).abs() < 1e-6);
+    }
+
+    #[test]
+    fn calibrate_ok() {
+        let config = HashMap::from([(
+            "distance".to_owned(),
+            CalibrationConfig::Linear {
+                offset: 10.,
+                scale: 0.9,
+            },
+        )]);
+        let calibration = Calibration::new::<WaterLevelMeasurement>(&config).unwrap();
+
+        let mut reading = WaterLevelMeasurement::new(0, "test".into()).distance(500);
+        calibration.calibrate(&mut reading);
+        assert_eq!(reading.distance, Some(460));
+        assert_eq!(reading.raw_distance, Some(500));
+
+        let mut reading = WaterLevelMeasurement::new(0, "test".into());
+        calibration.calibrate(&mut reading);
+        assert_eq!(reading.distance, None);
+        assert_eq!(reading.raw_distance, None);
+    }
+
+    #[test]
+    fn new_err() {
+        let config = HashMap::from([(
+            "height".to_owned(),
+            CalibrationConfig::Linear {
+                offset: 0.,
+                scale: 1.,
+            },
+        )]);
+        assert!(Calibration::new::<WaterLevelMeasurement>(&config).is_err());
+
+        let config = HashMap::from([(
+            "distance".to_owned(),
+            CalibrationConfig::Linear {
+                offset: 0.,
+                scale: 0.,
+            },
+        )]);
+        assert!(Calibration::new::<WaterLevelMeasurement>(&config).is_err());
+
+        let config = HashMap::from([(
+            "distance".to_owned(),
+            CalibrationConfig::Table {
+                points: vec![
+                    CalibrationPoint {
+                        raw: 100.,
+                        value: 90.,
+                    },
+                    CalibrationPoint {
+                        raw: 100.,
+                        value: 95.,
+                    },
+                ],
+            },
+        )]);
+        assert!(Calibration::new::<WaterLevelMeasurement>(&config).is_err());
+    }
+}
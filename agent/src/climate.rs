@@ -0,0 +1,65 @@
+//! Derived climate metrics based on the Magnus formula with the coefficients of
+//! Alduchov and Eskridge (1996).
+
+const MAGNUS_A: f64 = 17.625;
+const MAGNUS_B: f64 = 243.04;
+const MAGNUS_C: f64 = 0.61094;
+
+/// Returns the saturation vapour pressure in kPa at the given temperature in degree celsius.
+pub fn saturation_vapour_pressure(temperature: f64) -> f64 {
+    MAGNUS_C * ((MAGNUS_A * temperature) / (temperature + MAGNUS_B)).exp()
+}
+
+/// Returns the vapour pressure deficit in kPa.
+///
+/// The leaf temperature offset is added to the air temperature to get the leaf temperature,
+/// pass 0 to compute the air VPD.
+pub fn vapour_pressure_deficit(
+    temperature: f64,
+    humidity: f64,
+    leaf_temperature_offset: f64,
+) -> f64 {
+    saturation_vapour_pressure(temperature + leaf_temperature_offset)
+        - saturation_vapour_pressure(temperature) * humidity / 100.
+}
+
+/// Returns the dew point in degree celsius.
+pub fn dew_point(temperature: f64, humidity: f64) -> f64 {
+    let gamma = (humidity / 100.).ln() + (MAGNUS_A * temperature) / (MAGNUS_B + temperature);
+    MAGNUS_B * gamma / (MAGNUS_A - gamma)
+}
+
+/// Returns the absolute humidity in g/m³.
+pub fn absolute_humidity(temperature: f64, humidity: f64) -> f64 {
+    const WATER_GAS_CONSTANT: f64 = 461.5;
+
+    // vapour pressure in Pa divided by the specific gas constant of water vapour and the
+    // temperature in kelvin yields kg/m³
+    let vapour_pressure = saturation_vapour_pressure(temperature) * 1000. * humidity / 100.;
+    vapour_pressure / (WATER_GAS_CONSTANT * (temperature + 273.15)) * 1000.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vapour_pressure_deficit_ok() {
+        assert!((saturation_vapour_pressure(25.) - 3.1617).abs() < 0.001);
+        assert!((vapour_pressure_deficit(25., 60., 0.) - 1.2647).abs() < 0.001);
+        assert!((vapour_pressure_deficit(25., 60., -2.) - 0.9068).abs() < 0.001);
+        assert!(vapour_pressure_deficit(20., 100., 0.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dew_point_ok() {
+        assert!((dew_point(25., 60.) - 16.70).abs() < 0.01);
+        assert!((dew_point(20., 100.) - 20.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn absolute_humidity_ok() {
+        assert!((absolute_humidity(25., 60.) - 13.79).abs() < 0.01);
+        assert!((absolute_humidity(20., 50.) - 8.62).abs() < 0.01);
+    }
+}
@@ -7,14 +7,28 @@ use air::AirConfig;
 use air_pump::AirPumpConfig;
 use anyhow::{Context, Result};
 use fan::FanConfig;
+use generic::GenericConfig;
+use leaf::LeafConfig;
 use light::LightConfig;
+use nutrient::NutrientConfig;
+use power::PowerConfig;
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use water_level::WaterLevelConfig;
 
+pub mod aggregate;
 pub mod air;
 pub mod air_pump;
+pub mod calibration;
 pub mod fan;
+pub mod generic;
+pub mod i2c;
+pub mod leaf;
 pub mod light;
+pub mod nutrient;
+pub mod power;
+pub mod retry;
+pub mod sample;
+pub mod schedule;
 pub mod water_level;
 pub mod control;
 
@@ -36,6 +50,14 @@ pub struct Config {
     pub light: LightConfig,
     #[serde(default)]
     pub water_level: WaterLevelConfig,
+    #[serde(default)]
+    pub nutrient: NutrientConfig,
+    #[serde(default)]
+    pub generic: GenericConfig,
+    #[serde(default)]
+    pub power: PowerConfig,
+    #[serde(default)]
+    pub leaf: LeafConfig,
 }
 
 impl Config {
@@ -57,6 +79,10 @@ impl Default for Config {
             fan: FanConfig::default(),
             light: LightConfig::default(),
             water_level: WaterLevelConfig::default(),
+            nutrient: NutrientConfig::default(),
+            generic: GenericConfig::default(),
+            power: PowerConfig::default(),
+            leaf: LeafConfig::default(),
         }
     }
 }
@@ -86,14 +112,32 @@ where
 mod tests {
     use super::*;
 
-    use air::{AirSampleConfig, AirSensorConfig, AirSensorModel};
+    use air::{AirSampleConfig, AirSensorConfig, AirSensorModel, IaqConfig};
+    use crate::measure::{
+        bh1750fvi::Resolution,
+        bme680::{HeaterProfile, IirFilter, Oversampling, OversamplingSettings},
+        vl53l0x::RangingProfile,
+        vl53l1x::DistanceMode,
+        ControllerKind,
+    };
     use chrono::NaiveTime;
     use control::ControlConfig;
-    use light::{ LightSampleConfig, LightSensorConfig, LightSensorModel};
+    use generic::{GenericSampleConfig, GenericSensorConfig};
+    use i2c::MuxConfig;
+    use leaf::{LeafSampleConfig, LeafSensorConfig, LeafSensorModel};
+    use aggregate::{AggregateConfig, AggregateMethod, LimitConfig};
+    use calibration::{CalibrationConfig, CalibrationPoint};
+    use retry::RetryConfig;
+    use schedule::ScheduleConfig;
+    use light::{LightSampleConfig, LightSensorConfig, LightSensorModel, LightSource};
+    use nutrient::{
+        NutrientSampleConfig, NutrientSensorConfig, NutrientSensorModel, TemperatureCompensation,
+    };
+    use power::{PowerSampleConfig, PowerSensorConfig, PowerSensorModel};
     use std::{collections::HashMap, io::Write};
     use tempfile::NamedTempFile;
     use water_level::{
-        WaterLevelSampleConfig, WaterLevelSensorConfig,
+        TankConfig, TankShape, UltrasonicInterface, WaterLevelSampleConfig, WaterLevelSensorConfig,
         WaterLevelSensorModel,
     };
 
@@ -113,6 +157,10 @@ mod tests {
                 },
                 "sample": {
                     "sample_rate_secs": 1800,
+                    "leaf_temperature_offset": -2.5,
+                    "iaq": {
+                        "burn_in_secs": 600
+                    },
                     "sensors": {
                         "left": {
                             "model": "Bme680",
@@ -120,7 +168,16 @@ mod tests {
                         },
                         "right": {
                             "model": "Bme680",
-                            "address": "0x76"
+                            "address": "0x76",
+                            "sample_rate_secs": 600,
+                            "phase_offset_secs": 30,
+                            "jitter_ms": 500,
+                            "oversampling": {
+                                "temperature": "X8",
+                                "pressure": "Skipped"
+                            },
+                            "iir_filter": "C3",
+                            "heater": null
                         }
                     }
                 }
@@ -157,7 +214,17 @@ mod tests {
                         },
                         "right": {
                             "model": "Bh1750Fvi",
-                            "address": "0x5C"
+                            "address": "0x5C",
+                            "mux": { "address": "0x70", "channel": 2 },
+                            "light_source": "WhiteLed",
+                            "resolution": "High2",
+                            "mt_reg": 69,
+                            "auto_range": true
+                        },
+                        "spectral": {
+                            "model": "As7341",
+                            "address": "0x39",
+                            "light_source": { "Custom": 0.016 }
                         }
                     }
                 }
@@ -171,10 +238,123 @@ mod tests {
                 },
                 "sample": {
                     "sample_rate_secs": 86400,
+                    "aggregate": {
+                        "readings": 5,
+                        "interval_ms": 200,
+                        "method": { "TrimmedMean": 0.2 }
+                    },
+                    "limits": {
+                        "distance": { "min": 50, "max": 2000, "max_rate_per_min": 20 }
+                    },
                     "sensors": {
                         "main": {
                             "model": "Vl53L0X",
-                            "address": "0x29"
+                            "address": "0x29",
+                            "profile": "LongRange",
+                            "timing_budget_ms": 100,
+                            "calibration": {
+                                "distance": { "mode": "Linear", "offset": -12 }
+                            },
+                            "tank": {
+                                "empty_distance": 900,
+                                "full_distance": 100,
+                                "shape": "Cylinder",
+                                "diameter": 560.0
+                            }
+                        },
+                        "barrel": {
+                            "model": "JsnSr04T",
+                            "measure_timeout_ms": 2000,
+                            "interface": {
+                                "mode": "Gpio",
+                                "trigger_pin": 23,
+                                "echo_pin": 24
+                            }
+                        }
+                    }
+                }
+            },
+            "nutrient": {
+                "sample": {
+                    "sample_rate_secs": 600,
+                    "retry": {
+                        "retries": 5,
+                        "reinit_after": 10
+                    },
+                    "sensors": {
+                        "ph": {
+                            "model": "EzoPh",
+                            "address": "0x63",
+                            "calibration": {
+                                "ph": {
+                                    "mode": "Table",
+                                    "points": [
+                                        { "raw": 4.1, "value": 4.0 },
+                                        { "raw": 7.2, "value": 7.0 }
+                                    ]
+                                }
+                            },
+                            "temperature_compensation": {
+                                "mode": "Sensor",
+                                "label": "temperature"
+                            }
+                        },
+                        "ec": {
+                            "model": "EzoEc",
+                            "address": "0x64",
+                            "temperature_compensation": {
+                                "mode": "Fixed",
+                                "temperature": 20.5
+                            }
+                        },
+                        "temperature": {
+                            "model": "EzoRtd",
+                            "address": "0x66"
+                        }
+                    }
+                }
+            },
+            "generic": {
+                "sample": {
+                    "sample_rate_secs": 300,
+                    "sensors": {
+                        "pump": {
+                            "model": "Command",
+                            "measure_timeout_ms": 10000,
+                            "settings": {
+                                "program": "/usr/bin/pump-status"
+                            }
+                        }
+                    }
+                }
+            },
+            "power": {
+                "sample": {
+                    "sample_rate_secs": 10,
+                    "sensors": {
+                        "light": {
+                            "model": "Ina226",
+                            "address": "0x40",
+                            "shunt_resistance": 0.002,
+                            "max_current": 20.0,
+                            "averages": 16,
+                            "controller": "Light"
+                        },
+                        "pi": {
+                            "model": "Ina219",
+                            "address": "0x41"
+                        }
+                    }
+                }
+            },
+            "leaf": {
+                "sample": {
+                    "sample_rate_secs": 60,
+                    "sensors": {
+                        "canopy": {
+                            "model": "Mlx90614",
+                            "address": "0x5a",
+                            "emissivity": 0.95
                         }
                     }
                 }
@@ -193,12 +373,30 @@ mod tests {
                 },
                 sample: AirSampleConfig {
                     sample_rate_secs: 1800,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
+                    leaf_temperature_offset: -2.5,
+                    iaq: IaqConfig {
+                        burn_in_secs: 600,
+                        ..Default::default()
+                    },
                     sensors: HashMap::from([
                         (
                             "left".into(),
                             AirSensorConfig {
                                 model: AirSensorModel::Bme680,
                                 address: 119,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                oversampling: OversamplingSettings::default(),
+                                iir_filter: IirFilter::Off,
+                                heater: Some(HeaterProfile {
+                                    temperature: 300,
+                                    duration_ms: 700,
+                                }),
                             },
                         ),
                         (
@@ -206,6 +404,21 @@ mod tests {
                             AirSensorConfig {
                                 model: AirSensorModel::Bme680,
                                 address: 118,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig {
+                                    sample_rate_secs: Some(600),
+                                    phase_offset_secs: 30,
+                                    jitter_ms: 500,
+                                },
+                                oversampling: OversamplingSettings {
+                                    temperature: Oversampling::X8,
+                                    humidity: Oversampling::X2,
+                                    pressure: Oversampling::Skipped,
+                                },
+                                iir_filter: IirFilter::C3,
+                                heater: None,
                             },
                         ),
                     ]),
@@ -235,12 +448,23 @@ mod tests {
                 },
                 sample: LightSampleConfig {
                     sample_rate_secs: 123,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
                     sensors: HashMap::from([
                         (
                             "left".into(),
                             LightSensorConfig {
                                 model: LightSensorModel::Bh1750Fvi,
                                 address: 35,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                light_source: None,
+                                resolution: Resolution::High,
+                                mt_reg: 31,
+                                auto_range: false,
                             },
                         ),
                         (
@@ -248,6 +472,32 @@ mod tests {
                             LightSensorConfig {
                                 model: LightSensorModel::Bh1750Fvi,
                                 address: 92,
+                                mux: Some(MuxConfig {
+                                    address: 112,
+                                    channel: 2,
+                                }),
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                light_source: Some(LightSource::WhiteLed),
+                                resolution: Resolution::High2,
+                                mt_reg: 69,
+                                auto_range: true,
+                            },
+                        ),
+                        (
+                            "spectral".into(),
+                            LightSensorConfig {
+                                model: LightSensorModel::As7341,
+                                address: 57,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                light_source: Some(LightSource::Custom(0.016)),
+                                resolution: Resolution::High,
+                                mt_reg: 31,
+                                auto_range: false,
                             },
                         ),
                     ]),
@@ -263,11 +513,215 @@ mod tests {
                 },
                 sample: WaterLevelSampleConfig {
                     sample_rate_secs: 86400,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig {
+                        readings: 5,
+                        interval_ms: 200,
+                        method: AggregateMethod::TrimmedMean(0.2),
+                    },
+                    limits: HashMap::from([(
+                        "distance".into(),
+                        LimitConfig {
+                            min: Some(50.),
+                            max: Some(2000.),
+                            max_rate_per_min: Some(20.),
+                        },
+                    )]),
+                    sensors: HashMap::from([
+                        (
+                            "main".into(),
+                            WaterLevelSensorConfig {
+                                model: WaterLevelSensorModel::Vl53L0X,
+                                address: 41,
+                                mux: None,
+                                calibration: HashMap::from([(
+                                    "distance".into(),
+                                    CalibrationConfig::Linear {
+                                        offset: -12.,
+                                        scale: 1.,
+                                    },
+                                )]),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                profile: RangingProfile::LongRange,
+                                timing_budget_ms: Some(100),
+                                signal_rate_limit: None,
+                                distance_mode: DistanceMode::Long,
+                                interface: None,
+                                tank: Some(TankConfig {
+                                    empty_distance: 900,
+                                    full_distance: 100,
+                                    shape: TankShape::Cylinder { diameter: 560. },
+                                }),
+                            },
+                        ),
+                        (
+                            "barrel".into(),
+                            WaterLevelSensorConfig {
+                                model: WaterLevelSensorModel::JsnSr04T,
+                                address: 0,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: Some(2000),
+                                schedule: ScheduleConfig::default(),
+                                profile: RangingProfile::Default,
+                                timing_budget_ms: None,
+                                signal_rate_limit: None,
+                                distance_mode: DistanceMode::Long,
+                                interface: Some(UltrasonicInterface::Gpio {
+                                    trigger_pin: 23,
+                                    echo_pin: 24,
+                                }),
+                                tank: None,
+                            },
+                        ),
+                    ]),
+                },
+            },
+            nutrient: NutrientConfig {
+                sample: NutrientSampleConfig {
+                    sample_rate_secs: 600,
+                    retry: RetryConfig {
+                        retries: 5,
+                        reinit_after: 10,
+                        ..Default::default()
+                    },
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
+                    sensors: HashMap::from([
+                        (
+                            "ph".into(),
+                            NutrientSensorConfig {
+                                model: NutrientSensorModel::EzoPh,
+                                address: 99,
+                                mux: None,
+                                calibration: HashMap::from([(
+                                    "ph".into(),
+                                    CalibrationConfig::Table {
+                                        points: vec![
+                                            CalibrationPoint {
+                                                raw: 4.1,
+                                                value: 4.,
+                                            },
+                                            CalibrationPoint {
+                                                raw: 7.2,
+                                                value: 7.,
+                                            },
+                                        ],
+                                    },
+                                )]),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                temperature_compensation: TemperatureCompensation::Sensor {
+                                    label: "temperature".into(),
+                                },
+                            },
+                        ),
+                        (
+                            "ec".into(),
+                            NutrientSensorConfig {
+                                model: NutrientSensorModel::EzoEc,
+                                address: 100,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                temperature_compensation: TemperatureCompensation::Fixed {
+                                    temperature: 20.5,
+                                },
+                            },
+                        ),
+                        (
+                            "temperature".into(),
+                            NutrientSensorConfig {
+                                model: NutrientSensorModel::EzoRtd,
+                                address: 102,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                temperature_compensation: TemperatureCompensation::Off,
+                            },
+                        ),
+                    ]),
+                },
+            },
+            generic: GenericConfig {
+                sample: GenericSampleConfig {
+                    sample_rate_secs: 300,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
+                    sensors: HashMap::from([(
+                        "pump".into(),
+                        GenericSensorConfig {
+                            model: "Command".into(),
+                            address: 0,
+                            mux: None,
+                            calibration: HashMap::new(),
+                            measure_timeout_ms: Some(10000),
+                            schedule: ScheduleConfig::default(),
+                            settings: serde_json::json!({ "program": "/usr/bin/pump-status" }),
+                        },
+                    )]),
+                },
+            },
+            power: PowerConfig {
+                sample: PowerSampleConfig {
+                    sample_rate_secs: 10,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
+                    sensors: HashMap::from([
+                        (
+                            "light".into(),
+                            PowerSensorConfig {
+                                model: PowerSensorModel::Ina226,
+                                address: 64,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                shunt_resistance: 0.002,
+                                max_current: Some(20.),
+                                averages: 16,
+                                controller: Some(ControllerKind::Light),
+                            },
+                        ),
+                        (
+                            "pi".into(),
+                            PowerSensorConfig {
+                                model: PowerSensorModel::Ina219,
+                                address: 65,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                shunt_resistance: 0.1,
+                                max_current: None,
+                                averages: 1,
+                                controller: None,
+                            },
+                        ),
+                    ]),
+                },
+            },
+            leaf: LeafConfig {
+                sample: LeafSampleConfig {
+                    sample_rate_secs: 60,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
                     sensors: HashMap::from([(
-                        "main".into(),
-                        WaterLevelSensorConfig {
-                            model: WaterLevelSensorModel::Vl53L0X,
-                            address: 41,
+                        "canopy".into(),
+                        LeafSensorConfig {
+                            model: LeafSensorModel::Mlx90614,
+                            address: 90,
+                            mux: None,
+                            calibration: HashMap::new(),
+                            measure_timeout_ms: None,
+                            schedule: ScheduleConfig::default(),
+                            emissivity: Some(0.95),
                         },
                     )]),
                 },
@@ -326,12 +780,23 @@ mod tests {
                 },
                 sample: LightSampleConfig {
                     sample_rate_secs: 123,
+                    retry: RetryConfig::default(),
+                    aggregate: AggregateConfig::default(),
+                    limits: HashMap::new(),
                     sensors: HashMap::from([
                         (
                             "left".into(),
                             LightSensorConfig {
                                 model: LightSensorModel::Bh1750Fvi,
                                 address: 35,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                light_source: None,
+                                resolution: Resolution::High,
+                                mt_reg: 31,
+                                auto_range: false,
                             },
                         ),
                         (
@@ -339,6 +804,14 @@ mod tests {
                             LightSensorConfig {
                                 model: LightSensorModel::Bh1750Fvi,
                                 address: 92,
+                                mux: None,
+                                calibration: HashMap::new(),
+                                measure_timeout_ms: None,
+                                schedule: ScheduleConfig::default(),
+                                light_source: None,
+                                resolution: Resolution::High,
+                                mt_reg: 31,
+                                auto_range: false,
                             },
                         ),
                     ]),
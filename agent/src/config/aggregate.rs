@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// How the readings of a sensor within one sample period are combined.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateConfig {
+    /// The number of readings taken per sample period.
+    #[serde(default = "default_readings")]
+    pub readings: u32,
+    /// The delay between two readings in milliseconds.
+    #[serde(default)]
+    pub interval_ms: u64,
+    /// How the readings are combined into the stored value.
+    #[serde(default)]
+    pub method: AggregateMethod,
+}
+
+impl Default for AggregateConfig {
+    fn default() -> Self {
+        Self {
+            readings: default_readings(),
+            interval_ms: 0,
+            method: AggregateMethod::default(),
+        }
+    }
+}
+
+fn default_readings() -> u32 {
+    1
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub enum AggregateMethod {
+    #[default]
+    Median,
+    /// The mean after discarding the given fraction of the lowest and the highest readings,
+    /// from 0 to 0.5.
+    TrimmedMean(f64),
+}
+
+/// The range of plausible values of a measured quantity. Implausible values are not stored.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LimitConfig {
+    /// The lowest plausible value.
+    #[serde(default)]
+    pub min: Option<f64>,
+    /// The highest plausible value.
+    #[serde(default)]
+    pub max: Option<f64>,
+    /// The maximum plausible change per minute compared to the last plausible value.
+    #[serde(default)]
+    pub max_rate_per_min: Option<f64>,
+}
@@ -1,7 +1,15 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use super::control::ControlConfig;
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    control::ControlConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
+use crate::measure::bme680::{HeaterProfile, IirFilter, OversamplingSettings};
 
 #[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct AirConfig {
@@ -19,18 +27,95 @@ pub struct AirSampleConfig {
     /// The air sensors in use.
     #[serde(default)]
     pub sensors: HashMap<String, AirSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
+    /// The difference between leaf and air temperature in degree celsius, used to compute
+    /// the leaf vapour pressure deficit.
+    #[serde(default)]
+    pub leaf_temperature_offset: f64,
+    /// The configuration of the indoor air quality estimation.
+    #[serde(default)]
+    pub iaq: IaqConfig,
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct AirSensorConfig {
     /// The type of the air sensor.
     pub model: AirSensorModel,
     /// The address of the air sensor.
     #[serde(deserialize_with = "super::from_hex")]
     pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The oversampling of temperature, humidity and pressure.
+    #[serde(default)]
+    pub oversampling: OversamplingSettings,
+    /// The IIR filter coefficient for temperature and pressure.
+    #[serde(default)]
+    pub iir_filter: IirFilter,
+    /// The heater profile for gas measurements, `null` disables gas measurements.
+    #[serde(default = "default_heater")]
+    pub heater: Option<HeaterProfile>,
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum AirSensorModel {
     Bme680,
 }
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct IaqConfig {
+    /// The duration in seconds after startup in which no IAQ is computed.
+    #[serde(default = "default_burn_in_secs")]
+    pub burn_in_secs: u64,
+    /// The optimal relative humidity in percentage.
+    #[serde(default = "default_humidity_baseline")]
+    pub humidity_baseline: f64,
+    /// The share of the humidity in the IAQ, between 0 and 1.
+    #[serde(default = "default_humidity_weighting")]
+    pub humidity_weighting: f64,
+}
+
+impl Default for IaqConfig {
+    fn default() -> Self {
+        Self {
+            burn_in_secs: default_burn_in_secs(),
+            humidity_baseline: default_humidity_baseline(),
+            humidity_weighting: default_humidity_weighting(),
+        }
+    }
+}
+
+fn default_heater() -> Option<HeaterProfile> {
+    Some(HeaterProfile::default())
+}
+
+fn default_burn_in_secs() -> u64 {
+    300
+}
+
+fn default_humidity_baseline() -> f64 {
+    40.
+}
+
+fn default_humidity_weighting() -> f64 {
+    0.25
+}
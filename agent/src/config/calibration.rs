@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+/// How the raw value of a measured quantity is corrected before it's stored.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum CalibrationConfig {
+    /// The value is computed as `raw * scale + offset`.
+    Linear {
+        #[serde(default)]
+        offset: f64,
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    /// The value is interpolated linearly between reference points, values outside of the
+    /// table are extrapolated from the outermost points.
+    Table { points: Vec<CalibrationPoint> },
+}
+
+fn default_scale() -> f64 {
+    1.
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationPoint {
+    /// The value reported by the sensor.
+    pub raw: f64,
+    /// The reference value at this point.
+    pub value: f64,
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct GenericConfig {
+    #[serde(default)]
+    pub sample: GenericSampleConfig,
+}
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct GenericSampleConfig {
+    /// The rate in which the sensors take measurements in seconds.
+    #[serde(default)]
+    pub sample_rate_secs: u64,
+    /// The sensors of registered models in use.
+    #[serde(default)]
+    pub sensors: HashMap<String, GenericSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name, applied to every sensor that
+    /// measures the quantity.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct GenericSensorConfig {
+    /// The name of the registered model of the sensor.
+    pub model: String,
+    /// The address of the sensor, only used by I2C sensors.
+    #[serde(default, deserialize_with = "super::from_hex")]
+    pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The settings of the model.
+    #[serde(default)]
+    pub settings: Value,
+}
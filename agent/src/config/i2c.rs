@@ -0,0 +1,61 @@
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+use crate::measure::I2cAddress;
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct MuxConfig {
+    /// The address of the TCA9548A multiplexer.
+    #[serde(deserialize_with = "super::from_hex")]
+    pub address: u8,
+    /// The channel of the multiplexer the sensor is connected to, from 0 to 7.
+    #[serde(deserialize_with = "mux_channel")]
+    pub channel: u8,
+}
+
+/// The number of channels of a TCA9548A.
+const MUX_CHANNELS: u8 = 8;
+
+fn mux_channel<'de, D>(deserializer: D) -> Result<u8, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let channel = u8::deserialize(deserializer)?;
+    if channel >= MUX_CHANNELS {
+        return Err(D::Error::custom(format!(
+            "multiplexer channel {channel} is out of range, must be from 0 to {}",
+            MUX_CHANNELS - 1
+        )));
+    }
+
+    Ok(channel)
+}
+
+/// Returns the address of a sensor that is optionally connected via a multiplexer.
+pub fn sensor_address(address: u8, mux: Option<&MuxConfig>) -> I2cAddress {
+    match mux {
+        Some(mux) => I2cAddress::new(address).mux(mux.address, mux.channel),
+        None => I2cAddress::new(address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn mux_config_ok() {
+        let mux: MuxConfig =
+            serde_json::from_value(json!({ "address": "0x70", "channel": 7 })).unwrap();
+        assert_eq!(
+            sensor_address(0x40, Some(&mux)),
+            I2cAddress::new(0x40).mux(0x70, 7)
+        );
+    }
+
+    #[test]
+    fn mux_config_err() {
+        let mux = serde_json::from_value::<MuxConfig>(json!({ "address": "0x70", "channel": 8 }));
+        assert!(mux.is_err());
+    }
+}
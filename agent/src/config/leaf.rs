@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct LeafConfig {
+    #[serde(default)]
+    pub sample: LeafSampleConfig,
+}
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct LeafSampleConfig {
+    /// The rate in which the leaf sensors take measurements in seconds.
+    #[serde(default)]
+    pub sample_rate_secs: u64,
+    /// The leaf sensors in use.
+    #[serde(default)]
+    pub sensors: HashMap<String, LeafSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct LeafSensorConfig {
+    /// The model of the leaf sensor.
+    pub model: LeafSensorModel,
+    /// The address of the leaf sensor.
+    #[serde(deserialize_with = "super::from_hex")]
+    pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The emissivity of the measured surface between 0 and 1. Defaults to the emissivity
+    /// the sensor is configured with.
+    #[serde(default)]
+    pub emissivity: Option<f64>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum LeafSensorModel {
+    Mlx90614,
+}
@@ -1,7 +1,16 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use super::control::ControlConfig;
+use crate::measure::bh1750fvi::{Resolution, MT_REG_MIN};
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    control::ControlConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
 
 #[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct LightConfig {
@@ -19,18 +28,87 @@ pub struct LightSampleConfig {
     /// The light sensors in use.
     #[serde(default)]
     pub sensors: HashMap<String, LightSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct LightSensorConfig {
     /// The type of the light sensor.
     pub model: LightSensorModel,
     /// The address of the light sensor.
     #[serde(deserialize_with = "super::from_hex")]
     pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The light source the sensor is exposed to, used to estimate the PPFD
+    /// from the illuminance.
+    #[serde(default)]
+    pub light_source: Option<LightSource>,
+    /// The resolution mode of a BH1750FVI.
+    #[serde(default)]
+    pub resolution: Resolution,
+    /// The measurement time register of a BH1750FVI, from 31 to 254.
+    /// Higher values increase the sensitivity.
+    #[serde(default = "default_mt_reg")]
+    pub mt_reg: u8,
+    /// Whether a BH1750FVI adjusts the measurement time register automatically
+    /// when it saturates or it's dark.
+    #[serde(default)]
+    pub auto_range: bool,
+}
+
+fn default_mt_reg() -> u8 {
+    MT_REG_MIN
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum LightSensorModel {
     Bh1750Fvi,
+    As7341,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum LightSource {
+    Sunlight,
+    WhiteLed,
+    HighPressureSodium,
+    MetalHalide,
+    Fluorescent,
+    Incandescent,
+    /// A custom factor to convert lux to µmol/m²/s.
+    Custom(f64),
+}
+
+impl LightSource {
+    /// Returns the factor to convert illuminance in lux to PPFD in µmol/m²/s.
+    pub fn ppfd_factor(&self) -> f64 {
+        match self {
+            LightSource::Sunlight => 0.0185,
+            LightSource::WhiteLed => 0.0150,
+            LightSource::HighPressureSodium => 0.0122,
+            LightSource::MetalHalide => 0.0141,
+            LightSource::Fluorescent => 0.0135,
+            LightSource::Incandescent => 0.0200,
+            LightSource::Custom(factor) => *factor,
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct NutrientConfig {
+    #[serde(default)]
+    pub sample: NutrientSampleConfig,
+}
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct NutrientSampleConfig {
+    /// The rate in which the nutrient sensors take measurements in seconds.
+    #[serde(default)]
+    pub sample_rate_secs: u64,
+    /// The nutrient sensors in use.
+    #[serde(default)]
+    pub sensors: HashMap<String, NutrientSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct NutrientSensorConfig {
+    /// The model of the nutrient sensor.
+    pub model: NutrientSensorModel,
+    /// The address of the nutrient sensor.
+    #[serde(deserialize_with = "super::from_hex")]
+    pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The water temperature used to compensate pH and EC readings.
+    #[serde(default)]
+    pub temperature_compensation: TemperatureCompensation,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum NutrientSensorModel {
+    EzoPh,
+    EzoEc,
+    EzoRtd,
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum TemperatureCompensation {
+    /// Use the temperature stored on the circuit.
+    #[default]
+    Off,
+    /// Compensate with a fixed water temperature.
+    Fixed {
+        /// The water temperature in degree celsius.
+        temperature: f64,
+    },
+    /// Compensate with the latest temperature measured by another nutrient sensor.
+    Sensor {
+        /// The label of the temperature sensor.
+        label: String,
+    },
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::measure::ControllerKind;
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct PowerConfig {
+    #[serde(default)]
+    pub sample: PowerSampleConfig,
+}
+
+#[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct PowerSampleConfig {
+    /// The rate in which the power sensors take measurements in seconds.
+    #[serde(default)]
+    pub sample_rate_secs: u64,
+    /// The power sensors in use.
+    #[serde(default)]
+    pub sensors: HashMap<String, PowerSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct PowerSensorConfig {
+    /// The model of the power sensor.
+    pub model: PowerSensorModel,
+    /// The address of the power sensor.
+    #[serde(deserialize_with = "super::from_hex")]
+    pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The resistance of the shunt resistor in ohms.
+    #[serde(default = "default_shunt_resistance")]
+    pub shunt_resistance: f64,
+    /// The largest expected current in amperes, which determines the resolution. Defaults to
+    /// the current at the full shunt voltage range of the model.
+    #[serde(default)]
+    pub max_current: Option<f64>,
+    /// The number of samples averaged in a single measurement, rounded down to a number the
+    /// model supports.
+    #[serde(default = "default_averages")]
+    pub averages: u16,
+    /// The controller that switches the measured load, if any.
+    #[serde(default)]
+    pub controller: Option<ControllerKind>,
+}
+
+fn default_shunt_resistance() -> f64 {
+    0.1
+}
+
+fn default_averages() -> u16 {
+    1
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum PowerSensorModel {
+    Ina219,
+    Ina226,
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// How failed measurements of a single sensor are handled. A failing sensor never stops the
+/// other sensors from sampling.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// How often a failed measurement is retried before the sample is skipped.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// The delay before the first retry in milliseconds, doubled with every further retry.
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// The number of consecutive failed samples after which the sensor is initialized again.
+    #[serde(default = "default_reinit_after")]
+    pub reinit_after: u32,
+    /// The minimum time between two initializations of a failing sensor in seconds.
+    #[serde(default = "default_reinit_interval_secs")]
+    pub reinit_interval_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            retries: default_retries(),
+            backoff_ms: default_backoff_ms(),
+            reinit_after: default_reinit_after(),
+            reinit_interval_secs: default_reinit_interval_secs(),
+        }
+    }
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+fn default_reinit_after() -> u32 {
+    3
+}
+
+fn default_reinit_interval_secs() -> u64 {
+    300
+}
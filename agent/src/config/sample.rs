@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    air::{AirSampleConfig, AirSensorConfig},
+    calibration::CalibrationConfig,
+    generic::{GenericSampleConfig, GenericSensorConfig},
+    leaf::{LeafSampleConfig, LeafSensorConfig},
+    light::{LightSampleConfig, LightSensorConfig},
+    nutrient::{NutrientSampleConfig, NutrientSensorConfig},
+    power::{PowerSampleConfig, PowerSensorConfig},
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+    water_level::{WaterLevelSampleConfig, WaterLevelSensorConfig},
+};
+
+/// The settings that every section of sensors shares.
+pub trait SampleConfig {
+    type Sensor: SensorConfig;
+
+    fn sample_rate_secs(&self) -> u64;
+    fn sensors(&self) -> &HashMap<String, Self::Sensor>;
+    fn retry(&self) -> &RetryConfig;
+    fn aggregate(&self) -> &AggregateConfig;
+    fn limits(&self) -> &HashMap<String, LimitConfig>;
+}
+
+/// The settings that every sensor shares, regardless of its model.
+pub trait SensorConfig {
+    fn calibration(&self) -> &HashMap<String, CalibrationConfig>;
+    fn measure_timeout_ms(&self) -> Option<u64>;
+    fn schedule(&self) -> &ScheduleConfig;
+}
+
+macro_rules! impl_sample_config {
+    ($($sample:ty => $sensor:ty),* $(,)?) => {
+        $(
+            impl SampleConfig for $sample {
+                type Sensor = $sensor;
+
+                fn sample_rate_secs(&self) -> u64 {
+                    self.sample_rate_secs
+                }
+
+                fn sensors(&self) -> &HashMap<String, Self::Sensor> {
+                    &self.sensors
+                }
+
+                fn retry(&self) -> &RetryConfig {
+                    &self.retry
+                }
+
+                fn aggregate(&self) -> &AggregateConfig {
+                    &self.aggregate
+                }
+
+                fn limits(&self) -> &HashMap<String, LimitConfig> {
+                    &self.limits
+                }
+            }
+
+            impl SensorConfig for $sensor {
+                fn calibration(&self) -> &HashMap<String, CalibrationConfig> {
+                    &self.calibration
+                }
+
+                fn measure_timeout_ms(&self) -> Option<u64> {
+                    self.measure_timeout_ms
+                }
+
+                fn schedule(&self) -> &ScheduleConfig {
+                    &self.schedule
+                }
+            }
+        )*
+    };
+}
+
+impl_sample_config!(
+    AirSampleConfig => AirSensorConfig,
+    GenericSampleConfig => GenericSensorConfig,
+    LeafSampleConfig => LeafSensorConfig,
+    LightSampleConfig => LightSensorConfig,
+    NutrientSampleConfig => NutrientSensorConfig,
+    PowerSampleConfig => PowerSensorConfig,
+    WaterLevelSampleConfig => WaterLevelSensorConfig,
+);
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// When a single sensor takes measurements.
+///
+/// Measurements are aligned to full multiples of the sample rate since the epoch, e.g. on the
+/// full minute, so that data of different grows lines up.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// The rate in which the sensor takes measurements in seconds, overrides the
+    /// sample rate of the section.
+    #[serde(default)]
+    pub sample_rate_secs: Option<u64>,
+    /// The delay of each measurement after the aligned tick in seconds, must be
+    /// smaller than the sample rate.
+    #[serde(default)]
+    pub phase_offset_secs: u64,
+    /// The upper bound of a random delay that is added to each measurement in
+    /// milliseconds.
+    #[serde(default)]
+    pub jitter_ms: u64,
+}
@@ -1,8 +1,17 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use super::control::ControlConfig;
+use crate::measure::{vl53l0x::RangingProfile, vl53l1x::DistanceMode};
+
+use super::{
+    aggregate::{AggregateConfig, LimitConfig},
+    calibration::CalibrationConfig,
+    control::ControlConfig,
+    i2c::MuxConfig,
+    retry::RetryConfig,
+    schedule::ScheduleConfig,
+};
 
 #[derive(PartialEq, Debug, Default, Serialize, Deserialize)]
 pub struct WaterLevelConfig {
@@ -20,18 +29,103 @@ pub struct WaterLevelSampleConfig {
     /// The water level sensors in use.
     #[serde(default)]
     pub sensors: HashMap<String, WaterLevelSensorConfig>,
+    /// How failed measurements are retried.
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// How the readings within one sample period are combined.
+    #[serde(default)]
+    pub aggregate: AggregateConfig,
+    /// The plausible ranges of the measured quantities by name.
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct WaterLevelSensorConfig {
     /// The model of the water level sensor.
     pub model: WaterLevelSensorModel,
-    /// The address of the water level sensor.
-    #[serde(deserialize_with = "super::from_hex")]
+    /// The address of the water level sensor, only used by I2C sensors.
+    #[serde(default, deserialize_with = "super::from_hex")]
     pub address: u8,
+    /// The TCA9548A multiplexer channel the sensor is connected to, if any.
+    #[serde(default)]
+    pub mux: Option<MuxConfig>,
+    /// The calibration of the measured quantities by name.
+    #[serde(default)]
+    pub calibration: HashMap<String, CalibrationConfig>,
+    /// The time a single measurement or initialization of the sensor may take in milliseconds,
+    /// defaults to 30 seconds.
+    #[serde(default)]
+    pub measure_timeout_ms: Option<u64>,
+    /// When the sensor takes measurements.
+    #[serde(flatten)]
+    pub schedule: ScheduleConfig,
+    /// The ranging profile of the water level sensor.
+    #[serde(default)]
+    pub profile: RangingProfile,
+    /// The time a single range measurement may take in milliseconds, overrides
+    /// the timing budget of the profile.
+    #[serde(default)]
+    pub timing_budget_ms: Option<u64>,
+    /// The minimum return signal rate in mega counts per second for a valid
+    /// range, overrides the signal rate limit of the profile.
+    #[serde(default)]
+    pub signal_rate_limit: Option<f64>,
+    /// The distance mode of a VL53L1X.
+    #[serde(default)]
+    pub distance_mode: DistanceMode,
+    /// How an ultrasonic sensor is connected.
+    #[serde(default)]
+    pub interface: Option<UltrasonicInterface>,
+    /// The geometry of the tank, used to compute the fill level and volume.
+    #[serde(default)]
+    pub tank: Option<TankConfig>,
 }
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum WaterLevelSensorModel {
     Vl53L0X,
+    Vl53L1X,
+    JsnSr04T,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum UltrasonicInterface {
+    /// Read via a serial port.
+    Uart { path: PathBuf },
+    /// Triggered and read via GPIOs.
+    Gpio { trigger_pin: u32, echo_pin: u32 },
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct TankConfig {
+    /// The distance between the sensor and the water surface in mm when the
+    /// tank is empty.
+    pub empty_distance: u32,
+    /// The distance between the sensor and the water surface in mm when the
+    /// tank is full.
+    pub full_distance: u32,
+    /// The shape of the tank.
+    #[serde(flatten)]
+    pub shape: TankShape,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "shape")]
+pub enum TankShape {
+    /// A rectangular tank with vertical walls, dimensions in mm.
+    Rectangular { length: f64, width: f64 },
+    /// An upright cylindrical tank, diameter in mm.
+    Cylinder { diameter: f64 },
+    /// A tank of any shape with measured volumes at several distances.
+    Table { points: Vec<TankCalibrationPoint> },
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct TankCalibrationPoint {
+    /// The distance between the sensor and the water surface in mm.
+    pub distance: u32,
+    /// The volume of the water in litres at this distance.
+    pub volume: f64,
 }
@@ -3,6 +3,7 @@ use async_trait::async_trait;
 use chrono::{NaiveTime, Utc};
 use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
 use std::{path::Path, time::Duration};
+use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
@@ -19,10 +20,12 @@ trait Control {
 
 pub struct Controller {
     inner: Option<Box<dyn Control + Send>>,
+    state: watch::Receiver<Option<bool>>,
 }
 
 impl Controller {
     pub fn new(config: &ControlConfig, gpio_path: impl AsRef<Path>) -> Result<Self> {
+        let (sender, state) = watch::channel(None);
         let inner: Option<Box<dyn Control + Send>> = match config {
             ControlConfig::Off => None,
             ControlConfig::Cyclic {
@@ -36,6 +39,7 @@ impl Controller {
                         *pin,
                         Duration::from_secs(*on_duration_secs),
                         Duration::from_secs(*off_duration_secs),
+                        sender,
                     )
                     .context("Failed to create cyclic controller")?,
                 );
@@ -48,15 +52,27 @@ impl Controller {
                 deactivate_time,
             } => {
                 let controller = Box::new(
-                    TimeBasedController::new(gpio_path, *pin, *activate_time, *deactivate_time)
-                        .context("Failed to create time based controller")?,
+                    TimeBasedController::new(
+                        gpio_path,
+                        *pin,
+                        *activate_time,
+                        *deactivate_time,
+                        sender,
+                    )
+                    .context("Failed to create time based controller")?,
                 );
 
                 Some(controller)
             }
         };
 
-        Ok(Self { inner })
+        Ok(Self { inner, state })
+    }
+
+    /// Returns the state of the control pin, which is `None` while the controller is disabled
+    /// or hasn't set the pin yet.
+    pub fn state(&self) -> watch::Receiver<Option<bool>> {
+        self.state.clone()
     }
 
     pub async fn run(self, cancel_token: CancellationToken) -> Result<()> {
@@ -73,10 +89,21 @@ impl Controller {
     }
 }
 
+/// Sets the value of the control pin and publishes whether it's activated.
+fn set_pin(handle: &LineHandle, state: &watch::Sender<Option<bool>>, value: u8) -> Result<()> {
+    handle
+        .set_value(value)
+        .context("Failed to set value of control pin")?;
+    state.send_replace(Some(value == GPIO_ACTIVATE));
+
+    Ok(())
+}
+
 struct CyclicController {
     handle: LineHandle,
     on_duration: Duration,
     off_duration: Duration,
+    state: watch::Sender<Option<bool>>,
 }
 
 impl CyclicController {
@@ -85,6 +112,7 @@ impl CyclicController {
         pin: u32,
         on_duration: Duration,
         off_duration: Duration,
+        state: watch::Sender<Option<bool>>,
     ) -> Result<Self> {
         let mut chip = Chip::new(gpio_path).context("Failed to open GPIO chip")?;
         let handle = chip
@@ -97,6 +125,7 @@ impl CyclicController {
             handle,
             on_duration,
             off_duration,
+            state,
         })
     }
 }
@@ -106,9 +135,7 @@ impl Control for CyclicController {
     async fn run(&mut self, cancel_token: CancellationToken) -> Result<()> {
         if self.off_duration.is_zero() {
             info!("Activating control pin permanently");
-            self.handle
-                .set_value(GPIO_ACTIVATE)
-                .context("Failed to set value of control pin")?;
+            set_pin(&self.handle, &self.state, GPIO_ACTIVATE)?;
 
             cancel_token.cancelled().await;
             return Ok(());
@@ -116,18 +143,14 @@ impl Control for CyclicController {
 
         if self.on_duration.is_zero() {
             info!("Deactivating control pin permanently");
-            self.handle
-                .set_value(GPIO_DEACTIVATE)
-                .context("Failed to set value of control pin")?;
+            set_pin(&self.handle, &self.state, GPIO_DEACTIVATE)?;
 
             cancel_token.cancelled().await;
             return Ok(());
         }
 
         debug!("Activating control pin");
-        self.handle
-            .set_value(GPIO_ACTIVATE)
-            .context("Failed to set value of control pin")?;
+        set_pin(&self.handle, &self.state, GPIO_ACTIVATE)?;
         let mut timeout = self.on_duration;
 
         loop {
@@ -139,15 +162,11 @@ impl Control for CyclicController {
 
                     if value == GPIO_ACTIVATE {
                         debug!("Deactivating control pin");
-                        self.handle
-                            .set_value(GPIO_DEACTIVATE)
-                            .context("Failed to set value of control pin")?;
+                        set_pin(&self.handle, &self.state, GPIO_DEACTIVATE)?;
                         timeout = self.on_duration;
                     } else {
                         debug!("Activating control pin");
-                        self.handle
-                            .set_value(GPIO_ACTIVATE)
-                            .context("Failed to set value of control pin")?;
+                        set_pin(&self.handle, &self.state, GPIO_ACTIVATE)?;
                         timeout = self.off_duration;
                     }
                 }
@@ -163,6 +182,7 @@ struct TimeBasedController {
     handle: LineHandle,
     activate_time: NaiveTime,
     deactivate_time: NaiveTime,
+    state: watch::Sender<Option<bool>>,
 }
 
 impl TimeBasedController {
@@ -171,6 +191,7 @@ impl TimeBasedController {
         pin: u32,
         activate_time: NaiveTime,
         deactivate_time: NaiveTime,
+        state: watch::Sender<Option<bool>>,
     ) -> Result<Self> {
         if activate_time == deactivate_time {
             bail!("Activate time and deactivate time cannot be equal");
@@ -187,6 +208,7 @@ impl TimeBasedController {
             handle,
             activate_time,
             deactivate_time,
+            state,
         })
     }
 }
@@ -206,9 +228,7 @@ impl Control for TimeBasedController {
             };
 
             debug!("{} control pin", actions.0);
-            self.handle
-                .set_value(value)
-                .context("Failed to set value of control pin")?;
+            set_pin(&self.handle, &self.state, value)?;
 
             debug!(
                 "{} control pin in {:02}:{:02}:{:02}h",
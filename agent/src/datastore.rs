@@ -3,7 +3,10 @@ use std::str::FromStr;
 use anyhow::{Context, Result};
 use sqlx::{sqlite::SqliteConnectOptions, QueryBuilder, Sqlite, SqlitePool};
 
-use crate::measure::{AirMeasurement, LightMeasurement, WaterLevelMeasurement};
+use crate::measure::{
+    registry::Reading, AirMeasurement, BusStatus, LeafMeasurement, LightMeasurement,
+    NutrientMeasurement, PowerMeasurement, SampleStatus, WaterLevelMeasurement,
+};
 
 pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
 
@@ -29,17 +32,30 @@ impl DataStore {
         Ok(Self { pool })
     }
 
+    #[cfg(test)]
+    pub(crate) fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
     pub async fn add_air_measurements(&self, measurements: Vec<AirMeasurement>) -> Result<()> {
         let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
-            "INSERT INTO air_measurements(measure_time, label, temperature, humidity, pressure, resistance) ",
+            "INSERT INTO air_measurements(measure_time, label, temperature, humidity, pressure, resistance, iaq, vpd, dew_point, absolute_humidity, raw_temperature, raw_humidity, raw_pressure, raw_resistance) ",
         );
-        query_builder.push_values(measurements.into_iter(), |mut b, m| {
+        query_builder.push_values(measurements, |mut b, m| {
             b.push_bind(m.measure_time)
                 .push_bind(m.label)
                 .push_bind(m.temperature)
                 .push_bind(m.humidity)
                 .push_bind(m.pressure)
-                .push_bind(m.resistance);
+                .push_bind(m.resistance)
+                .push_bind(m.iaq)
+                .push_bind(m.vpd)
+                .push_bind(m.dew_point)
+                .push_bind(m.absolute_humidity)
+                .push_bind(m.raw_temperature)
+                .push_bind(m.raw_humidity)
+                .push_bind(m.raw_pressure)
+                .push_bind(m.raw_resistance);
         });
         query_builder
             .build()
@@ -50,13 +66,61 @@ impl DataStore {
         Ok(())
     }
 
+    pub async fn gas_baseline(&self, label: &str) -> Result<Option<f64>> {
+        let baseline = sqlx::query_scalar("SELECT resistance FROM gas_baselines WHERE label = $1")
+            .bind(label)
+            .fetch_optional(&self.pool)
+            .await
+            .with_context(|| format!("Failed to get gas baseline of {label:?}"))?;
+
+        Ok(baseline)
+    }
+
+    pub async fn set_gas_baseline(
+        &self,
+        label: &str,
+        resistance: f64,
+        update_time: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO gas_baselines(label, resistance, update_time) VALUES ($1, $2, $3)
+            ON CONFLICT(label) DO UPDATE SET
+                resistance = excluded.resistance,
+                update_time = excluded.update_time;
+        "#,
+        )
+        .bind(label)
+        .bind(resistance)
+        .bind(update_time)
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to set gas baseline of {label:?}"))?;
+
+        Ok(())
+    }
+
     pub async fn add_light_measurements(&self, measurements: Vec<LightMeasurement>) -> Result<()> {
-        let mut query_builder: QueryBuilder<Sqlite> =
-            QueryBuilder::new("INSERT INTO light_measurements(measure_time, label, illuminance) ");
-        query_builder.push_values(measurements.into_iter(), |mut b, m| {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO light_measurements(measure_time, label, illuminance, ppfd, f1, f2, f3, f4, f5, f6, f7, f8, clear, nir, raw_illuminance, raw_ppfd) ",
+        );
+        query_builder.push_values(measurements, |mut b, m| {
             b.push_bind(m.measure_time)
                 .push_bind(m.label)
-                .push_bind(m.illuminance);
+                .push_bind(m.illuminance)
+                .push_bind(m.ppfd)
+                .push_bind(m.f1)
+                .push_bind(m.f2)
+                .push_bind(m.f3)
+                .push_bind(m.f4)
+                .push_bind(m.f5)
+                .push_bind(m.f6)
+                .push_bind(m.f7)
+                .push_bind(m.f8)
+                .push_bind(m.clear)
+                .push_bind(m.nir)
+                .push_bind(m.raw_illuminance)
+                .push_bind(m.raw_ppfd);
         });
         query_builder
             .build()
@@ -72,12 +136,16 @@ impl DataStore {
         measurements: Vec<WaterLevelMeasurement>,
     ) -> Result<()> {
         let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
-            "INSERT INTO water_level_measurements(measure_time, label, distance) ",
+            "INSERT INTO water_level_measurements(measure_time, label, distance, range_status, fill_level, volume, raw_distance) ",
         );
-        query_builder.push_values(measurements.into_iter(), |mut b, m| {
+        query_builder.push_values(measurements, |mut b, m| {
             b.push_bind(m.measure_time)
                 .push_bind(m.label)
-                .push_bind(m.distance);
+                .push_bind(m.distance)
+                .push_bind(m.range_status)
+                .push_bind(m.fill_level)
+                .push_bind(m.volume)
+                .push_bind(m.raw_distance);
         });
         query_builder
             .build()
@@ -87,17 +155,188 @@ impl DataStore {
 
         Ok(())
     }
+
+    pub async fn add_nutrient_measurements(
+        &self,
+        measurements: Vec<NutrientMeasurement>,
+    ) -> Result<()> {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO nutrient_measurements(measure_time, label, ph, conductivity, temperature, raw_ph, raw_conductivity, raw_temperature) ",
+        );
+        query_builder.push_values(measurements, |mut b, m| {
+            b.push_bind(m.measure_time)
+                .push_bind(m.label)
+                .push_bind(m.ph)
+                .push_bind(m.conductivity)
+                .push_bind(m.temperature)
+                .push_bind(m.raw_ph)
+                .push_bind(m.raw_conductivity)
+                .push_bind(m.raw_temperature);
+        });
+        query_builder
+            .build()
+            .execute(&self.pool)
+            .await
+            .context("Failed to store nutrient measurements")?;
+
+        Ok(())
+    }
+
+    pub async fn add_power_measurements(&self, measurements: Vec<PowerMeasurement>) -> Result<()> {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO power_measurements(measure_time, label, controller, load_active, voltage, current, power, raw_voltage, raw_current, raw_power) ",
+        );
+        query_builder.push_values(measurements, |mut b, m| {
+            b.push_bind(m.measure_time)
+                .push_bind(m.label)
+                .push_bind(m.controller)
+                .push_bind(m.load_active)
+                .push_bind(m.voltage)
+                .push_bind(m.current)
+                .push_bind(m.power)
+                .push_bind(m.raw_voltage)
+                .push_bind(m.raw_current)
+                .push_bind(m.raw_power);
+        });
+        query_builder
+            .build()
+            .execute(&self.pool)
+            .await
+            .context("Failed to store power measurements")?;
+
+        Ok(())
+    }
+
+    pub async fn add_leaf_measurements(&self, measurements: Vec<LeafMeasurement>) -> Result<()> {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO leaf_measurements(measure_time, label, object_temperature, ambient_temperature, raw_object_temperature, raw_ambient_temperature) ",
+        );
+        query_builder.push_values(measurements, |mut b, m| {
+            b.push_bind(m.measure_time)
+                .push_bind(m.label)
+                .push_bind(m.object_temperature)
+                .push_bind(m.ambient_temperature)
+                .push_bind(m.raw_object_temperature)
+                .push_bind(m.raw_ambient_temperature);
+        });
+        query_builder
+            .build()
+            .execute(&self.pool)
+            .await
+            .context("Failed to store leaf measurements")?;
+
+        Ok(())
+    }
+
+    /// Stores the values of readings of registered sensor models, one row per quantity.
+    /// Quantities without a value are skipped.
+    pub async fn add_readings(&self, readings: Vec<Reading>) -> Result<()> {
+        let rows = readings
+            .iter()
+            .flat_map(|r| {
+                r.values()
+                    .filter(|(_, value, raw)| value.is_some() || raw.is_some())
+                    .map(move |(quantity, value, raw)| (r, quantity, value, raw))
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO readings(measure_time, label, model, quantity, unit, value, raw_value) ",
+        );
+        query_builder.push_values(rows, |mut b, (r, quantity, value, raw)| {
+            b.push_bind(r.measure_time)
+                .push_bind(&r.label)
+                .push_bind(r.model)
+                .push_bind(quantity.name)
+                .push_bind(quantity.unit)
+                .push_bind(value)
+                .push_bind(raw);
+        });
+        query_builder
+            .build()
+            .execute(&self.pool)
+            .await
+            .context("Failed to store readings")?;
+
+        Ok(())
+    }
+
+    /// Adds the retries and reinitializations of a sample to the failure counters of a sensor.
+    /// The sample failed if an error is given, otherwise the consecutive failures are reset.
+    pub async fn update_sensor_status(&self, status: &SampleStatus<'_>) -> Result<()> {
+        let failures = i64::from(status.error.is_some());
+        sqlx::query(
+            r#"
+            INSERT INTO sensor_status(kind, label, failures, retries, reinits, consecutive_failures, last_error, last_failure_time, present)
+            VALUES ($1, $2, $3, $4, $5, $3, $6, $7, $8)
+            ON CONFLICT(kind, label) DO UPDATE SET
+                failures = failures + excluded.failures,
+                retries = retries + excluded.retries,
+                reinits = reinits + excluded.reinits,
+                consecutive_failures = CASE
+                    WHEN excluded.failures > 0 THEN consecutive_failures + 1
+                    ELSE 0
+                END,
+                last_error = coalesce(excluded.last_error, last_error),
+                last_failure_time = coalesce(excluded.last_failure_time, last_failure_time),
+                present = excluded.present;
+        "#,
+        )
+        .bind(status.kind)
+        .bind(status.label)
+        .bind(failures)
+        .bind(status.retries)
+        .bind(status.reinits)
+        .bind(status.error)
+        .bind(status.error.map(|_| status.sample_time))
+        .bind(status.present)
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to update status of {:?} sensor", status.label))?;
+
+        Ok(())
+    }
+
+    /// Replaces the stored counters of an I2C bus.
+    pub async fn update_bus_status(&self, status: &BusStatus) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO bus_status(path, transactions, errors, nacks, timeouts, arbitration_lost, update_time)
+            VALUES ($1, $2, $3, $4, $5, $6, $7);
+        "#,
+        )
+        .bind(&status.path)
+        .bind(status.transactions)
+        .bind(status.errors)
+        .bind(status.nacks)
+        .bind(status.timeouts)
+        .bind(status.arbitration_lost)
+        .bind(status.update_time)
+        .execute(&self.pool)
+        .await
+        .with_context(|| format!("Failed to update status of I2C bus {:?}", status.path))?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::measure::{
+        registry::{tests::Counter, ReadingValue},
+        BusStats, ControllerKind, Quantities, RangeStatus, SensorKind, SensorStatus,
+    };
     use chrono::Utc;
+    use std::path::Path;
 
     #[sqlx::test]
     async fn add_air_measurement_ok() {
         let store = DataStore::new("sqlite::memory:").await.unwrap();
-        let measure_time = Utc::now().timestamp();
+        let measure_time = Utc::now().timestamp_millis();
         let measurements = vec![
             AirMeasurement {
                 measure_time,
@@ -106,6 +345,14 @@ mod tests {
                 humidity: Some(56.123),
                 pressure: Some(1021.),
                 resistance: None,
+                iaq: None,
+                vpd: Some(1.093),
+                dew_point: Some(12.1),
+                absolute_humidity: Some(10.4),
+                raw_temperature: Some(21.4),
+                raw_humidity: Some(55.1),
+                raw_pressure: None,
+                raw_resistance: None,
             },
             AirMeasurement {
                 measure_time: measure_time + 100,
@@ -113,7 +360,15 @@ mod tests {
                 temperature: Some(69.),
                 humidity: None,
                 pressure: Some(666.777),
-                resistance: None,
+                resistance: Some(123456.7),
+                iaq: Some(42.),
+                vpd: None,
+                dew_point: None,
+                absolute_humidity: None,
+                raw_temperature: None,
+                raw_humidity: None,
+                raw_pressure: None,
+                raw_resistance: None,
             },
         ];
 
@@ -130,21 +385,43 @@ mod tests {
         assert_eq!(measurements, retrieved_measurements);
     }
 
+    #[sqlx::test]
+    async fn gas_baseline_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let update_time = Utc::now().timestamp_millis();
+
+        assert_eq!(store.gas_baseline("left").await.unwrap(), None);
+        store
+            .set_gas_baseline("left", 123456.7, update_time)
+            .await
+            .unwrap();
+        store
+            .set_gas_baseline("left", 234567.8, update_time + 60_000)
+            .await
+            .unwrap();
+        store
+            .set_gas_baseline("right", 345678.9, update_time)
+            .await
+            .unwrap();
+
+        assert_eq!(store.gas_baseline("left").await.unwrap(), Some(234567.8));
+        assert_eq!(store.gas_baseline("right").await.unwrap(), Some(345678.9));
+    }
+
     #[sqlx::test]
     async fn add_light_measurement_ok() {
         let store = DataStore::new("sqlite::memory:").await.unwrap();
-        let measure_time = Utc::now().timestamp();
+        let measure_time = Utc::now().timestamp_millis();
         let measurements = vec![
-            LightMeasurement {
-                measure_time,
-                label: "test".into(),
-                illuminance: Some(123.123),
-            },
-            LightMeasurement {
-                measure_time,
-                label: "another_test".into(),
-                illuminance: Some(12.34),
-            },
+            LightMeasurement::new(measure_time, "test".into()).illuminance(123.123),
+            LightMeasurement::new(measure_time, "another_test".into())
+                .illuminance(12.34)
+                .ppfd(0.228),
+            LightMeasurement::new(measure_time, "spectral".into()).spectral_counts(
+                [12, 345, 6789, 10111, 21314, 15161, 7181, 920],
+                65535,
+                0,
+            ),
         ];
 
         store
@@ -163,17 +440,25 @@ mod tests {
     #[sqlx::test]
     async fn add_water_level_measurement_ok() {
         let store = DataStore::new("sqlite::memory:").await.unwrap();
-        let measure_time = Utc::now().timestamp();
+        let measure_time = Utc::now().timestamp_millis();
         let measurements = vec![
             WaterLevelMeasurement {
                 measure_time,
                 label: "foo".into(),
                 distance: Some(987),
+                range_status: Some(RangeStatus::Valid),
+                fill_level: Some(42.5),
+                volume: Some(85.),
+                raw_distance: Some(995),
             },
             WaterLevelMeasurement {
                 measure_time,
                 label: "bar".into(),
-                distance: Some(923),
+                distance: None,
+                range_status: Some(RangeStatus::SignalFail),
+                fill_level: None,
+                volume: None,
+                raw_distance: None,
             },
         ];
 
@@ -189,4 +474,306 @@ mod tests {
 
         assert_eq!(measurements, retrieved_measurements);
     }
+
+    #[sqlx::test]
+    async fn add_nutrient_measurement_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let measure_time = Utc::now().timestamp_millis();
+        let measurements = vec![
+            NutrientMeasurement {
+                measure_time,
+                label: "ph".into(),
+                ph: Some(5.82),
+                conductivity: None,
+                temperature: None,
+                raw_ph: Some(5.91),
+                raw_conductivity: None,
+                raw_temperature: None,
+            },
+            NutrientMeasurement {
+                measure_time,
+                label: "ec".into(),
+                ph: None,
+                conductivity: Some(1413.),
+                temperature: None,
+                raw_ph: None,
+                raw_conductivity: None,
+                raw_temperature: None,
+            },
+            NutrientMeasurement {
+                measure_time,
+                label: "rtd".into(),
+                ph: None,
+                conductivity: None,
+                temperature: Some(19.87),
+                raw_ph: None,
+                raw_conductivity: None,
+                raw_temperature: None,
+            },
+        ];
+
+        store
+            .add_nutrient_measurements(measurements.clone())
+            .await
+            .unwrap();
+        let retrieved_measurements =
+            sqlx::query_as::<_, NutrientMeasurement>("SELECT * FROM nutrient_measurements")
+                .fetch_all(&store.pool)
+                .await
+                .unwrap();
+
+        assert_eq!(measurements, retrieved_measurements);
+    }
+
+    #[sqlx::test]
+    async fn add_power_measurement_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let measure_time = Utc::now().timestamp_millis();
+        let measurements = vec![
+            PowerMeasurement {
+                measure_time,
+                label: "light".into(),
+                controller: Some(ControllerKind::Light),
+                load_active: Some(true),
+                voltage: Some(24.02),
+                current: Some(10.13),
+                power: Some(243.3),
+                raw_voltage: None,
+                raw_current: Some(10.01),
+                raw_power: None,
+            },
+            PowerMeasurement {
+                measure_time,
+                label: "pi".into(),
+                controller: None,
+                load_active: None,
+                voltage: Some(5.1),
+                current: Some(0.62),
+                power: Some(3.16),
+                raw_voltage: None,
+                raw_current: None,
+                raw_power: None,
+            },
+        ];
+
+        store
+            .add_power_measurements(measurements.clone())
+            .await
+            .unwrap();
+        let retrieved_measurements =
+            sqlx::query_as::<_, PowerMeasurement>("SELECT * FROM power_measurements")
+                .fetch_all(&store.pool)
+                .await
+                .unwrap();
+
+        assert_eq!(measurements, retrieved_measurements);
+    }
+
+    #[sqlx::test]
+    async fn add_leaf_measurement_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let measure_time = Utc::now().timestamp_millis();
+        let measurements = vec![
+            LeafMeasurement {
+                measure_time,
+                label: "canopy".into(),
+                object_temperature: Some(23.41),
+                ambient_temperature: Some(25.87),
+                raw_object_temperature: Some(23.12),
+                raw_ambient_temperature: None,
+            },
+            LeafMeasurement {
+                measure_time,
+                label: "leaf".into(),
+                object_temperature: Some(22.95),
+                ambient_temperature: Some(25.63),
+                raw_object_temperature: None,
+                raw_ambient_temperature: None,
+            },
+        ];
+
+        store
+            .add_leaf_measurements(measurements.clone())
+            .await
+            .unwrap();
+        let retrieved_measurements =
+            sqlx::query_as::<_, LeafMeasurement>("SELECT * FROM leaf_measurements")
+                .fetch_all(&store.pool)
+                .await
+                .unwrap();
+
+        assert_eq!(measurements, retrieved_measurements);
+    }
+
+    #[sqlx::test]
+    async fn add_readings_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let mut calibrated = Reading::new::<Counter>(2000, "counter".into()).value("count", 4.);
+        calibrated.set_raw_quantity("count", Some(3.));
+        let readings = vec![
+            Reading::new::<Counter>(1000, "counter".into())
+                .value("count", 1.)
+                .value("rate", 0.5),
+            calibrated,
+        ];
+
+        store.add_readings(readings).await.unwrap();
+        store.add_readings(Vec::new()).await.unwrap();
+        let retrieved = sqlx::query_as::<_, ReadingValue>("SELECT * FROM readings")
+            .fetch_all(&store.pool)
+            .await
+            .unwrap();
+
+        let value = |measure_time, quantity: &str, unit: &str, value, raw_value| ReadingValue {
+            measure_time,
+            label: "counter".into(),
+            model: "Counter".into(),
+            quantity: quantity.into(),
+            unit: unit.into(),
+            value,
+            raw_value,
+        };
+        assert_eq!(
+            retrieved,
+            vec![
+                value(1000, "count", "", Some(1.), None),
+                value(1000, "rate", "1/s", Some(0.5), None),
+                value(2000, "count", "", Some(4.), Some(3.)),
+            ]
+        );
+    }
+
+    async fn status(store: &DataStore) -> Vec<SensorStatus> {
+        sqlx::query_as::<_, SensorStatus>("SELECT * FROM sensor_status")
+            .fetch_all(&store.pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn update_sensor_status_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let kind = SensorKind::Light;
+        let sample = SampleStatus {
+            kind,
+            label: "test",
+            retries: 0,
+            reinits: 0,
+            error: None,
+            sample_time: 0,
+            present: true,
+        };
+
+        store
+            .update_sensor_status(&SampleStatus {
+                retries: 2,
+                reinits: 0,
+                error: Some("timeout"),
+                sample_time: 100,
+                ..sample
+            })
+            .await
+            .unwrap();
+        store
+            .update_sensor_status(&SampleStatus {
+                retries: 1,
+                reinits: 1,
+                error: Some("nack"),
+                sample_time: 200,
+                ..sample
+            })
+            .await
+            .unwrap();
+
+        let expected = SensorStatus {
+            kind,
+            label: "test".into(),
+            failures: 2,
+            retries: 3,
+            reinits: 1,
+            consecutive_failures: 2,
+            last_error: Some("nack".into()),
+            last_failure_time: Some(200),
+            present: true,
+        };
+        assert_eq!(status(&store).await, vec![expected.clone()]);
+
+        store
+            .update_sensor_status(&SampleStatus {
+                retries: 1,
+                sample_time: 300,
+                ..sample.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            status(&store).await,
+            vec![SensorStatus {
+                retries: 4,
+                consecutive_failures: 0,
+                ..expected.clone()
+            }]
+        );
+
+        store
+            .update_sensor_status(&SampleStatus {
+                error: Some("no response"),
+                sample_time: 400,
+                present: false,
+                ..sample
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            status(&store).await,
+            vec![SensorStatus {
+                failures: 3,
+                retries: 4,
+                consecutive_failures: 1,
+                last_error: Some("no response".into()),
+                last_failure_time: Some(400),
+                present: false,
+                ..expected
+            }]
+        );
+    }
+
+    #[sqlx::test]
+    async fn update_bus_status_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let stats = BusStats {
+            transactions: 120,
+            errors: 3,
+            nacks: 2,
+            timeouts: 1,
+            arbitration_lost: 0,
+        };
+        let path = Path::new("/dev/i2c-1");
+
+        store
+            .update_bus_status(&BusStatus::new(path, BusStats::default(), 1000))
+            .await
+            .unwrap();
+        store
+            .update_bus_status(&BusStatus::new(path, stats, 2000))
+            .await
+            .unwrap();
+        let status = sqlx::query_as::<_, BusStatus>("SELECT * FROM bus_status")
+            .fetch_all(&store.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            status,
+            vec![BusStatus {
+                path: "/dev/i2c-1".into(),
+                transactions: 120,
+                errors: 3,
+                nacks: 2,
+                timeouts: 1,
+                arbitration_lost: 0,
+                update_time: 2000,
+            }]
+        );
+    }
 }
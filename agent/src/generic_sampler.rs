@@ -0,0 +1,159 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use futures::future::join_all;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{generic::GenericSampleConfig, i2c::sensor_address},
+    datastore::DataStore,
+    measure::{
+        registry::{Reading, Registration, Sensor, Setup},
+        SensorKind,
+    },
+    sample::{Sampler, Slot},
+};
+
+/// Samples the sensors of registered models.
+pub struct GenericSampler {
+    receiver: mpsc::Receiver<Vec<Reading>>,
+    sampler: Sampler<Sensor>,
+    store: DataStore,
+}
+
+impl GenericSampler {
+    pub async fn new(
+        config: &GenericSampleConfig,
+        i2c_path: &Path,
+        gpio_path: &Path,
+        store: DataStore,
+    ) -> Result<Self> {
+        let mut models = HashMap::new();
+        for (label, c) in &config.sensors {
+            let model = Registration::find(&c.model)
+                .with_context(|| format!("Invalid model of {label:?} sensor"))?;
+            let quantities = model
+                .validate(&c.settings)
+                .with_context(|| format!("Invalid settings of {label:?} sensor"))?;
+            let names = quantities.iter().map(|q| q.name).collect::<Vec<_>>();
+            models.insert(label.as_str(), (model, names));
+        }
+
+        let sensors = join_all(config.sensors.iter().map(|(label, c)| {
+            let (model, _) = models[label.as_str()];
+            let setup = Setup {
+                label: label.clone(),
+                address: sensor_address(c.address, c.mux.as_ref()),
+                i2c_path: i2c_path.to_owned(),
+                gpio_path: gpio_path.to_owned(),
+                config: c.settings.clone(),
+            };
+            Slot::init(label.clone(), move || model.init(setup.clone()))
+        }))
+        .await;
+
+        // the limits apply to every sensor that measures the quantity
+        let mut quantities = models
+            .values()
+            .flat_map(|(_, names)| names.iter().copied())
+            .collect::<Vec<_>>();
+        quantities.sort_unstable();
+        quantities.dedup();
+        let (sampler, receiver) = Sampler::from_config_with_quantities(
+            SensorKind::Generic,
+            config,
+            sensors,
+            store.clone(),
+            &quantities,
+            |label| &models[label].1,
+        )
+        .context("Failed to initialize generic sampler")?;
+
+        Ok(Self {
+            receiver,
+            sampler,
+            store,
+        })
+    }
+
+    pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
+        let mut sampler_handle = tokio::spawn(self.sampler.run(cancel_token.clone()));
+
+        loop {
+            tokio::select! {
+                Some(readings) = self.receiver.recv() => {
+                    self.store
+                        .add_readings(readings)
+                        .await
+                        .context("Failed to store readings")?;
+                }
+                res = &mut sampler_handle => {
+                    res.context("Generic sampler panicked")?
+                        .context("Failed to run generic sampler")?;
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::registry::ReadingValue;
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn run_ok() {
+        let config = serde_json::from_value(json!({
+            "sample_rate_secs": 1,
+            "sensors": {
+                "plug": {
+                    "model": "Command",
+                    "calibration": {
+                        "power": { "mode": "Linear", "scale": 2.0 }
+                    },
+                    "settings": {
+                        "program": "sh",
+                        "args": ["-c", r#"echo '{"ENERGY": {"Power": 41.5}, "On": true}'"#],
+                        "quantities": {
+                            "power": { "unit": "W", "pointer": "/ENERGY/Power" },
+                            "on": { "type": "integer", "pointer": "/On" }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let sampler = GenericSampler::new(&config, Path::new(""), Path::new(""), store.clone())
+            .await
+            .unwrap();
+
+        let cancel_token = CancellationToken::new();
+        let handle = tokio::spawn(sampler.run(cancel_token.clone()));
+        sleep(Duration::from_millis(1500)).await;
+        cancel_token.cancel();
+        handle.await.unwrap().unwrap();
+
+        let values = sqlx::query_as::<_, ReadingValue>(
+            "SELECT * FROM readings WHERE measure_time = (SELECT min(measure_time) FROM readings)",
+        )
+        .fetch_all(store.pool())
+        .await
+        .unwrap();
+        let value = |quantity: &str| values.iter().find(|v| v.quantity == quantity).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(value("power").value, Some(83.));
+        assert_eq!(value("power").raw_value, Some(41.5));
+        assert_eq!(value("power").unit, "W");
+        assert_eq!(value("on").value, Some(1.));
+        assert!(values
+            .iter()
+            .all(|v| v.model == "Command" && v.label == "plug"));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::config::air::IaqConfig;
+
+/// The weight of a single reading when the baseline adapts towards lower resistances.
+const BASELINE_ADAPTATION: f64 = 0.001;
+/// The maximum number of readings used to compute the initial baseline after burn-in.
+const BURN_IN_READINGS_MAX: usize = 50;
+const MILLIS_PER_SEC: i64 = 1000;
+
+/// Estimates the indoor air quality (IAQ) index from the gas resistance of a BME680.
+///
+/// The absolute gas resistance differs between sensors, hence the resistance is compared to a
+/// per-sensor baseline that represents clean air. The baseline follows rising resistances
+/// immediately and adapts slowly to falling ones. The result is compensated with the humidity and
+/// scaled from 0 (excellent) to 500 (extremely polluted).
+pub struct IaqEstimator {
+    config: IaqConfig,
+    baseline: Option<f64>,
+    first_measure_time: Option<i64>,
+    burn_in_readings: Vec<f64>,
+}
+
+impl IaqEstimator {
+    pub fn new(config: IaqConfig, baseline: Option<f64>) -> Self {
+        Self {
+            config,
+            baseline,
+            first_measure_time: None,
+            burn_in_readings: Vec::new(),
+        }
+    }
+
+    /// Returns the current gas resistance baseline in ohm.
+    pub fn baseline(&self) -> Option<f64> {
+        self.baseline
+    }
+
+    /// Updates the baseline with a new reading taken at the given unix time in milliseconds and
+    /// returns the IAQ index, or `None` while the sensor is burning in.
+    pub fn estimate(&mut self, measure_time: i64, resistance: f64, humidity: f64) -> Option<f64> {
+        let first_measure_time = *self.first_measure_time.get_or_insert(measure_time);
+        if measure_time - first_measure_time < self.config.burn_in_secs as i64 * MILLIS_PER_SEC {
+            if self.burn_in_readings.len() == BURN_IN_READINGS_MAX {
+                self.burn_in_readings.remove(0);
+            }
+            self.burn_in_readings.push(resistance);
+            return None;
+        }
+
+        let baseline = match self.baseline {
+            Some(baseline) if resistance > baseline => resistance,
+            Some(baseline) => baseline + (resistance - baseline) * BASELINE_ADAPTATION,
+            None if self.burn_in_readings.is_empty() => resistance,
+            None => {
+                self.burn_in_readings.iter().sum::<f64>() / (self.burn_in_readings.len() as f64)
+            }
+        };
+        self.baseline = Some(baseline);
+        self.burn_in_readings.clear();
+
+        Some(self.index(resistance, humidity, baseline))
+    }
+
+    fn index(&self, resistance: f64, humidity: f64, baseline: f64) -> f64 {
+        let humidity_baseline = self.config.humidity_baseline;
+        let humidity_weight = self.config.humidity_weighting * 100.;
+        let gas_weight = 100. - humidity_weight;
+
+        let humidity_offset = humidity - humidity_baseline;
+        let humidity_score = if humidity_offset > 0. {
+            (100. - humidity_baseline - humidity_offset) / (100. - humidity_baseline)
+                * humidity_weight
+        } else {
+            (humidity_baseline + humidity_offset) / humidity_baseline * humidity_weight
+        };
+
+        let gas_score = if resistance < baseline {
+            resistance / baseline * gas_weight
+        } else {
+            gas_weight
+        };
+
+        let quality = (humidity_score + gas_score).clamp(0., 100.);
+        (100. - quality) * 5.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> IaqConfig {
+        IaqConfig {
+            burn_in_secs: 300,
+            humidity_baseline: 40.,
+            humidity_weighting: 0.25,
+        }
+    }
+
+    #[test]
+    fn burn_in_ok() {
+        let mut estimator = IaqEstimator::new(config(), None);
+
+        assert_eq!(estimator.estimate(0, 100_000., 40.), None);
+        assert_eq!(estimator.estimate(100_000, 200_000., 40.), None);
+        assert_eq!(estimator.baseline(), None);
+        assert_eq!(estimator.estimate(300_000, 150_000., 40.), Some(0.));
+        assert_eq!(estimator.baseline(), Some(150_000.));
+    }
+
+    #[test]
+    fn persisted_baseline_ok() {
+        let mut estimator = IaqEstimator::new(config(), Some(200_000.));
+
+        assert_eq!(estimator.estimate(0, 100_000., 40.), None);
+        // half the baseline resistance at ideal humidity
+        let iaq = estimator.estimate(300_000, 100_000., 40.).unwrap();
+        assert!((iaq - 187.5).abs() < 1.);
+        assert!(estimator.baseline().unwrap() < 200_000.);
+        // higher resistance becomes the new baseline
+        assert_eq!(estimator.estimate(600_000, 250_000., 40.), Some(0.));
+        assert_eq!(estimator.baseline(), Some(250_000.));
+    }
+
+    #[test]
+    fn humidity_compensation_ok() {
+        let mut estimator = IaqEstimator::new(config(), Some(100_000.));
+        estimator.estimate(0, 100_000., 40.);
+
+        let dry = estimator.estimate(300_000, 100_000., 20.).unwrap();
+        let humid = estimator.estimate(600_000, 100_000., 70.).unwrap();
+        assert!((dry - 62.5).abs() < 1e-9);
+        assert!((humid - 62.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use futures::future::join_all;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{
+        i2c::sensor_address,
+        leaf::{LeafSampleConfig, LeafSensorConfig, LeafSensorModel},
+    },
+    datastore::DataStore,
+    measure::{mlx90614::Mlx90614, LeafMeasurement, SensorKind},
+    sample::{Sampler, Slot},
+};
+
+pub struct LeafSampler {
+    receiver: mpsc::Receiver<Vec<LeafMeasurement>>,
+    sampler: Sampler<Mlx90614>,
+    store: DataStore,
+}
+
+impl LeafSampler {
+    pub async fn new(config: &LeafSampleConfig, i2c_path: &Path, store: DataStore) -> Result<Self> {
+        for (label, config) in &config.sensors {
+            Self::validate_sensor(config, label)?;
+        }
+        let sensors = join_all(config.sensors.iter().map(|(label, config)| {
+            let (config, sensor_label, i2c_path) =
+                (config.clone(), label.clone(), i2c_path.to_owned());
+            Slot::init(label.clone(), move || {
+                Self::init_sensor(config.clone(), sensor_label.clone(), i2c_path.clone())
+            })
+        }))
+        .await;
+
+        let (sampler, receiver) =
+            Sampler::from_config(SensorKind::Leaf, config, sensors, store.clone())
+                .context("Failed to initialize leaf sampler")?;
+
+        Ok(Self {
+            receiver,
+            sampler,
+            store,
+        })
+    }
+
+    pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
+        let mut sampler_handle = tokio::spawn(self.sampler.run(cancel_token.clone()));
+
+        loop {
+            tokio::select! {
+                Some(measurements) = self.receiver.recv() => {
+                    self.store
+                        .add_leaf_measurements(measurements)
+                        .await
+                        .context("Failed to store leaf measurements")?;
+                }
+                res = &mut sampler_handle => {
+                    res.context("Leaf sampler panicked")?
+                        .context("Failed to run leaf sampler")?;
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn validate_sensor(config: &LeafSensorConfig, label: &str) -> Result<()> {
+        if let Some(emissivity) = config.emissivity {
+            if emissivity <= 0. || emissivity > 1. {
+                bail!("Invalid emissivity {emissivity} of {label:?} leaf sensor");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn init_sensor(
+        config: LeafSensorConfig,
+        label: String,
+        i2c_path: PathBuf,
+    ) -> Result<Mlx90614> {
+        let sensor = match config.model {
+            LeafSensorModel::Mlx90614 => Mlx90614::new(
+                i2c_path,
+                sensor_address(config.address, config.mux.as_ref()),
+                label.clone(),
+            )
+            .await
+            .with_context(|| format!("Failed to initialize {:?} leaf sensor", label))?,
+        };
+
+        Ok(match config.emissivity {
+            Some(emissivity) => sensor.emissivity(emissivity),
+            None => sensor,
+        })
+    }
+}
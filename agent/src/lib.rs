@@ -1,9 +1,18 @@
 pub mod agent;
+mod aggregate;
 mod air_manager;
+mod calibration;
+pub mod climate;
 pub mod config;
 mod control;
 mod datastore;
+mod generic_sampler;
+mod iaq;
+mod leaf_sampler;
 mod light_sampler;
 pub mod measure;
+mod nutrient_sampler;
+mod power_sampler;
 mod sample;
+mod tank;
 mod water_level_manager;
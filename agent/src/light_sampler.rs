@@ -1,20 +1,60 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use futures::future::join_all;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    config::light::{LightSampleConfig, LightSensorConfig, LightSensorModel},
+    config::{
+        i2c::sensor_address,
+        light::{LightSampleConfig, LightSensorConfig, LightSensorModel},
+    },
     datastore::DataStore,
-    measure::{bh1750fvi::Bh1750Fvi, LightMeasurement},
-    sample::Sampler,
+    measure::{
+        as7341::As7341,
+        bh1750fvi::{Bh1750Fvi, MT_REG_MAX, MT_REG_MIN},
+        LightMeasurement, Measure, SensorError, SensorKind,
+    },
+    sample::{Sampler, Slot},
 };
 
+pub enum LightSensor {
+    Bh1750Fvi(Bh1750Fvi),
+    As7341(As7341),
+}
+
+impl Measure for LightSensor {
+    type Measurement = LightMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        match self {
+            LightSensor::Bh1750Fvi(sensor) => sensor.measure(cancel_token).await,
+            LightSensor::As7341(sensor) => sensor.measure(cancel_token).await,
+        }
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        match self {
+            LightSensor::Bh1750Fvi(sensor) => sensor.reinit().await,
+            LightSensor::As7341(sensor) => sensor.reinit().await,
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            LightSensor::Bh1750Fvi(sensor) => sensor.label(),
+            LightSensor::As7341(sensor) => sensor.label(),
+        }
+    }
+}
+
 pub struct LightSampler {
     receiver: mpsc::Receiver<Vec<LightMeasurement>>,
-    sampler: Sampler<Bh1750Fvi>,
+    sampler: Sampler<LightSensor>,
     store: DataStore,
 }
 
@@ -24,19 +64,21 @@ impl LightSampler {
         i2c_path: &Path,
         store: DataStore,
     ) -> Result<Self> {
-        let sensors = join_all(
-            config
-                .sensors
-                .iter()
-                .map(|(label, config)| Self::init_sensor(config, label, i2c_path)),
-        )
-        .await
-        .into_iter()
-        .collect::<Result<Vec<Bh1750Fvi>>>()?;
-
-        let (sender, receiver) = mpsc::channel(8);
-        let sampler = Sampler::new(config.sample_rate_secs, sender, sensors)
-            .context("Failed to initialize light sampler")?;
+        for (label, config) in &config.sensors {
+            Self::validate_sensor(config, label)?;
+        }
+        let sensors = join_all(config.sensors.iter().map(|(label, config)| {
+            let (config, sensor_label, i2c_path) =
+                (config.clone(), label.clone(), i2c_path.to_owned());
+            Slot::init(label.clone(), move || {
+                Self::init_sensor(config.clone(), sensor_label.clone(), i2c_path.clone())
+            })
+        }))
+        .await;
+
+        let (sampler, receiver) =
+            Sampler::from_config(SensorKind::Light, config, sensors, store.clone())
+                .context("Failed to initialize light sampler")?;
 
         Ok(Self {
             receiver,
@@ -66,17 +108,60 @@ impl LightSampler {
         }
     }
 
+    fn validate_sensor(config: &LightSensorConfig, label: &str) -> Result<()> {
+        if config.model == LightSensorModel::Bh1750Fvi
+            && !(MT_REG_MIN..=MT_REG_MAX).contains(&config.mt_reg)
+        {
+            bail!("MTreg of {label:?} light sensor must be between {MT_REG_MIN} and {MT_REG_MAX}");
+        }
+
+        // the PPFD is estimated from the illuminance before it's calibrated
+        if config.light_source.is_some()
+            && config.calibration.contains_key("illuminance")
+            && !config.calibration.contains_key("ppfd")
+        {
+            bail!(
+                "Calibration of illuminance of {label:?} light sensor requires a calibration of \
+                 ppfd, which is estimated from the uncalibrated illuminance"
+            );
+        }
+
+        Ok(())
+    }
+
     async fn init_sensor(
-        config: &LightSensorConfig,
-        label: &str,
-        i2c_path: impl AsRef<Path>,
-    ) -> Result<Bh1750Fvi> {
-        match config.model {
+        config: LightSensorConfig,
+        label: String,
+        i2c_path: PathBuf,
+    ) -> Result<LightSensor> {
+        let sensor = match config.model {
             LightSensorModel::Bh1750Fvi => {
-                Bh1750Fvi::new(i2c_path, config.address, label.to_owned())
-                    .await
-                    .with_context(|| format!("Failed to initialize {:?} light sensor", label))
+                let sensor = Bh1750Fvi::new(
+                    i2c_path,
+                    sensor_address(config.address, config.mux.as_ref()),
+                    label.clone(),
+                )
+                .await;
+                sensor.map(|s| {
+                    let s = s
+                        .resolution(config.resolution)
+                        .mt_reg(config.mt_reg)
+                        .auto_range(config.auto_range);
+                    match &config.light_source {
+                        Some(source) => LightSensor::Bh1750Fvi(s.ppfd_factor(source.ppfd_factor())),
+                        None => LightSensor::Bh1750Fvi(s),
+                    }
+                })
             }
-        }
+            LightSensorModel::As7341 => As7341::new(
+                i2c_path,
+                sensor_address(config.address, config.mux.as_ref()),
+                label.clone(),
+            )
+            .await
+            .map(LightSensor::As7341),
+        };
+
+        sensor.with_context(|| format!("Failed to initialize {:?} light sensor", label))
     }
 }
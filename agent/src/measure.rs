@@ -1,25 +1,75 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use sqlx::prelude::FromRow;
+use sqlx::prelude::{FromRow, Type};
 use tokio_util::sync::CancellationToken;
 
+use crate::{climate, tank::Tank};
+
+pub use error::{Recovery, SensorError};
+pub use i2c::{BusStats, BusStatus, I2cAddress, I2cBus, I2cError};
+
+pub mod as7341;
 pub mod bh1750fvi;
 pub mod bme680;
+pub mod command;
+mod error;
+pub mod ezo;
+pub mod ezo_ec;
+pub mod ezo_ph;
+pub mod ezo_rtd;
 mod i2c;
+pub mod ina2xx;
+pub mod jsn_sr04t;
+pub mod mlx90614;
+pub mod registry;
 pub mod vl53l0x;
+pub mod vl53l1x;
 
-#[trait_variant::make]
+#[trait_variant::make(Send)]
 pub trait Measure {
     type Measurement;
 
-    async fn measure(&mut self, cancel_token: CancellationToken) -> Result<Self::Measurement>;
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError>;
+    /// Initializes the sensor again, e.g. after it lost power or its configuration.
+    async fn reinit(&mut self) -> Result<(), SensorError>;
     fn label(&self) -> &str;
 }
 
+/// Access to the quantities a sensor measures by their name, e.g. to combine or check
+/// readings independent of the measurement type. Quantities derived from other ones are not
+/// included.
+pub trait Quantities {
+    /// The names of the measured quantities.
+    const QUANTITIES: &'static [&'static str];
+    /// The names of the quantities that can be calibrated, their raw values are kept.
+    const CALIBRATABLE: &'static [&'static str];
+
+    /// The names of the measured quantities of this reading, which only differ from
+    /// `QUANTITIES` if they depend on the sensor model.
+    fn quantity_names(&self) -> Vec<&'static str> {
+        Self::QUANTITIES.to_vec()
+    }
+    fn quantity(&self, name: &str) -> Option<f64>;
+    fn set_quantity(&mut self, name: &str, value: Option<f64>);
+    /// Sets the value of a quantity before calibration.
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>);
+}
+
+fn to_u16(value: Option<f64>) -> Option<u16> {
+    value.map(|v| v.round().clamp(0., f64::from(u16::MAX)) as u16)
+}
+
+fn to_u32(value: Option<f64>) -> Option<u32> {
+    value.map(|v| v.round().clamp(0., f64::from(u32::MAX)) as u32)
+}
+
 /// A single air measurement.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
 pub struct AirMeasurement {
-    /// The number of seconds since unix epoch.
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
     pub measure_time: i64,
     /// The label of the sensor that took this measurement.
     pub label: String,
@@ -34,6 +84,23 @@ pub struct AirMeasurement {
     /// Higher concentration of VOCs leads to lower resistance.
     /// Lower concentration of VOCs leads to higher resistance.
     pub resistance: Option<f64>,
+    /// The indoor air quality index from 0 (excellent) to 500 (extremely polluted),
+    /// estimated from the resistance and humidity.
+    pub iaq: Option<f64>,
+    /// The vapour pressure deficit in kPa.
+    pub vpd: Option<f64>,
+    /// The dew point in degree celsius.
+    pub dew_point: Option<f64>,
+    /// The absolute humidity in g/m³.
+    pub absolute_humidity: Option<f64>,
+    /// The temperature before calibration, only set if it's calibrated.
+    pub raw_temperature: Option<f64>,
+    /// The humidity before calibration, only set if it's calibrated.
+    pub raw_humidity: Option<f64>,
+    /// The pressure before calibration, only set if it's calibrated.
+    pub raw_pressure: Option<f64>,
+    /// The resistance before calibration, only set if it's calibrated.
+    pub raw_resistance: Option<f64>,
 }
 
 impl AirMeasurement {
@@ -45,6 +112,14 @@ impl AirMeasurement {
             humidity: None,
             pressure: None,
             resistance: None,
+            iaq: None,
+            vpd: None,
+            dew_point: None,
+            absolute_humidity: None,
+            raw_temperature: None,
+            raw_humidity: None,
+            raw_pressure: None,
+            raw_resistance: None,
         }
     }
 
@@ -67,17 +142,66 @@ impl AirMeasurement {
         self.resistance = Some(resistance);
         self
     }
+
+    pub fn iaq(mut self, iaq: f64) -> Self {
+        self.iaq = Some(iaq);
+        self
+    }
+
+    /// Computes the climate metrics that are derived from temperature and humidity.
+    /// The leaf temperature offset is added to the temperature to compute the leaf VPD.
+    pub fn derive_climate(mut self, leaf_temperature_offset: f64) -> Self {
+        if let (Some(temperature), Some(humidity)) = (self.temperature, self.humidity) {
+            if humidity > 0. {
+                self.vpd = Some(climate::vapour_pressure_deficit(
+                    temperature,
+                    humidity,
+                    leaf_temperature_offset,
+                ));
+                self.dew_point = Some(climate::dew_point(temperature, humidity));
+                self.absolute_humidity = Some(climate::absolute_humidity(temperature, humidity));
+            }
+        }
+
+        self
+    }
 }
 
 /// A single light measurement.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
 pub struct LightMeasurement {
-    /// The number of seconds since unix epoch.
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
     pub measure_time: i64,
     /// The label of the sensor that took this measurement.
     pub label: String,
     /// The illuminance in lux.
     pub illuminance: Option<f64>,
+    /// The photosynthetic photon flux density in µmol/m²/s.
+    pub ppfd: Option<f64>,
+    /// The raw counts of the 415nm spectral channel.
+    pub f1: Option<u16>,
+    /// The raw counts of the 445nm spectral channel.
+    pub f2: Option<u16>,
+    /// The raw counts of the 480nm spectral channel.
+    pub f3: Option<u16>,
+    /// The raw counts of the 515nm spectral channel.
+    pub f4: Option<u16>,
+    /// The raw counts of the 555nm spectral channel.
+    pub f5: Option<u16>,
+    /// The raw counts of the 590nm spectral channel.
+    pub f6: Option<u16>,
+    /// The raw counts of the 630nm spectral channel.
+    pub f7: Option<u16>,
+    /// The raw counts of the 680nm spectral channel.
+    pub f8: Option<u16>,
+    /// The raw counts of the clear (unfiltered) channel.
+    pub clear: Option<u16>,
+    /// The raw counts of the near infrared channel.
+    pub nir: Option<u16>,
+    /// The illuminance before calibration, only set if it's calibrated.
+    pub raw_illuminance: Option<f64>,
+    /// The PPFD before calibration, only set if it's calibrated.
+    pub raw_ppfd: Option<f64>,
 }
 
 impl LightMeasurement {
@@ -86,6 +210,19 @@ impl LightMeasurement {
             measure_time,
             label,
             illuminance: None,
+            ppfd: None,
+            f1: None,
+            f2: None,
+            f3: None,
+            f4: None,
+            f5: None,
+            f6: None,
+            f7: None,
+            f8: None,
+            clear: None,
+            nir: None,
+            raw_illuminance: None,
+            raw_ppfd: None,
         }
     }
 
@@ -93,17 +230,46 @@ impl LightMeasurement {
         self.illuminance = Some(illuminance);
         self
     }
+
+    pub fn ppfd(mut self, ppfd: f64) -> Self {
+        self.ppfd = Some(ppfd);
+        self
+    }
+
+    /// Sets the raw counts of the spectral channels F1 to F8, clear and near infrared.
+    pub fn spectral_counts(mut self, bands: [u16; 8], clear: u16, nir: u16) -> Self {
+        let [f1, f2, f3, f4, f5, f6, f7, f8] = bands.map(Some);
+        self.f1 = f1;
+        self.f2 = f2;
+        self.f3 = f3;
+        self.f4 = f4;
+        self.f5 = f5;
+        self.f6 = f6;
+        self.f7 = f7;
+        self.f8 = f8;
+        self.clear = Some(clear);
+        self.nir = Some(nir);
+        self
+    }
 }
 
 /// A single water level measurement.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
 pub struct WaterLevelMeasurement {
-    /// The number of seconds since unix epoch.
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
     pub measure_time: i64,
     /// The label of the sensor that took this measurement.
     pub label: String,
     /// The distance between the sensor and the water surface in mm.
     pub distance: Option<u32>,
+    /// The status of the range measurement, the distance is only set if it's valid.
+    pub range_status: Option<RangeStatus>,
+    /// The fill level of the tank in percent.
+    pub fill_level: Option<f64>,
+    /// The volume of the water in the tank in litres.
+    pub volume: Option<f64>,
+    /// The distance before calibration, only set if it's calibrated.
+    pub raw_distance: Option<u32>,
 }
 
 impl WaterLevelMeasurement {
@@ -112,6 +278,10 @@ impl WaterLevelMeasurement {
             measure_time,
             label,
             distance: None,
+            range_status: None,
+            fill_level: None,
+            volume: None,
+            raw_distance: None,
         }
     }
 
@@ -119,4 +289,472 @@ impl WaterLevelMeasurement {
         self.distance = Some(distance);
         self
     }
+
+    pub fn range_status(mut self, range_status: RangeStatus) -> Self {
+        self.range_status = Some(range_status);
+        self
+    }
+
+    /// Computes the fill level and volume from the distance with the geometry of the tank.
+    pub fn derive_fill(mut self, tank: &Tank) -> Self {
+        if let Some(distance) = self.distance {
+            self.fill_level = Some(tank.fill_level(distance));
+            self.volume = Some(tank.volume(distance));
+        }
+
+        self
+    }
+}
+
+/// The status of a range measurement of a time-of-flight sensor.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum RangeStatus {
+    /// The distance is valid.
+    Valid,
+    /// The estimated standard deviation of the distance is too high.
+    SigmaFail,
+    /// The return signal is too weak, e.g. because the target is out of range.
+    SignalFail,
+    /// The target is closer than the minimum range.
+    MinRangeFail,
+    /// The phase of the return signal is out of bounds.
+    PhaseFail,
+    /// The sensor reported a hardware failure.
+    HardwareFail,
+    /// The sensor reported an unknown status.
+    Unknown,
+}
+
+/// A single nutrient solution measurement.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+pub struct NutrientMeasurement {
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
+    pub measure_time: i64,
+    /// The label of the sensor that took this measurement.
+    pub label: String,
+    /// The pH value.
+    pub ph: Option<f64>,
+    /// The electrical conductivity in µS/cm.
+    pub conductivity: Option<f64>,
+    /// The temperature in degree celsius.
+    pub temperature: Option<f64>,
+    /// The pH value before calibration, only set if it's calibrated.
+    pub raw_ph: Option<f64>,
+    /// The electrical conductivity before calibration, only set if it's calibrated.
+    pub raw_conductivity: Option<f64>,
+    /// The temperature before calibration, only set if it's calibrated.
+    pub raw_temperature: Option<f64>,
+}
+
+impl NutrientMeasurement {
+    pub fn new(measure_time: i64, label: String) -> Self {
+        Self {
+            measure_time,
+            label,
+            ph: None,
+            conductivity: None,
+            temperature: None,
+            raw_ph: None,
+            raw_conductivity: None,
+            raw_temperature: None,
+        }
+    }
+
+    pub fn ph(mut self, ph: f64) -> Self {
+        self.ph = Some(ph);
+        self
+    }
+
+    pub fn conductivity(mut self, conductivity: f64) -> Self {
+        self.conductivity = Some(conductivity);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+}
+
+/// A single power measurement of a load.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+pub struct PowerMeasurement {
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
+    pub measure_time: i64,
+    /// The label of the sensor that took this measurement.
+    pub label: String,
+    /// The controller that switches the load, if any.
+    pub controller: Option<ControllerKind>,
+    /// Whether the controller activated the load, unknown while it's disabled.
+    pub load_active: Option<bool>,
+    /// The bus voltage in volts.
+    pub voltage: Option<f64>,
+    /// The current in amperes.
+    pub current: Option<f64>,
+    /// The power in watts.
+    pub power: Option<f64>,
+    /// The voltage before calibration, only set if it's calibrated.
+    pub raw_voltage: Option<f64>,
+    /// The current before calibration, only set if it's calibrated.
+    pub raw_current: Option<f64>,
+    /// The power before calibration, only set if it's calibrated.
+    pub raw_power: Option<f64>,
+}
+
+impl PowerMeasurement {
+    pub fn new(measure_time: i64, label: String) -> Self {
+        Self {
+            measure_time,
+            label,
+            controller: None,
+            load_active: None,
+            voltage: None,
+            current: None,
+            power: None,
+            raw_voltage: None,
+            raw_current: None,
+            raw_power: None,
+        }
+    }
+
+    pub fn voltage(mut self, voltage: f64) -> Self {
+        self.voltage = Some(voltage);
+        self
+    }
+
+    pub fn current(mut self, current: f64) -> Self {
+        self.current = Some(current);
+        self
+    }
+
+    pub fn power(mut self, power: f64) -> Self {
+        self.power = Some(power);
+        self
+    }
+
+    /// Links the measurement to the controller that switches the load and its current state.
+    pub fn load(mut self, controller: ControllerKind, active: Option<bool>) -> Self {
+        self.controller = Some(controller);
+        self.load_active = active;
+        self
+    }
+}
+
+/// A single infrared measurement of the temperature of leaves or the canopy.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+pub struct LeafMeasurement {
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
+    pub measure_time: i64,
+    /// The label of the sensor that took this measurement.
+    pub label: String,
+    /// The temperature of the surface in the field of view in degree celsius.
+    pub object_temperature: Option<f64>,
+    /// The temperature of the sensor in degree celsius.
+    pub ambient_temperature: Option<f64>,
+    /// The object temperature before calibration, only set if it's calibrated.
+    pub raw_object_temperature: Option<f64>,
+    /// The ambient temperature before calibration, only set if it's calibrated.
+    pub raw_ambient_temperature: Option<f64>,
+}
+
+impl LeafMeasurement {
+    pub fn new(measure_time: i64, label: String) -> Self {
+        Self {
+            measure_time,
+            label,
+            object_temperature: None,
+            ambient_temperature: None,
+            raw_object_temperature: None,
+            raw_ambient_temperature: None,
+        }
+    }
+
+    pub fn object_temperature(mut self, temperature: f64) -> Self {
+        self.object_temperature = Some(temperature);
+        self
+    }
+
+    pub fn ambient_temperature(mut self, temperature: f64) -> Self {
+        self.ambient_temperature = Some(temperature);
+        self
+    }
+}
+
+/// The kind of measurements a sensor takes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum SensorKind {
+    Air,
+    Light,
+    WaterLevel,
+    Nutrient,
+    /// A sensor of a registered model.
+    Generic,
+    Power,
+    Leaf,
+}
+
+/// The controllers of the agent, which switch the loads that power sensors measure.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Type)]
+#[sqlx(rename_all = "snake_case")]
+pub enum ControllerKind {
+    Air,
+    AirPump,
+    Fan,
+    Light,
+    WaterLevel,
+}
+
+/// The failure counters of a single sensor.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+pub struct SensorStatus {
+    pub kind: SensorKind,
+    pub label: String,
+    /// The number of samples that failed after all retries.
+    pub failures: i64,
+    /// The number of retried measurements.
+    pub retries: i64,
+    /// The number of times the sensor was initialized again.
+    pub reinits: i64,
+    /// The number of samples that failed since the last successful one.
+    pub consecutive_failures: i64,
+    pub last_error: Option<String>,
+    /// The number of milliseconds since unix epoch.
+    pub last_failure_time: Option<i64>,
+    /// Whether the sensor responded since the agent started, absent sensors aren't sampled.
+    pub present: bool,
+}
+
+/// The outcome of a single sample of a sensor, which is added to its [`SensorStatus`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleStatus<'a> {
+    pub kind: SensorKind,
+    pub label: &'a str,
+    pub retries: u32,
+    pub reinits: u32,
+    /// Why the sample failed, if it did.
+    pub error: Option<&'a str>,
+    /// The number of milliseconds since unix epoch.
+    pub sample_time: i64,
+    /// Whether the sensor responded, absent sensors fail to initialize and aren't sampled.
+    pub present: bool,
+}
+
+impl Quantities for AirMeasurement {
+    const QUANTITIES: &'static [&'static str] =
+        &["temperature", "humidity", "pressure", "resistance"];
+    const CALIBRATABLE: &'static [&'static str] = Self::QUANTITIES;
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        match name {
+            "temperature" => self.temperature,
+            "humidity" => self.humidity,
+            "pressure" => self.pressure,
+            "resistance" => self.resistance,
+            _ => None,
+        }
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "temperature" => self.temperature = value,
+            "humidity" => self.humidity = value,
+            "pressure" => self.pressure = value,
+            "resistance" => self.resistance = value,
+            _ => {}
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "temperature" => self.raw_temperature = value,
+            "humidity" => self.raw_humidity = value,
+            "pressure" => self.raw_pressure = value,
+            "resistance" => self.raw_resistance = value,
+            _ => {}
+        }
+    }
+}
+
+impl Quantities for LightMeasurement {
+    const QUANTITIES: &'static [&'static str] = &[
+        "illuminance",
+        "ppfd",
+        "f1",
+        "f2",
+        "f3",
+        "f4",
+        "f5",
+        "f6",
+        "f7",
+        "f8",
+        "clear",
+        "nir",
+    ];
+    // the spectral channels are raw counts anyway
+    const CALIBRATABLE: &'static [&'static str] = &["illuminance", "ppfd"];
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        let channel = match name {
+            "illuminance" => return self.illuminance,
+            "ppfd" => return self.ppfd,
+            "f1" => self.f1,
+            "f2" => self.f2,
+            "f3" => self.f3,
+            "f4" => self.f4,
+            "f5" => self.f5,
+            "f6" => self.f6,
+            "f7" => self.f7,
+            "f8" => self.f8,
+            "clear" => self.clear,
+            "nir" => self.nir,
+            _ => None,
+        };
+
+        channel.map(f64::from)
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "illuminance" => self.illuminance = value,
+            "ppfd" => self.ppfd = value,
+            "f1" => self.f1 = to_u16(value),
+            "f2" => self.f2 = to_u16(value),
+            "f3" => self.f3 = to_u16(value),
+            "f4" => self.f4 = to_u16(value),
+            "f5" => self.f5 = to_u16(value),
+            "f6" => self.f6 = to_u16(value),
+            "f7" => self.f7 = to_u16(value),
+            "f8" => self.f8 = to_u16(value),
+            "clear" => self.clear = to_u16(value),
+            "nir" => self.nir = to_u16(value),
+            _ => {}
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "illuminance" => self.raw_illuminance = value,
+            "ppfd" => self.raw_ppfd = value,
+            _ => {}
+        }
+    }
+}
+
+impl Quantities for WaterLevelMeasurement {
+    const QUANTITIES: &'static [&'static str] = &["distance"];
+    const CALIBRATABLE: &'static [&'static str] = Self::QUANTITIES;
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        match name {
+            "distance" => self.distance.map(f64::from),
+            _ => None,
+        }
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        if name == "distance" {
+            self.distance = to_u32(value);
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        if name == "distance" {
+            self.raw_distance = to_u32(value);
+        }
+    }
+}
+
+impl Quantities for NutrientMeasurement {
+    const QUANTITIES: &'static [&'static str] = &["ph", "conductivity", "temperature"];
+    const CALIBRATABLE: &'static [&'static str] = Self::QUANTITIES;
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        match name {
+            "ph" => self.ph,
+            "conductivity" => self.conductivity,
+            "temperature" => self.temperature,
+            _ => None,
+        }
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "ph" => self.ph = value,
+            "conductivity" => self.conductivity = value,
+            "temperature" => self.temperature = value,
+            _ => {}
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "ph" => self.raw_ph = value,
+            "conductivity" => self.raw_conductivity = value,
+            "temperature" => self.raw_temperature = value,
+            _ => {}
+        }
+    }
+}
+
+impl Quantities for PowerMeasurement {
+    const QUANTITIES: &'static [&'static str] = &["voltage", "current", "power"];
+    const CALIBRATABLE: &'static [&'static str] = Self::QUANTITIES;
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        match name {
+            "voltage" => self.voltage,
+            "current" => self.current,
+            "power" => self.power,
+            _ => None,
+        }
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "voltage" => self.voltage = value,
+            "current" => self.current = value,
+            "power" => self.power = value,
+            _ => {}
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "voltage" => self.raw_voltage = value,
+            "current" => self.raw_current = value,
+            "power" => self.raw_power = value,
+            _ => {}
+        }
+    }
+}
+
+impl Quantities for LeafMeasurement {
+    const QUANTITIES: &'static [&'static str] = &["object_temperature", "ambient_temperature"];
+    const CALIBRATABLE: &'static [&'static str] = Self::QUANTITIES;
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        match name {
+            "object_temperature" => self.object_temperature,
+            "ambient_temperature" => self.ambient_temperature,
+            _ => None,
+        }
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "object_temperature" => self.object_temperature = value,
+            "ambient_temperature" => self.ambient_temperature = value,
+            _ => {}
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        match name {
+            "object_temperature" => self.raw_object_temperature = value,
+            "ambient_temperature" => self.raw_ambient_temperature = value,
+            _ => {}
+        }
+    }
 }
@@ -0,0 +1,194 @@
+use super::{
+    i2c::{I2cAddress, I2C},
+    LightMeasurement, Measure, SensorError,
+};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::{path::Path, time::Duration};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+const DEVICE_ID: u8 = 0b001001;
+
+const REG_CONFIG: u8 = 0x70;
+const REG_ENABLE: u8 = 0x80;
+const REG_ATIME: u8 = 0x81;
+const REG_ID: u8 = 0x92;
+const REG_CH0_DATA_L: u8 = 0x95;
+const REG_STATUS2: u8 = 0xA3;
+const REG_CFG0: u8 = 0xA9;
+const REG_CFG1: u8 = 0xAA;
+const REG_CFG6: u8 = 0xAF;
+const REG_ASTEP_L: u8 = 0xCA;
+const REG_ASTEP_H: u8 = 0xCB;
+
+const MASK_PON: u8 = 0x01;
+const MASK_SP_EN: u8 = 0x02;
+const MASK_SMUXEN: u8 = 0x10;
+const MASK_AVALID: u8 = 0x40;
+const MASK_REG_BANK: u8 = 0x10;
+const CMD_SMUX_WRITE: u8 = 0x10;
+
+// integration time = (ATIME + 1) * (ASTEP + 1) * 2.78µs ≈ 50ms
+const ATIME: u8 = 29;
+const ASTEP: u16 = 599;
+// 4x gain, which doesn't saturate under typical grow lights
+const AGAIN: u8 = 3;
+
+const POLL_DURATION: Duration = Duration::from_millis(10);
+// ten times the integration time
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+const DATA_SIZE: usize = 12;
+
+/// SMUX configuration that maps F1-F4, Clear and NIR to the ADCs 0-5.
+const SMUX_LOW_CHANNELS: [u8; 20] = [
+    0x30, 0x01, 0x00, 0x00, 0x00, 0x42, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x20, 0x04, 0x00, 0x30,
+    0x01, 0x50, 0x00, 0x06,
+];
+/// SMUX configuration that maps F5-F8, Clear and NIR to the ADCs 0-5.
+const SMUX_HIGH_CHANNELS: [u8; 20] = [
+    0x00, 0x00, 0x00, 0x40, 0x02, 0x00, 0x10, 0x03, 0x50, 0x10, 0x03, 0x00, 0x00, 0x00, 0x24, 0x00,
+    0x00, 0x50, 0x00, 0x06,
+];
+
+/// AS7341
+pub struct As7341 {
+    i2c: I2C,
+    label: String,
+}
+
+impl As7341 {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut i2c = I2C::new(i2c_path, address).await?;
+        Self::init(&mut i2c)
+            .await
+            .with_context(|| format!("Failed to initialize AS7341 at address {address}"))?;
+
+        Ok(Self { i2c, label })
+    }
+
+    async fn init(i2c: &mut I2C) -> Result<()> {
+        let id = i2c.read_reg_byte(REG_ID).await?;
+        if (id >> 2) != DEVICE_ID {
+            return Err(SensorError::WrongChipId {
+                expected: DEVICE_ID.into(),
+                found: (id >> 2).into(),
+            }
+            .into());
+        }
+
+        i2c.write_reg_byte(REG_ENABLE, MASK_PON).await?;
+        // select register bank 0x80 and above
+        let cfg0 = i2c.read_reg_byte(REG_CFG0).await?;
+        i2c.write_reg_byte(REG_CFG0, cfg0 & !MASK_REG_BANK).await?;
+        i2c.write_reg_byte(REG_ATIME, ATIME).await?;
+        let astep = ASTEP.to_le_bytes();
+        i2c.write_reg_byte(REG_ASTEP_L, astep[0]).await?;
+        i2c.write_reg_byte(REG_ASTEP_H, astep[1]).await?;
+        i2c.write_reg_byte(REG_CFG1, AGAIN).await?;
+        // spectral measurement mode
+        i2c.write_reg_byte(REG_CONFIG, 0x00).await?;
+
+        Ok(())
+    }
+
+    async fn read_channels(
+        &mut self,
+        smux: &[u8; 20],
+        cancel_token: &CancellationToken,
+    ) -> Result<[u16; 6]> {
+        let enable = self.i2c.read_reg_byte(REG_ENABLE).await?;
+        self.i2c
+            .write_reg_byte(REG_ENABLE, enable & !MASK_SP_EN)
+            .await?;
+
+        // write SMUX configuration from RAM to the SMUX chain
+        self.i2c.write_reg_byte(REG_CFG6, CMD_SMUX_WRITE).await?;
+        for (reg, value) in smux.iter().enumerate() {
+            self.i2c.write_reg_byte(reg as u8, *value).await?;
+        }
+        self.i2c
+            .write_reg_byte(REG_ENABLE, MASK_PON | MASK_SMUXEN)
+            .await?;
+        self.wait_until(REG_ENABLE, MASK_SMUXEN, 0, cancel_token)
+            .await?;
+
+        self.i2c
+            .write_reg_byte(REG_ENABLE, MASK_PON | MASK_SP_EN)
+            .await?;
+        self.wait_until(REG_STATUS2, MASK_AVALID, MASK_AVALID, cancel_token)
+            .await?;
+
+        let mut buf = [0; DATA_SIZE];
+        self.i2c.read_reg_bytes(REG_CH0_DATA_L, &mut buf).await?;
+        let mut channels = [0; 6];
+        for (channel, bytes) in channels.iter_mut().zip(buf.chunks_exact(2)) {
+            *channel = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        Ok(channels)
+    }
+
+    async fn wait_until(
+        &mut self,
+        register: u8,
+        mask: u8,
+        expected: u8,
+        cancel_token: &CancellationToken,
+    ) -> Result<()> {
+        let deadline = Instant::now() + POLL_TIMEOUT;
+        loop {
+            if (self.i2c.read_reg_byte(register).await? & mask) == expected {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout(format!("register {register:#04x}")).into());
+            }
+
+            tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    return Err(SensorError::Cancelled.into());
+                }
+                _ = tokio::time::sleep(POLL_DURATION) => {}
+            }
+        }
+    }
+}
+
+impl Measure for As7341 {
+    type Measurement = LightMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        let low = self
+            .read_channels(&SMUX_LOW_CHANNELS, &cancel_token)
+            .await?;
+        let high = self
+            .read_channels(&SMUX_HIGH_CHANNELS, &cancel_token)
+            .await?;
+        let measure_time = Utc::now().timestamp_millis();
+
+        let bands = [
+            low[0], low[1], low[2], low[3], high[0], high[1], high[2], high[3],
+        ];
+        let measurement = LightMeasurement::new(measure_time, self.label.clone())
+            .spectral_counts(bands, high[4], high[5]);
+
+        Ok(measurement)
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        Ok(Self::init(&mut self.i2c).await?)
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
@@ -1,68 +1,350 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use std::{path::Path, time::Duration};
 use tokio_util::sync::CancellationToken;
 
 use crate::measure::LightMeasurement;
 
-use super::{i2c::I2C, Measure};
+use super::{
+    i2c::{I2cAddress, I2C},
+    Measure, SensorError,
+};
 
 const MODE_ONE_TIME_HIGH_RES: u8 = 0x20;
-const WAIT_DURATION: Duration = Duration::from_millis(200);
-const MT_REG_MAX: u8 = 31;
+const MODE_ONE_TIME_HIGH_RES2: u8 = 0x21;
+const MODE_ONE_TIME_LOW_RES: u8 = 0x23;
+const WAIT_DURATION_HIGH_RES_MS: u64 = 180;
+const WAIT_DURATION_LOW_RES_MS: u64 = 24;
+pub const MT_REG_MIN: u8 = 31;
+pub const MT_REG_MAX: u8 = 254;
 const MT_REG_DEFAULT: u8 = 69;
-const MASK_MT_REG_MIN: u8 = 0x1F;
+const MASK_MT_REG_LOW: u8 = 0x1F;
 const CMD_SET_MT_HIGH: u8 = 0b01000 << 3;
 const CMD_SET_MT_LOW: u8 = 0b011 << 5;
+const COUNTS_SATURATED: u16 = u16::MAX;
+const COUNTS_DARK: u16 = 1000;
+
+/// The resolution mode of a measurement.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum Resolution {
+    /// 1 lx resolution.
+    #[default]
+    High,
+    /// 0.5 lx resolution, with half the measurement range of `High`.
+    High2,
+    /// 4 lx resolution, with a much shorter measurement time.
+    Low,
+}
+
+impl Resolution {
+    fn mode(&self) -> u8 {
+        match self {
+            Resolution::High => MODE_ONE_TIME_HIGH_RES,
+            Resolution::High2 => MODE_ONE_TIME_HIGH_RES2,
+            Resolution::Low => MODE_ONE_TIME_LOW_RES,
+        }
+    }
+
+    /// Returns the maximum measurement time with the given MTreg value.
+    fn wait_duration(&self, mt_reg: u8) -> Duration {
+        let wait_ms = match self {
+            Resolution::High | Resolution::High2 => WAIT_DURATION_HIGH_RES_MS,
+            Resolution::Low => WAIT_DURATION_LOW_RES_MS,
+        };
+
+        Duration::from_millis(wait_ms * (mt_reg as u64) / (MT_REG_DEFAULT as u64) + 1)
+    }
+
+    /// Converts the raw counts into lux.
+    fn illuminance(&self, counts: u16, mt_reg: u8) -> f64 {
+        let divisor = match self {
+            Resolution::High2 => 2.,
+            Resolution::High | Resolution::Low => 1.,
+        };
+
+        (counts as f64) / 1.2 * ((MT_REG_DEFAULT as f64) / (mt_reg as f64)) / divisor
+    }
+}
 
 /// BH1750FVI
 pub struct Bh1750Fvi {
     i2c: I2C,
     label: String,
+    ppfd_factor: Option<f64>,
+    resolution: Resolution,
+    mt_reg: u8,
+    auto_range: bool,
 }
 
 impl Bh1750Fvi {
-    pub async fn new(i2c_path: impl AsRef<Path>, address: u8, label: String) -> Result<Self> {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
         let i2c = I2C::new(i2c_path, address).await?;
 
-        Ok(Self { i2c, label })
+        Ok(Self {
+            i2c,
+            label,
+            ppfd_factor: None,
+            resolution: Resolution::default(),
+            mt_reg: MT_REG_MIN,
+            auto_range: false,
+        })
     }
-}
 
-impl Measure for Bh1750Fvi {
-    type Measurement = LightMeasurement;
+    /// Sets the factor to convert illuminance in lux to PPFD in µmol/m²/s, which depends
+    /// on the spectrum of the light source.
+    pub fn ppfd_factor(mut self, factor: f64) -> Self {
+        self.ppfd_factor = Some(factor);
+        self
+    }
 
-    async fn measure(
-        &mut self,
-        cancel_token: CancellationToken,
-    ) -> Result<Self::Measurement> {
+    pub fn resolution(mut self, resolution: Resolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Sets the measurement time register, which determines the sensitivity. Higher values
+    /// increase the sensitivity but reduce the measurement range. Values are clamped to the
+    /// range from 31 to 254.
+    pub fn mt_reg(mut self, mt_reg: u8) -> Self {
+        self.mt_reg = mt_reg.clamp(MT_REG_MIN, MT_REG_MAX);
+        self
+    }
+
+    /// Enables automatic adjustment of the MTreg value, lowering the sensitivity when the
+    /// sensor saturates and raising it in the dark.
+    pub fn auto_range(mut self, auto_range: bool) -> Self {
+        self.auto_range = auto_range;
+        self
+    }
+
+    async fn read_counts(&mut self, cancel_token: &CancellationToken) -> Result<u16> {
         self.i2c
-            .write_bytes(&[CMD_SET_MT_HIGH | (MT_REG_MAX >> 5)])
+            .write_bytes(&[CMD_SET_MT_HIGH | (self.mt_reg >> 5)])
             .await?;
 
         self.i2c
-            .write_bytes(&[CMD_SET_MT_LOW | (MT_REG_MAX & MASK_MT_REG_MIN)])
+            .write_bytes(&[CMD_SET_MT_LOW | (self.mt_reg & MASK_MT_REG_LOW)])
             .await?;
 
-        self.i2c.write_bytes(&[MODE_ONE_TIME_HIGH_RES]).await?;
-        let measure_time = Utc::now().timestamp();
+        self.i2c.write_bytes(&[self.resolution.mode()]).await?;
 
         tokio::select! {
-            _ = cancel_token.cancelled() => {
-                bail!("Measurement cancelled");
-            }
-            _ = tokio::time::sleep(WAIT_DURATION) => {
+            _ = cancel_token.cancelled() => Err(SensorError::Cancelled.into()),
+            _ = tokio::time::sleep(self.resolution.wait_duration(self.mt_reg)) => {
                 let mut buf = [0; 2];
                 self.i2c.read_bytes(&mut buf[..]).await?;
-                let illuminance = ((((buf[0] as u32) << 8) | (buf[1] as u32)) as f64) / 1.2 * ((MT_REG_DEFAULT as f64) / (MT_REG_MAX as f64));
-                let measurement = LightMeasurement::new(measure_time, self.label.clone()).illuminance(illuminance);
 
-                Ok(measurement)
+                Ok(u16::from_be_bytes(buf))
+            }
+        }
+    }
+
+    /// Returns the MTreg value that should be used after a reading with the given counts,
+    /// if it differs from the current one.
+    fn next_mt_reg(&self, counts: u16) -> Option<u8> {
+        let mt_reg = if counts == COUNTS_SATURATED {
+            (self.mt_reg / 2).max(MT_REG_MIN)
+        } else if counts < COUNTS_DARK {
+            self.mt_reg.saturating_mul(2).min(MT_REG_MAX)
+        } else {
+            self.mt_reg
+        };
+
+        (mt_reg != self.mt_reg).then_some(mt_reg)
+    }
+}
+
+impl Measure for Bh1750Fvi {
+    type Measurement = LightMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        let mut counts = self.read_counts(&cancel_token).await?;
+
+        if self.auto_range {
+            let mut previous_counts = None;
+            while let Some(mt_reg) = self.next_mt_reg(counts) {
+                // stop when the sensitivity would be raised again after it was just lowered
+                if previous_counts == Some(COUNTS_SATURATED) && counts < COUNTS_DARK {
+                    break;
+                }
+
+                self.mt_reg = mt_reg;
+                previous_counts = Some(counts);
+                counts = self.read_counts(&cancel_token).await?;
             }
         }
+
+        let measure_time = Utc::now().timestamp_millis();
+        let illuminance = self.resolution.illuminance(counts, self.mt_reg);
+        let mut measurement =
+            LightMeasurement::new(measure_time, self.label.clone()).illuminance(illuminance);
+        if let Some(factor) = self.ppfd_factor {
+            measurement = measurement.ppfd(illuminance * factor);
+        }
+
+        Ok(measurement)
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        // the sensor is powered on and configured with every measurement
+        Ok(())
     }
 
-    fn label(&self) ->  &str {
+    fn label(&self) -> &str {
         &self.label
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::i2c::emulator::{Device, Emulator};
+    use std::io;
+
+    const ADDRESS: u8 = 0x23;
+
+    /// Emulates a sensor under constant light, the counts depend on the MTreg value.
+    struct Light {
+        lux: f64,
+        mt_reg: u8,
+        mode: Option<u8>,
+    }
+
+    impl Light {
+        fn new(lux: f64) -> Self {
+            Self {
+                lux,
+                mt_reg: MT_REG_DEFAULT,
+                mode: None,
+            }
+        }
+    }
+
+    impl Device for Light {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+            for &byte in bytes {
+                match byte {
+                    MODE_ONE_TIME_HIGH_RES | MODE_ONE_TIME_HIGH_RES2 | MODE_ONE_TIME_LOW_RES => {
+                        self.mode = Some(byte)
+                    }
+                    _ if byte & 0xF8 == CMD_SET_MT_HIGH => {
+                        self.mt_reg = (self.mt_reg & MASK_MT_REG_LOW) | (byte << 5)
+                    }
+                    _ if byte & 0xE0 == CMD_SET_MT_LOW => {
+                        self.mt_reg = (self.mt_reg & !MASK_MT_REG_LOW) | (byte & MASK_MT_REG_LOW)
+                    }
+                    _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+                }
+            }
+
+            Ok(())
+        }
+
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+            // one time modes power down after a measurement
+            let divisor = match self.mode.take() {
+                Some(MODE_ONE_TIME_HIGH_RES2) => 0.5,
+                Some(_) => 1.,
+                None => return Err(io::Error::from(io::ErrorKind::InvalidData)),
+            };
+            let counts =
+                self.lux * 1.2 * f64::from(self.mt_reg) / f64::from(MT_REG_DEFAULT) / divisor;
+            buf.copy_from_slice(&(counts.min(u16::MAX.into()) as u16).to_be_bytes());
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn illuminance_ok() {
+        assert_eq!(Resolution::High.illuminance(120, MT_REG_DEFAULT), 100.);
+        assert_eq!(Resolution::High2.illuminance(120, MT_REG_DEFAULT), 50.);
+        assert_eq!(Resolution::Low.illuminance(120, MT_REG_DEFAULT), 100.);
+        assert!((Resolution::High.illuminance(u16::MAX, MT_REG_MIN) - 121_557.).abs() < 1.);
+    }
+
+    #[test]
+    fn wait_duration_ok() {
+        assert_eq!(
+            Resolution::High.wait_duration(MT_REG_DEFAULT),
+            Duration::from_millis(181)
+        );
+        assert_eq!(
+            Resolution::Low.wait_duration(MT_REG_MAX),
+            Duration::from_millis(89)
+        );
+    }
+
+    #[tokio::test]
+    async fn measure_ok() {
+        let path = Emulator::new().device(ADDRESS, Light::new(100.)).attach();
+        let mut sensor = Bh1750Fvi::new(&path, ADDRESS, "main".into())
+            .await
+            .unwrap()
+            .resolution(Resolution::High2)
+            .mt_reg(MT_REG_DEFAULT)
+            .ppfd_factor(0.02);
+
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(measurement.illuminance, Some(100.));
+        assert_eq!(measurement.ppfd, Some(2.));
+    }
+
+    #[tokio::test]
+    async fn auto_range_ok() {
+        let path = Emulator::new()
+            .device(ADDRESS, Light::new(10.))
+            .device(ADDRESS + 1, Light::new(100_000.))
+            .attach();
+
+        // the sensitivity is raised until the counts leave the dark range or MTreg is at maximum
+        let mut dark = Bh1750Fvi::new(&path, ADDRESS, "dark".into())
+            .await
+            .unwrap()
+            .resolution(Resolution::Low)
+            .auto_range(true);
+        let measurement = dark.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(dark.mt_reg, MT_REG_MAX);
+        assert!((measurement.illuminance.unwrap() - 10.).abs() < 0.1);
+
+        // the sensitivity is lowered until the sensor doesn't saturate anymore
+        let mut bright = Bh1750Fvi::new(&path, ADDRESS + 1, "bright".into())
+            .await
+            .unwrap()
+            .resolution(Resolution::Low)
+            .mt_reg(MT_REG_MAX)
+            .auto_range(true);
+        let measurement = bright.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(bright.mt_reg, MT_REG_MIN);
+        assert!((measurement.illuminance.unwrap() - 100_000.).abs() < 5.);
+    }
+
+    #[tokio::test]
+    async fn measure_err() {
+        let path = Emulator::new().device(ADDRESS, Light::new(100.)).attach();
+        let mut sensor = Bh1750Fvi::new(&path, ADDRESS + 1, "absent".into())
+            .await
+            .unwrap();
+        assert!(matches!(
+            sensor.measure(CancellationToken::new()).await,
+            Err(SensorError::NotFound(_))
+        ));
+
+        let mut sensor = Bh1750Fvi::new(&path, ADDRESS, "main".into()).await.unwrap();
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        assert!(matches!(
+            sensor.measure(cancel_token).await,
+            Err(SensorError::Cancelled)
+        ));
+    }
+}
@@ -1,7 +1,12 @@
-use super::{i2c::I2C, AirMeasurement, Measure};
-use anyhow::{bail, Context, Result};
+use super::{
+    i2c::{I2cAddress, I2C},
+    AirMeasurement, Measure, SensorError,
+};
+use anyhow::{Context, Result};
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use std::{path::Path, time::Duration};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 
 const TEMPERATURE_MAX: u16 = 400;
@@ -67,12 +72,9 @@ const MASK_GAS_RANGE: u8 = 0x0F;
 const MASK_GAS_VALID: u8 = 0x20;
 const MASK_HEATER_STABLE: u8 = 0x10;
 
-const OVERSAMPLING_X2: u8 = 0b010;
-#[allow(dead_code)]
-const OVERSAMPLING_X4: u8 = 0b011;
-#[allow(dead_code)]
-const OVERSAMPLING_X8: u8 = 0b100;
-const OVERSAMPLING_X16: u8 = 0b101;
+const AMBIENT_TEMPERATURE_DEFAULT: i8 = 25;
+// the time a measurement may take in addition to the heating duration
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
 
 const CMD_SOFT_RESET: u8 = 0xB6;
 const CHIP_ID: u8 = 0x61;
@@ -95,8 +97,86 @@ const REG_CTRL_HUM: u8 = 0x72;
 const REG_GAS_WAIT0: u8 = 0x64;
 const REG_RES_HEAT0: u8 = 0x5A;
 const REG_CTRL_GAS1: u8 = 0x71;
+const REG_CONFIG: u8 = 0x75;
 const REG_DATA0: u8 = 0x1D;
 
+/// The oversampling rate of a measurement, higher rates reduce noise but take longer.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Oversampling {
+    /// Skip the measurement entirely.
+    Skipped = 0b000,
+    X1 = 0b001,
+    X2 = 0b010,
+    X4 = 0b011,
+    X8 = 0b100,
+    X16 = 0b101,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OversamplingSettings {
+    #[serde(default = "default_temperature_oversampling")]
+    pub temperature: Oversampling,
+    #[serde(default = "default_humidity_oversampling")]
+    pub humidity: Oversampling,
+    #[serde(default = "default_pressure_oversampling")]
+    pub pressure: Oversampling,
+}
+
+impl Default for OversamplingSettings {
+    fn default() -> Self {
+        Self {
+            temperature: default_temperature_oversampling(),
+            humidity: default_humidity_oversampling(),
+            pressure: default_pressure_oversampling(),
+        }
+    }
+}
+
+fn default_temperature_oversampling() -> Oversampling {
+    Oversampling::X2
+}
+
+fn default_humidity_oversampling() -> Oversampling {
+    Oversampling::X2
+}
+
+fn default_pressure_oversampling() -> Oversampling {
+    Oversampling::X16
+}
+
+/// The coefficient of the IIR filter applied to temperature and pressure,
+/// which suppresses short-term disturbances.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum IirFilter {
+    #[default]
+    Off = 0b000,
+    C1 = 0b001,
+    C3 = 0b010,
+    C7 = 0b011,
+    C15 = 0b100,
+    C31 = 0b101,
+    C63 = 0b110,
+    C127 = 0b111,
+}
+
+/// The heater profile used for gas measurements.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeaterProfile {
+    /// The target temperature of the heater in degree celsius, at most 400.
+    pub temperature: u16,
+    /// The heating duration in milliseconds, at most 4032.
+    pub duration_ms: u16,
+}
+
+impl Default for HeaterProfile {
+    fn default() -> Self {
+        Self {
+            temperature: 300,
+            duration_ms: 700,
+        }
+    }
+}
+
 struct SensorData {
     temp_adc: u32,
     press_adc: u32,
@@ -222,13 +302,13 @@ impl Params {
         }
 
         let var1 = ((self.gas.gh1 as f64) / 16.) + 49.;
-        let var2 = (((self.gas.gh2 as f64) / 32768.) * 0.00005) + 0.00235;
+        let var2 = (((self.gas.gh2 as f64) / 32768.) * 0.0005) + 0.00235;
         let var3 = (self.gas.gh3 as f64) / 1024.;
         let var4 = var1 * (1. + (var2 * (temperature as f64)));
         let var5 = var4 + (var3 * (ambient_temperature as f64));
-        (3.4 * (var5
-            * (4.
-                / (4. + (self.gas.heat_range as f64) * (1. + (self.gas.heat_val as f64) * 0.002)))
+        (3.4 * ((var5
+            * (4. / (4. + (self.gas.heat_range as f64)))
+            * (1. / (1. + ((self.gas.heat_val as f64) * 0.002))))
             - 25.)) as u8
     }
 
@@ -317,16 +397,49 @@ pub struct Bme680 {
     i2c: I2C,
     label: String,
     params: Params,
+    oversampling: OversamplingSettings,
+    iir_filter: IirFilter,
+    heater: Option<HeaterProfile>,
+    ambient_temperature: i8,
 }
 
 impl Bme680 {
-    pub async fn new(i2c_path: impl AsRef<Path>, address: u8, label: String) -> Result<Self> {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
         let mut i2c = I2C::new(i2c_path, address).await?;
         let params = Self::init_params(&mut i2c).await.with_context(|| {
-            format!("Failed to initialize parameters of BME680 at address 0x{address:02x}")
+            format!("Failed to initialize parameters of BME680 at address {address}")
         })?;
 
-        Ok(Self { i2c, label, params })
+        Ok(Self {
+            i2c,
+            label,
+            params,
+            oversampling: OversamplingSettings::default(),
+            iir_filter: IirFilter::default(),
+            heater: Some(HeaterProfile::default()),
+            ambient_temperature: AMBIENT_TEMPERATURE_DEFAULT,
+        })
+    }
+
+    pub fn oversampling(mut self, oversampling: OversamplingSettings) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    pub fn iir_filter(mut self, iir_filter: IirFilter) -> Self {
+        self.iir_filter = iir_filter;
+        self
+    }
+
+    /// Sets the heater profile, `None` disables gas measurements.
+    pub fn heater(mut self, heater: Option<HeaterProfile>) -> Self {
+        self.heater = heater;
+        self
     }
 
     async fn read_sensor_data(&mut self, cancel_token: CancellationToken) -> Result<SensorData> {
@@ -337,10 +450,18 @@ impl Bme680 {
             return Ok(SensorData::new(&buf));
         }
 
+        let heating = self
+            .heater
+            .map(|h| Duration::from_millis(h.duration_ms.into()))
+            .unwrap_or_default();
+        let deadline = Instant::now() + heating + POLL_TIMEOUT;
         loop {
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout("new data".into()).into());
+            }
             tokio::select! {
                 _ = cancel_token.cancelled() => {
-                    bail!("Measurement cancelled");
+                    return Err(SensorError::Cancelled.into());
                 },
                 _ = tokio::time::sleep(Duration::from_millis(10)) => {
                     self.i2c.read_reg_bytes(REG_DATA0, &mut buf).await?;
@@ -362,17 +483,16 @@ impl Bme680 {
         Ok(())
     }
 
-    async fn ensure_oversampling(
-        &mut self,
-        humidity: u8,
-        temperature: u8,
-        pressure: u8,
-    ) -> Result<()> {
+    async fn ensure_oversampling(&mut self) -> Result<()> {
         const OSRS_HMASK: u8 = 0x07;
         const OSRS_TMASK: u8 = 0xE0;
         const OSRS_PMASK: u8 = 0x1C;
         const OSRS_TP_MASK: u8 = OSRS_TMASK | OSRS_PMASK;
 
+        let humidity = self.oversampling.humidity as u8;
+        let temperature = self.oversampling.temperature as u8;
+        let pressure = self.oversampling.pressure as u8;
+
         let ctr_hum = self.i2c.read_reg_byte(REG_CTRL_HUM).await?;
         if (ctr_hum & OSRS_HMASK) != humidity {
             self.i2c
@@ -391,22 +511,37 @@ impl Bme680 {
         Ok(())
     }
 
-    async fn set_heater_config(
-        &mut self,
-        ambient_temperature: i8,
-        temperature: u16,
-        duration: u16,
-    ) -> Result<()> {
+    async fn ensure_iir_filter(&mut self) -> Result<()> {
+        const FILTER_MASK: u8 = 0x1C;
+
+        let config = self.i2c.read_reg_byte(REG_CONFIG).await?;
+        let desired_config = (self.iir_filter as u8) << 2;
+        if (config & FILTER_MASK) != desired_config {
+            self.i2c
+                .write_reg_byte(REG_CONFIG, (config & !FILTER_MASK) | desired_config)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn set_heater_config(&mut self) -> Result<()> {
+        let Some(heater) = self.heater else {
+            // disable run gas
+            self.i2c.write_reg_byte(REG_CTRL_GAS1, 0).await?;
+            return Ok(());
+        };
+
         self.i2c
             .write_reg_byte(
                 REG_RES_HEAT0,
                 self.params
-                    .calc_heat_resistance(ambient_temperature, temperature),
+                    .calc_heat_resistance(self.ambient_temperature, heater.temperature),
             )
             .await?;
 
         self.i2c
-            .write_reg_byte(REG_GAS_WAIT0, self.params.calc_gas_wait(duration))
+            .write_reg_byte(REG_GAS_WAIT0, self.params.calc_gas_wait(heater.duration_ms))
             .await?;
 
         // enable run gas and select heater profile 0
@@ -419,7 +554,11 @@ impl Bme680 {
         let id = i2c.read_reg_byte(REG_CHIP_ID).await?;
 
         if id != CHIP_ID {
-            bail!("Failed to identify sensor");
+            return Err(SensorError::WrongChipId {
+                expected: CHIP_ID.into(),
+                found: id.into(),
+            }
+            .into());
         }
 
         i2c.write_reg_byte(REG_RESET, CMD_SOFT_RESET).await?;
@@ -441,30 +580,197 @@ impl Bme680 {
 impl Measure for Bme680 {
     type Measurement = AirMeasurement;
 
-    async fn measure(&mut self, cancel_token: CancellationToken) -> Result<Self::Measurement> {
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
         self.set_op_mode(MODE_SLEEP).await?;
-        self.ensure_oversampling(OVERSAMPLING_X2, OVERSAMPLING_X2, OVERSAMPLING_X16)
-            .await?;
-        self.set_heater_config(25, 300, 700).await?;
+        self.ensure_oversampling().await?;
+        self.ensure_iir_filter().await?;
+        self.set_heater_config().await?;
         self.set_op_mode(MODE_FORCED).await?;
         let data = self.read_sensor_data(cancel_token).await?;
-        let measure_time = Utc::now().timestamp();
+        let measure_time = Utc::now().timestamp_millis();
         let (t_fine, temperature) = self.params.calc_temperature(data.temp_adc);
-        let humidity = self.params.calc_humidity(data.hum_adc, temperature);
-        let pressure = self.params.calc_pressure(data.press_adc, t_fine) / 100.;
-        let resistance = self
-            .params
-            .compute_resistance(data.gas_adc, data.gas_range as usize);
-        let measurement = AirMeasurement::new(measure_time, self.label.clone())
-            .temperature(temperature)
-            .humidity(humidity)
-            .pressure(pressure)
-            .resistance(resistance);
+        // the heater resistance of the next measurement depends on the ambient temperature
+        self.ambient_temperature = temperature.clamp(i8::MIN.into(), i8::MAX.into()) as i8;
+        let mut measurement =
+            AirMeasurement::new(measure_time, self.label.clone()).temperature(temperature);
+
+        if self.oversampling.humidity != Oversampling::Skipped {
+            let humidity = self.params.calc_humidity(data.hum_adc, temperature);
+            measurement = measurement.humidity(humidity);
+        }
+
+        if self.oversampling.pressure != Oversampling::Skipped {
+            let pressure = self.params.calc_pressure(data.press_adc, t_fine) / 100.;
+            measurement = measurement.pressure(pressure);
+        }
+
+        if self.heater.is_some() {
+            let resistance = self
+                .params
+                .compute_resistance(data.gas_adc, data.gas_range as usize);
+            measurement = measurement.resistance(resistance);
+        }
 
         Ok(measurement)
     }
 
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        self.params = Self::init_params(&mut self.i2c).await?;
+        Ok(())
+    }
+
     fn label(&self) -> &str {
         &self.label
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::i2c::emulator::{Emulator, RegisterMap};
+    use nix::libc;
+
+    const ADDRESS: u8 = 0x77;
+    // t1 26130, t2 26329, t3 3, p1 36380, p2 -10453, p3 88, p4 7150, p5 -79, p6 30, p7 33,
+    // p8 -2467, p9 -2476, p10 30, h1 767, h2 1017, h3 0, h4 45, h5 20, h6 120, h7 -100,
+    // gh1 -30, gh2 -12131, gh3 18, res_heat_val 47, res_heat_range 1, range_sw_err 1
+    const PARAMS: [u8; 42] = [
+        217, 102, 3, 0, 28, 142, 43, 215, 88, 0, 238, 27, 177, 255, 33, 30, 0, 0, 93, 246, 84, 246,
+        30, 63, 159, 47, 0, 45, 20, 120, 156, 18, 102, 157, 208, 226, 18, 47, 0, 16, 0, 16,
+    ];
+    // temperature 500000, pressure 400000, humidity 25000, gas 600 in range 5
+    const DATA: [u8; DATA_SIZE] = [
+        0x80, 0, 0x61, 0xA8, 0x00, 0x7A, 0x12, 0x00, 0x61, 0xA8, 0, 0, 0, 150, 0x35, 0, 0,
+    ];
+
+    fn device() -> RegisterMap {
+        RegisterMap::new()
+            .set(REG_CHIP_ID, &[CHIP_ID])
+            .set(REG_PARAMS1, &PARAMS[..PARAMS_SIZE1])
+            .set(
+                REG_PARAMS2,
+                &PARAMS[PARAMS_SIZE1..PARAMS_SIZE1 + PARAMS_SIZE2],
+            )
+            .set(REG_PARAMS3, &PARAMS[PARAMS_SIZE1 + PARAMS_SIZE2..])
+            .set(REG_DATA0, &DATA)
+    }
+
+    // The expected values are the results of the integer compensation of the Bosch BME68x
+    // SensorAPI (bme68x.c built without BME68X_USE_FPU: calc_temperature, calc_pressure,
+    // calc_humidity, calc_gas_resistance_low and calc_res_heat) for PARAMS and DATA. The
+    // floating point compensation agrees with it within the resolution of the integer outputs.
+    const REF_T_FINE: f64 = 131_663.;
+    // 0.01 degree celsius
+    const REF_TEMPERATURE: f64 = 25.72;
+    // pascal, the integer variant is accurate to a few pascal
+    const REF_PRESSURE: f64 = 91_757.;
+    // 0.001 %RH
+    const REF_HUMIDITY: f64 = 70.922;
+    // ohm
+    const REF_RESISTANCE_RANGE5: f64 = 232_872.;
+    const REF_RESISTANCE_RANGE4: f64 = 468_826.;
+    // res_heat_0 for 300 and 400 degree celsius at 25 degree celsius
+    const REF_RES_HEAT_300: u8 = 108;
+    const REF_RES_HEAT_400: u8 = 134;
+
+    #[test]
+    fn compensation_ok() {
+        let params = Params::new(&PARAMS);
+
+        let (t_fine, temperature) = params.calc_temperature(500_000);
+        assert!((t_fine - REF_T_FINE).abs() < 1.);
+        assert!((temperature - REF_TEMPERATURE).abs() < 0.01);
+        assert!((params.calc_humidity(25_000, temperature) - REF_HUMIDITY).abs() < 0.05);
+        assert!((params.calc_pressure(400_000, t_fine) - REF_PRESSURE).abs() < 5.);
+        assert!((params.compute_resistance(600, 5) - REF_RESISTANCE_RANGE5).abs() < 1.);
+        assert!((params.compute_resistance(600, 4) - REF_RESISTANCE_RANGE4).abs() < 1.);
+    }
+
+    #[test]
+    fn heater_ok() {
+        let params = Params::new(&PARAMS);
+
+        // the integer variant rounds while the floating point one truncates
+        let res_heat = |temperature| params.calc_heat_resistance(25, temperature);
+        assert!(res_heat(300).abs_diff(REF_RES_HEAT_300) <= 1);
+        assert!(res_heat(400).abs_diff(REF_RES_HEAT_400) <= 1);
+        assert_eq!(
+            params.calc_heat_resistance(25, 500),
+            params.calc_heat_resistance(25, TEMPERATURE_MAX)
+        );
+        // gas_wait_0 is 6 bits of milliseconds times a factor of 1, 4, 16 or 64 in the upper bits
+        assert_eq!(params.calc_gas_wait(100), 0x59);
+        assert_eq!(params.calc_gas_wait(700), 0xAB);
+        assert_eq!(params.calc_gas_wait(5000), GAS_WAIT_VALUE_MAX);
+    }
+
+    #[tokio::test]
+    async fn measure_ok() {
+        // the first read of the data registers has no new data yet
+        let device = device().script(REG_DATA0, &[0x00]);
+        let path = Emulator::new().device(ADDRESS, device.clone()).attach();
+        let mut sensor = Bme680::new(&path, ADDRESS, "bme".into()).await.unwrap();
+        assert_eq!(device.writes(), vec![(REG_RESET, CMD_SOFT_RESET)]);
+
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert!((measurement.temperature.unwrap() - REF_TEMPERATURE).abs() < 0.01);
+        assert!((measurement.humidity.unwrap() - REF_HUMIDITY).abs() < 0.05);
+        assert!((measurement.pressure.unwrap() - REF_PRESSURE / 100.).abs() < 0.05);
+        assert!((measurement.resistance.unwrap() - REF_RESISTANCE_RANGE5).abs() < 1.);
+
+        assert_eq!(device.get(REG_CTRL_HUM), Oversampling::X2 as u8);
+        assert_eq!(device.get(REG_CTRL_MEAS), 0x55);
+        assert!(device.get(REG_RES_HEAT0).abs_diff(REF_RES_HEAT_300) <= 1);
+        assert_eq!(device.get(REG_GAS_WAIT0), 0xAB);
+        assert_eq!(device.get(REG_CTRL_GAS1), 1 << 4);
+    }
+
+    #[tokio::test]
+    async fn measure_err() {
+        let device = device();
+        let path = Emulator::new().device(ADDRESS, device.clone()).attach();
+        let mut sensor = Bme680::new(&path, ADDRESS, "bme".into())
+            .await
+            .unwrap()
+            .heater(None);
+
+        device.fail(Some(libc::EREMOTEIO));
+        assert!(matches!(
+            sensor.measure(CancellationToken::new()).await,
+            Err(SensorError::NotFound(_))
+        ));
+        assert!(matches!(
+            sensor.reinit().await,
+            Err(SensorError::NotFound(_))
+        ));
+
+        device.fail(None);
+        sensor.reinit().await.unwrap();
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(measurement.resistance, None);
+        assert_eq!(device.get(REG_CTRL_GAS1), 0);
+    }
+
+    #[tokio::test]
+    async fn init_err() {
+        let path = Emulator::new()
+            .device(ADDRESS, device().set(REG_CHIP_ID, &[0x60]))
+            .attach();
+        let err = Bme680::new(&path, ADDRESS, "bme".into())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(SensorError::WrongChipId {
+                expected: 0x61,
+                found: 0x60
+            })
+        ));
+
+        assert!(Bme680::new(&path, ADDRESS - 1, "bme".into()).await.is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use super::{
+    registry::{self, Model, Quantity, QuantityType, Reading, Setup},
+    Measure, Quantities, SensorError,
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, path::PathBuf, process::Stdio, time::Duration};
+use tokio::process::Command as Process;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+/// The settings of a command sensor.
+#[derive(Debug, Deserialize)]
+pub struct CommandConfig {
+    /// The program to run, looked up in `PATH` unless it's a path.
+    pub program: PathBuf,
+    /// The arguments passed to the program.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The time the program may run in milliseconds, it's killed afterwards.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// The quantities taken from the JSON output by name.
+    pub quantities: BTreeMap<String, QuantityConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuantityConfig {
+    /// The unit of the quantity.
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default, rename = "type")]
+    pub ty: QuantityType,
+    /// The JSON pointer to the value in the output, defaults to `/<name>`.
+    #[serde(default)]
+    pub pointer: Option<String>,
+}
+
+/// A program that prints a JSON object with the measured values to stdout, e.g. a vendor CLI or
+/// a script that queries a device over the network.
+pub struct Command {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+    quantities: &'static [Quantity],
+    /// The JSON pointers to the values of the quantities, in the same order.
+    pointers: Vec<String>,
+    label: String,
+}
+
+impl Command {
+    /// Runs the program and returns its stdout.
+    async fn run(&self) -> Result<Vec<u8>> {
+        let program = &self.program;
+        let output = Process::new(program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.timeout, output)
+            .await
+            .map_err(|_| SensorError::Timeout(format!("{program:?} to exit")))?
+            .with_context(|| format!("Failed to run {program:?}"))?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        if !output.status.success() {
+            if stderr.is_empty() {
+                bail!("{program:?} failed with {}", output.status);
+            }
+            bail!("{program:?} failed with {}: {stderr}", output.status);
+        }
+        if !stderr.is_empty() {
+            debug!("{program:?} wrote to stderr: {stderr}");
+        }
+
+        Ok(output.stdout)
+    }
+
+    fn parse(&self, stdout: &[u8], measure_time: i64) -> Result<Reading> {
+        let output: Value =
+            serde_json::from_slice(stdout).context("Failed to parse output as JSON")?;
+
+        let mut reading =
+            Reading::with_quantities::<Self>(measure_time, self.label.clone(), self.quantities);
+        for (quantity, pointer) in self.quantities.iter().zip(&self.pointers) {
+            match output.pointer(pointer).and_then(number) {
+                Some(value) => reading.set_quantity(quantity.name, Some(value)),
+                None => debug!("Output contains no {} at {pointer:?}", quantity.name),
+            }
+        }
+
+        Ok(reading)
+    }
+}
+
+/// Returns the value of a number, a boolean or a string that contains a number.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn pointer(name: &str, config: &QuantityConfig) -> String {
+    config.pointer.clone().unwrap_or_else(|| format!("/{name}"))
+}
+
+impl Model for Command {
+    const NAME: &'static str = "Command";
+    const QUANTITIES: &'static [Quantity] = &[];
+    type Config = CommandConfig;
+
+    fn quantities(config: &CommandConfig) -> Result<&'static [Quantity]> {
+        if config.quantities.is_empty() {
+            bail!("At least one quantity has to be configured");
+        }
+        for (name, quantity) in &config.quantities {
+            let pointer = pointer(name, quantity);
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                bail!("Pointer {pointer:?} of {name:?} must be empty or start with a slash");
+            }
+        }
+
+        Ok(registry::intern(
+            config
+                .quantities
+                .iter()
+                .map(|(name, q)| (name.clone(), q.unit.clone(), q.ty)),
+        ))
+    }
+
+    async fn init(setup: Setup<CommandConfig>) -> Result<Self> {
+        let config = setup.config;
+        let quantities = Self::quantities(&config)?;
+        let pointers = config
+            .quantities
+            .iter()
+            .map(|(name, q)| pointer(name, q))
+            .collect();
+
+        Ok(Self {
+            program: config.program,
+            args: config.args,
+            timeout: Duration::from_millis(config.timeout_ms),
+            quantities,
+            pointers,
+            label: setup.label,
+        })
+    }
+}
+
+impl Measure for Command {
+    type Measurement = Reading;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        let stdout = tokio::select! {
+            _ = cancel_token.cancelled() => return Err(SensorError::Cancelled),
+            stdout = self.run() => stdout?,
+        };
+        let measure_time = Utc::now().timestamp_millis();
+
+        Ok(self.parse(&stdout, measure_time)?)
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        // every measurement runs the program anew
+        Ok(())
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::I2cAddress;
+    use serde_json::json;
+
+    async fn command(script: &str, quantities: Value) -> Command {
+        let config = serde_json::from_value(json!({
+            "program": "sh",
+            "args": ["-c", script],
+            "timeout_ms": 500,
+            "quantities": quantities,
+        }))
+        .unwrap();
+
+        Command::init(Setup {
+            label: "plug".into(),
+            address: I2cAddress::new(0),
+            i2c_path: PathBuf::new(),
+            gpio_path: PathBuf::new(),
+            config,
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn measure_ok() {
+        let output = r#"{"StatusSNS": {"ENERGY": {"Power": 41.3, "Voltage": "229"}}, "On": true}"#;
+        let mut sensor = command(
+            &format!("echo '{output}'; echo 'warming up' >&2"),
+            json!({
+                "power": { "unit": "W", "pointer": "/StatusSNS/ENERGY/Power" },
+                "voltage": { "unit": "V", "type": "integer", "pointer": "/StatusSNS/ENERGY/Voltage" },
+                "on": { "type": "integer", "pointer": "/On" },
+                "current": { "unit": "A" },
+            }),
+        )
+        .await;
+
+        let reading = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(reading.model, "Command");
+        assert_eq!(reading.quantity("power"), Some(41.3));
+        assert_eq!(reading.quantity("voltage"), Some(229.));
+        assert_eq!(reading.quantity("on"), Some(1.));
+        assert_eq!(reading.quantity("current"), None);
+        let units = reading
+            .values()
+            .map(|(q, _, _)| (q.name, q.unit))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            units,
+            [
+                ("current", "A"),
+                ("on", ""),
+                ("power", "W"),
+                ("voltage", "V")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn measure_err() {
+        let quantities = json!({ "co2": { "unit": "ppm" } });
+
+        let mut sensor = command("echo 'device not found' >&2; exit 2", quantities.clone()).await;
+        let err = sensor.measure(CancellationToken::new()).await.unwrap_err();
+        assert!(format!("{err:#}").ends_with("exit status: 2: device not found"));
+
+        let mut sensor = command("echo 'co2=400'", quantities.clone()).await;
+        let err = sensor.measure(CancellationToken::new()).await.unwrap_err();
+        assert!(format!("{err:#}").starts_with("Failed to parse output as JSON"));
+
+        // the program is killed when it doesn't exit in time
+        let mut sensor = command("sleep 5", quantities).await;
+        let err = sensor.measure(CancellationToken::new()).await.unwrap_err();
+        assert!(matches!(err, SensorError::Timeout(_)));
+    }
+
+    #[test]
+    fn quantities_err() {
+        let config = |quantities| {
+            serde_json::from_value::<CommandConfig>(json!({
+                "program": "true",
+                "quantities": quantities,
+            }))
+            .unwrap()
+        };
+
+        assert!(Command::quantities(&config(json!({}))).is_err());
+        assert!(Command::quantities(&config(json!({ "co2": { "pointer": "co2" } }))).is_err());
+        let quantities = Command::quantities(&config(json!({ "co2": { "unit": "ppm" } })));
+        assert_eq!(quantities.unwrap(), [Quantity::float("co2", "ppm")]);
+    }
+}
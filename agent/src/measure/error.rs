@@ -0,0 +1,185 @@
+use super::i2c::I2cError;
+
+/// Why a sensor failed to measure or to initialize.
+#[derive(Debug, thiserror::Error)]
+pub enum SensorError {
+    /// The sensor does not acknowledge its address, e.g. because it's not connected.
+    #[error("Sensor not found: {0}")]
+    NotFound(I2cError),
+
+    /// Another device responds at the address of the sensor.
+    #[error("Unexpected chip ID {found:#04x}, expected {expected:#04x}")]
+    WrongChipId { expected: u32, found: u32 },
+
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+
+    #[error("Checksum of the sensor data does not match")]
+    Crc,
+
+    /// The sensor reports a value it cannot measure, e.g. because a probe is missing.
+    #[error("{quantity} of {value} is out of range")]
+    OutOfRange { quantity: &'static str, value: f64 },
+
+    #[error("Measurement cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    I2c(I2cError),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+
+    /// An error of another kind with the context that was added to it, e.g. by a driver.
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<SensorError>,
+    },
+}
+
+/// How a failed sensor is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The failure is likely transient, the measurement is repeated.
+    Retry,
+    /// The sensor lost its configuration or connection and is initialized again before it
+    /// measures the next time.
+    Reinit,
+    /// Neither repeating the measurement nor initializing the sensor would help right now.
+    GiveUp,
+}
+
+impl SensorError {
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            SensorError::Context { source, .. } => source.recovery(),
+            SensorError::Crc | SensorError::I2c(_) | SensorError::Other(_) => Recovery::Retry,
+            // a sensor that doesn't finish a measurement is likely stuck in a bad state
+            SensorError::NotFound(_) | SensorError::Timeout(_) => Recovery::Reinit,
+            SensorError::WrongChipId { .. }
+            | SensorError::OutOfRange { .. }
+            | SensorError::Cancelled => Recovery::GiveUp,
+        }
+    }
+
+    /// Returns the error without the context that was added to it.
+    pub fn kind(&self) -> &SensorError {
+        match self {
+            SensorError::Context { source, .. } => source.kind(),
+            err => err,
+        }
+    }
+}
+
+impl From<I2cError> for SensorError {
+    fn from(err: I2cError) -> Self {
+        if err.is_nack() {
+            SensorError::NotFound(err)
+        } else if let I2cError::Pec { .. } = err {
+            SensorError::Crc
+        } else {
+            SensorError::I2c(err)
+        }
+    }
+}
+
+impl From<anyhow::Error> for SensorError {
+    /// Keeps the kind of errors that drivers pass through helpers returning `anyhow::Result`,
+    /// along with the context they added.
+    fn from(err: anyhow::Error) -> Self {
+        let context = err
+            .chain()
+            .take_while(|e| !e.is::<SensorError>() && !e.is::<I2cError>())
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(": ");
+
+        let kind = match err.downcast::<SensorError>() {
+            Ok(err) => err,
+            Err(err) => match err.downcast::<I2cError>() {
+                Ok(err) => err.into(),
+                Err(err) => return SensorError::Other(err),
+            },
+        };
+
+        if context.is_empty() {
+            kind
+        } else {
+            SensorError::Context {
+                context,
+                source: Box::new(kind),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use nix::libc;
+    use std::io;
+
+    #[test]
+    fn from_ok() {
+        let nack = || I2cError::Read(io::Error::from_raw_os_error(libc::EREMOTEIO));
+        assert!(matches!(
+            SensorError::from(nack()),
+            SensorError::NotFound(_)
+        ));
+        assert!(matches!(
+            SensorError::from(I2cError::Write(io::Error::from_raw_os_error(libc::EAGAIN))),
+            SensorError::I2c(_)
+        ));
+        assert!(matches!(
+            SensorError::from(I2cError::Pec {
+                expected: 0x12,
+                found: 0x34
+            }),
+            SensorError::Crc
+        ));
+
+        let err = Err::<(), _>(nack())
+            .context("Failed to read data")
+            .unwrap_err();
+        let err = SensorError::from(err);
+        assert!(matches!(err.kind(), SensorError::NotFound(_)));
+        assert_eq!(err.recovery(), Recovery::Reinit);
+        assert!(format!("{:#}", anyhow::Error::from(err)).starts_with("Failed to read data: "));
+        let err = anyhow::Error::from(SensorError::Cancelled).context("Failed to identify");
+        assert!(matches!(
+            SensorError::from(err).kind(),
+            SensorError::Cancelled
+        ));
+        assert!(matches!(
+            SensorError::from(anyhow::Error::from(SensorError::Crc)),
+            SensorError::Crc
+        ));
+        let err = anyhow::anyhow!("Syntax error");
+        assert!(matches!(SensorError::from(err), SensorError::Other(_)));
+    }
+
+    #[test]
+    fn recovery_ok() {
+        assert_eq!(SensorError::Crc.recovery(), Recovery::Retry);
+        assert_eq!(
+            SensorError::Timeout("new data".into()).recovery(),
+            Recovery::Reinit
+        );
+        assert_eq!(
+            SensorError::from(I2cError::Read(io::Error::from_raw_os_error(libc::ENXIO))).recovery(),
+            Recovery::Reinit
+        );
+        assert_eq!(
+            SensorError::WrongChipId {
+                expected: 0x61,
+                found: 0x60
+            }
+            .recovery(),
+            Recovery::GiveUp
+        );
+        assert_eq!(SensorError::Cancelled.recovery(), Recovery::GiveUp);
+    }
+}
@@ -0,0 +1,163 @@
+use super::{
+    i2c::{I2cAddress, I2C},
+    SensorError,
+};
+use anyhow::{bail, Context, Result};
+use std::{path::Path, time::Duration};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+const STATUS_SUCCESS: u8 = 1;
+const STATUS_SYNTAX_ERROR: u8 = 2;
+const STATUS_PENDING: u8 = 254;
+const STATUS_NO_DATA: u8 = 255;
+
+const RESPONSE_SIZE: usize = 41;
+const PENDING_RETRIES: u8 = 10;
+const PENDING_WAIT_DURATION: Duration = Duration::from_millis(100);
+
+pub(super) const DELAY_SHORT: Duration = Duration::from_millis(300);
+
+/// The source of the water temperature used to compensate readings of EZO circuits.
+#[derive(Debug, Clone)]
+pub enum TemperatureSource {
+    /// A fixed temperature in degree celsius.
+    Fixed(f64),
+    /// The latest temperature measured by another sensor in degree celsius.
+    Measured(watch::Receiver<Option<f64>>),
+}
+
+impl TemperatureSource {
+    fn temperature(&self) -> Option<f64> {
+        match self {
+            TemperatureSource::Fixed(temperature) => Some(*temperature),
+            TemperatureSource::Measured(receiver) => *receiver.borrow(),
+        }
+    }
+}
+
+/// Atlas Scientific EZO circuit in I2C mode.
+///
+/// EZO circuits are controlled with ASCII commands. After a command was sent, the circuit needs
+/// some processing time before the response can be read. Every response starts with a status byte
+/// followed by a null terminated ASCII string.
+pub(super) struct Ezo {
+    i2c: I2C,
+}
+
+impl Ezo {
+    pub async fn new(i2c_path: impl AsRef<Path>, address: I2cAddress) -> Result<Self> {
+        let i2c = I2C::new(i2c_path, address).await?;
+
+        Ok(Self { i2c })
+    }
+
+    /// Makes sure that the circuit is of the expected device type, e.g. "pH" or "EC".
+    pub async fn identify(&mut self, device_type: &str) -> Result<()> {
+        // the response looks like "?I,pH,1.98"
+        let response = self
+            .command("i", DELAY_SHORT, CancellationToken::new())
+            .await
+            .context("Failed to get device information")?;
+
+        match response.split(',').nth(1) {
+            Some(t) if t.eq_ignore_ascii_case(device_type) => Ok(()),
+            _ => bail!("Failed to identify EZO-{device_type} circuit, got {response:?}"),
+        }
+    }
+
+    /// Sets the temperature compensation from the given source, if it provides a temperature.
+    pub async fn compensate(&mut self, source: Option<&TemperatureSource>) -> Result<()> {
+        if let Some(temperature) = source.and_then(TemperatureSource::temperature) {
+            self.command(
+                &format!("T,{temperature:.2}"),
+                DELAY_SHORT,
+                CancellationToken::new(),
+            )
+            .await
+            .context("Failed to set temperature compensation")?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a single value and parses the first field of the response.
+    pub async fn read_value(
+        &mut self,
+        delay: Duration,
+        cancel_token: CancellationToken,
+    ) -> Result<f64> {
+        let response = self.command("R", delay, cancel_token).await?;
+        let value = response
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .with_context(|| format!("Failed to parse reading {response:?}"))?;
+
+        Ok(value)
+    }
+
+    /// Sends a command and returns the response once it's available.
+    pub async fn command(
+        &mut self,
+        command: &str,
+        delay: Duration,
+        cancel_token: CancellationToken,
+    ) -> Result<String> {
+        self.i2c.write_bytes(command.as_bytes()).await?;
+
+        tokio::select! {
+            _ = cancel_token.cancelled() => {
+                return Err(SensorError::Cancelled.into());
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+
+        let mut buf = [0; RESPONSE_SIZE];
+        for _ in 0..PENDING_RETRIES {
+            self.i2c.read_bytes(&mut buf).await?;
+
+            match buf[0] {
+                STATUS_SUCCESS => return Self::parse_response(&buf[1..]),
+                STATUS_SYNTAX_ERROR => bail!("Syntax error in EZO command {command:?}"),
+                STATUS_NO_DATA => bail!("No data to send for EZO command {command:?}"),
+                STATUS_PENDING => {
+                    tokio::select! {
+                        _ = cancel_token.cancelled() => {
+                            return Err(SensorError::Cancelled.into());
+                        }
+                        _ = tokio::time::sleep(PENDING_WAIT_DURATION) => {}
+                    }
+                }
+                status => bail!("Unknown EZO response status {status}"),
+            }
+        }
+
+        Err(SensorError::Timeout(format!("EZO command {command:?}")).into())
+    }
+
+    fn parse_response(data: &[u8]) -> Result<String> {
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let response = std::str::from_utf8(&data[..end])
+            .context("Failed to parse EZO response as ASCII")?
+            .trim()
+            .to_owned();
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_response_ok() {
+        let mut data = [0; RESPONSE_SIZE - 1];
+        data[..10].copy_from_slice(b"?I,pH,1.98");
+        assert_eq!(Ezo::parse_response(&data).unwrap(), "?I,pH,1.98");
+        assert_eq!(Ezo::parse_response(b"7.023").unwrap(), "7.023");
+        assert_eq!(Ezo::parse_response(&[0; 4]).unwrap(), "");
+    }
+}
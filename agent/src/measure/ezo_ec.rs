@@ -0,0 +1,129 @@
+use super::{
+    ezo::{Ezo, TemperatureSource, DELAY_SHORT},
+    I2cAddress, Measure, NutrientMeasurement, SensorError,
+};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::{path::Path, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+const DEVICE_TYPE: &str = "EC";
+const DELAY_READ: Duration = Duration::from_millis(600);
+const DELAY_CALIBRATE: Duration = Duration::from_millis(600);
+
+/// Atlas Scientific EZO-EC
+pub struct EzoEc {
+    ezo: Ezo,
+    label: String,
+    temperature_source: Option<TemperatureSource>,
+}
+
+impl EzoEc {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut ezo = Ezo::new(i2c_path, address).await?;
+        Self::init(&mut ezo)
+            .await
+            .with_context(|| format!("Failed to initialize EZO-EC at address {address}"))?;
+
+        Ok(Self {
+            ezo,
+            label,
+            temperature_source: None,
+        })
+    }
+
+    async fn init(ezo: &mut Ezo) -> Result<()> {
+        ezo.identify(DEVICE_TYPE).await?;
+
+        // only output the conductivity, which makes the response independent of the
+        // output configuration stored on the circuit
+        for command in ["O,EC,1", "O,TDS,0", "O,S,0", "O,SG,0"] {
+            ezo.command(command, DELAY_SHORT, CancellationToken::new())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn temperature_source(mut self, source: TemperatureSource) -> Self {
+        self.temperature_source = Some(source);
+        self
+    }
+
+    /// Calibrates the probe in dry condition, must be done before any other calibration.
+    pub async fn calibrate_dry(&mut self) -> Result<()> {
+        self.calibrate("Cal,dry").await
+    }
+
+    /// Calibrates a single point with the given conductivity in µS/cm.
+    pub async fn calibrate_single(&mut self, conductivity: f64) -> Result<()> {
+        self.calibrate(&format!("Cal,{conductivity:.0}")).await
+    }
+
+    pub async fn calibrate_low(&mut self, conductivity: f64) -> Result<()> {
+        self.calibrate(&format!("Cal,low,{conductivity:.0}")).await
+    }
+
+    pub async fn calibrate_high(&mut self, conductivity: f64) -> Result<()> {
+        self.calibrate(&format!("Cal,high,{conductivity:.0}")).await
+    }
+
+    pub async fn clear_calibration(&mut self) -> Result<()> {
+        self.calibrate("Cal,clear").await
+    }
+
+    /// Returns the number of calibrated points.
+    pub async fn calibration_points(&mut self) -> Result<u8> {
+        // the response looks like "?CAL,2"
+        let response = self
+            .ezo
+            .command("Cal,?", DELAY_SHORT, CancellationToken::new())
+            .await?;
+        response
+            .strip_prefix("?CAL,")
+            .and_then(|n| n.parse().ok())
+            .with_context(|| format!("Failed to parse calibration status {response:?}"))
+    }
+
+    async fn calibrate(&mut self, command: &str) -> Result<()> {
+        self.ezo
+            .compensate(self.temperature_source.as_ref())
+            .await?;
+        self.ezo
+            .command(command, DELAY_CALIBRATE, CancellationToken::new())
+            .await
+            .with_context(|| format!("Failed to calibrate {:?} EC sensor", self.label))?;
+
+        Ok(())
+    }
+}
+
+impl Measure for EzoEc {
+    type Measurement = NutrientMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        self.ezo
+            .compensate(self.temperature_source.as_ref())
+            .await?;
+        let conductivity = self.ezo.read_value(DELAY_READ, cancel_token).await?;
+        let measure_time = Utc::now().timestamp_millis();
+
+        Ok(NutrientMeasurement::new(measure_time, self.label.clone()).conductivity(conductivity))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        Ok(Self::init(&mut self.ezo).await?)
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
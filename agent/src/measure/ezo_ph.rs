@@ -0,0 +1,112 @@
+use super::{
+    ezo::{Ezo, TemperatureSource, DELAY_SHORT},
+    I2cAddress, Measure, NutrientMeasurement, SensorError,
+};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::{path::Path, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+const DEVICE_TYPE: &str = "pH";
+const DELAY_READ: Duration = Duration::from_millis(900);
+const DELAY_CALIBRATE: Duration = Duration::from_millis(900);
+
+/// Atlas Scientific EZO-pH
+pub struct EzoPh {
+    ezo: Ezo,
+    label: String,
+    temperature_source: Option<TemperatureSource>,
+}
+
+impl EzoPh {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut ezo = Ezo::new(i2c_path, address).await?;
+        ezo.identify(DEVICE_TYPE)
+            .await
+            .with_context(|| format!("Failed to initialize EZO-pH at address {address}"))?;
+
+        Ok(Self {
+            ezo,
+            label,
+            temperature_source: None,
+        })
+    }
+
+    pub fn temperature_source(mut self, source: TemperatureSource) -> Self {
+        self.temperature_source = Some(source);
+        self
+    }
+
+    /// Calibrates the mid point, must be done before calibrating the low or high point.
+    /// Clears any other calibration.
+    pub async fn calibrate_mid(&mut self, ph: f64) -> Result<()> {
+        self.calibrate(&format!("Cal,mid,{ph:.2}")).await
+    }
+
+    pub async fn calibrate_low(&mut self, ph: f64) -> Result<()> {
+        self.calibrate(&format!("Cal,low,{ph:.2}")).await
+    }
+
+    pub async fn calibrate_high(&mut self, ph: f64) -> Result<()> {
+        self.calibrate(&format!("Cal,high,{ph:.2}")).await
+    }
+
+    pub async fn clear_calibration(&mut self) -> Result<()> {
+        self.calibrate("Cal,clear").await
+    }
+
+    /// Returns the number of calibrated points.
+    pub async fn calibration_points(&mut self) -> Result<u8> {
+        // the response looks like "?CAL,2"
+        let response = self
+            .ezo
+            .command("Cal,?", DELAY_SHORT, CancellationToken::new())
+            .await?;
+        response
+            .strip_prefix("?CAL,")
+            .and_then(|n| n.parse().ok())
+            .with_context(|| format!("Failed to parse calibration status {response:?}"))
+    }
+
+    async fn calibrate(&mut self, command: &str) -> Result<()> {
+        self.ezo
+            .compensate(self.temperature_source.as_ref())
+            .await?;
+        self.ezo
+            .command(command, DELAY_CALIBRATE, CancellationToken::new())
+            .await
+            .with_context(|| format!("Failed to calibrate {:?} pH sensor", self.label))?;
+
+        Ok(())
+    }
+}
+
+impl Measure for EzoPh {
+    type Measurement = NutrientMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        self.ezo
+            .compensate(self.temperature_source.as_ref())
+            .await?;
+        let ph = self.ezo.read_value(DELAY_READ, cancel_token).await?;
+        let measure_time = Utc::now().timestamp_millis();
+
+        Ok(NutrientMeasurement::new(measure_time, self.label.clone()).ph(ph))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        Ok(self.ezo.identify(DEVICE_TYPE).await?)
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
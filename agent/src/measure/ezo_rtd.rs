@@ -0,0 +1,83 @@
+use super::{
+    ezo::{Ezo, TemperatureSource},
+    I2cAddress, Measure, NutrientMeasurement, SensorError,
+};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::{path::Path, sync::Arc, time::Duration};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+const DEVICE_TYPE: &str = "RTD";
+const DELAY_READ: Duration = Duration::from_millis(600);
+const NO_PROBE: f64 = -1023.;
+
+/// Atlas Scientific EZO-RTD
+pub struct EzoRtd {
+    ezo: Ezo,
+    label: String,
+    sender: Arc<watch::Sender<Option<f64>>>,
+}
+
+impl EzoRtd {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut ezo = Ezo::new(i2c_path, address).await?;
+        ezo.identify(DEVICE_TYPE)
+            .await
+            .with_context(|| format!("Failed to initialize EZO-RTD at address {address}"))?;
+        let (sender, _) = watch::channel(None);
+
+        Ok(Self {
+            ezo,
+            label,
+            sender: Arc::new(sender),
+        })
+    }
+
+    /// Publishes the measured temperatures to the given sender, so that temperature sources can
+    /// be created before the circuit responds.
+    pub fn temperature_sender(mut self, sender: Arc<watch::Sender<Option<f64>>>) -> Self {
+        self.sender = sender;
+        self
+    }
+
+    /// Returns a temperature source that always provides the latest measured temperature.
+    pub fn temperature_source(&self) -> TemperatureSource {
+        TemperatureSource::Measured(self.sender.subscribe())
+    }
+}
+
+impl Measure for EzoRtd {
+    type Measurement = NutrientMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        let temperature = self.ezo.read_value(DELAY_READ, cancel_token).await?;
+        // the circuit reports the lowest temperature if no probe is connected
+        if temperature <= NO_PROBE {
+            return Err(SensorError::OutOfRange {
+                quantity: "temperature",
+                value: temperature,
+            });
+        }
+        let measure_time = Utc::now().timestamp_millis();
+        self.sender.send_replace(Some(temperature));
+
+        Ok(NutrientMeasurement::new(measure_time, self.label.clone()).temperature(temperature))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        Ok(self.ezo.identify(DEVICE_TYPE).await?)
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
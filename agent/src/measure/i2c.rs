@@ -1,93 +1,779 @@
 use nix::libc;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
 use std::os::fd::AsRawFd;
-use std::path::Path;
-use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use tokio::fs::OpenOptions;
+use tokio::sync::Mutex;
+use tokio::task::spawn_blocking;
+use tracing::warn;
 
-const REQ_SLAVE: libc::c_ulong = 0x0706;
+const REQ_FUNCS: libc::c_ulong = 0x0705;
+const REQ_RDWR: libc::c_ulong = 0x0707;
+const FUNC_I2C: libc::c_ulong = 0x0000_0001;
+const MSG_READ: u16 = 0x0001;
+const MUX_CHANNELS: u8 = 8;
+
+#[cfg(test)]
+pub mod emulator;
+
+/// The I2C buses in use, by path of the adapter.
+static BUSES: LazyLock<std::sync::Mutex<HashMap<PathBuf, Arc<I2cBus>>>> =
+    LazyLock::new(Default::default);
 
 #[derive(Debug, thiserror::Error)]
 pub enum I2cError {
     #[error("Failed to open I2C bus: {err}")]
-    Open { err: tokio::io::Error },
+    Open { err: io::Error },
 
-    #[error("Failed to set I2C slave address \"{0:02x}\" via ioctl")]
-    SlaveAddr(u8),
+    #[error("I2C adapter does not support plain I2C transactions")]
+    Unsupported,
 
     #[error("Failed to write to I2C: {0}")]
-    Write(tokio::io::Error),
+    Write(io::Error),
 
     #[error("Failed to read from I2C: {0}")]
-    Read(tokio::io::Error),
+    Read(io::Error),
+
+    #[error("Invalid I2C multiplexer channel {0}")]
+    MuxChannel(u8),
+
+    #[error("Failed to select I2C multiplexer channel {channel}: {err}")]
+    SelectChannel { channel: u8, err: io::Error },
+
+    #[error("SMBus packet error code {found:#04x} does not match, expected {expected:#04x}")]
+    Pec { expected: u8, found: u8 },
 }
 
-pub struct I2C {
-    dev: File,
+impl I2cError {
+    /// Returns whether the addressed device did not acknowledge the transaction, e.g. because
+    /// it's not connected.
+    pub fn is_nack(&self) -> bool {
+        match self {
+            I2cError::Read(err) | I2cError::Write(err) => {
+                matches!(err.raw_os_error(), Some(libc::ENXIO | libc::EREMOTEIO))
+            }
+            _ => false,
+        }
+    }
 }
 
-impl I2C {
-    pub async fn new(i2c_path: impl AsRef<Path>, slave_address: u8) -> Result<Self, I2cError> {
+/// The address of a device on an I2C bus, optionally behind a channel of a TCA9548A
+/// multiplexer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cAddress {
+    address: u8,
+    mux: Option<(u8, u8)>,
+}
+
+impl I2cAddress {
+    pub fn new(address: u8) -> Self {
+        Self { address, mux: None }
+    }
+
+    /// Places the device behind a channel of the multiplexer at the given address.
+    pub fn mux(mut self, mux_address: u8, channel: u8) -> Self {
+        self.mux = Some((mux_address, channel));
+        self
+    }
+}
+
+impl From<u8> for I2cAddress {
+    fn from(address: u8) -> Self {
+        Self::new(address)
+    }
+}
+
+impl fmt::Display for I2cAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02x}", self.address)?;
+        if let Some((mux_address, channel)) = self.mux {
+            write!(f, " (channel {channel} of mux 0x{mux_address:02x})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes the SMBus packet error code, a CRC-8 with polynomial 0x07, of the bytes of a
+/// transaction including the address bytes.
+pub fn smbus_pec(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x07,
+        })
+    })
+}
+
+/// A single message of a combined transaction.
+pub enum Message {
+    Write(Vec<u8>),
+    Read(Vec<u8>),
+}
+
+/// struct i2c_msg from linux/i2c.h
+#[repr(C)]
+struct RawMessage {
+    addr: u16,
+    flags: u16,
+    len: u16,
+    buf: *mut u8,
+}
+
+/// struct i2c_rdwr_ioctl_data from linux/i2c-dev.h
+#[repr(C)]
+struct RdwrData {
+    msgs: *mut RawMessage,
+    nmsgs: u32,
+}
+
+/// The hardware of an I2C bus. Other implementations than the adapters of the kernel are
+/// attached to a path with [`I2cBus::attach`], e.g. emulated buses in tests.
+pub trait Adapter: Send + Sync + 'static {
+    /// Transfers all messages in one transaction, separated by repeated starts.
+    fn transfer(&self, address: u8, messages: &mut [Message]) -> io::Result<()>;
+}
+
+/// An I2C adapter of the kernel, e.g. `/dev/i2c-1`.
+struct DevAdapter(File);
+
+impl Adapter for DevAdapter {
+    fn transfer(&self, address: u8, messages: &mut [Message]) -> io::Result<()> {
+        transfer(&self.0, address, messages)
+    }
+}
+
+fn transfer(dev: &File, address: u8, messages: &mut [Message]) -> io::Result<()> {
+    let mut raw = messages
+        .iter_mut()
+        .map(|message| {
+            let (flags, buf) = match message {
+                Message::Write(buf) => (0, buf),
+                Message::Read(buf) => (MSG_READ, buf),
+            };
+            RawMessage {
+                addr: u16::from(address),
+                flags,
+                len: buf.len() as u16,
+                buf: buf.as_mut_ptr(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut data = RdwrData {
+        msgs: raw.as_mut_ptr(),
+        nmsgs: raw.len() as u32,
+    };
+
+    // the buffers outlive the call and their lengths match the messages
+    if unsafe { libc::ioctl(dev.as_raw_fd(), REQ_RDWR, &mut data) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Transaction and error counters of an I2C bus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BusStats {
+    pub transactions: u64,
+    pub errors: u64,
+    /// Transactions that were not acknowledged by the addressed device.
+    pub nacks: u64,
+    pub timeouts: u64,
+    pub arbitration_lost: u64,
+}
+
+impl BusStats {
+    fn record(&mut self, res: &io::Result<()>) {
+        self.transactions += 1;
+        if let Err(err) = res {
+            self.errors += 1;
+            match err.raw_os_error() {
+                Some(libc::ENXIO | libc::EREMOTEIO) => self.nacks += 1,
+                Some(libc::ETIMEDOUT) => self.timeouts += 1,
+                Some(libc::EAGAIN) => self.arbitration_lost += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Display for BusStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} transactions, {} errors ({} nacks, {} timeouts, {} arbitration lost)",
+            self.transactions, self.errors, self.nacks, self.timeouts, self.arbitration_lost
+        )
+    }
+}
+
+/// The counters of an I2C bus since the agent started, as stored periodically.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct BusStatus {
+    /// The path of the adapter.
+    pub path: String,
+    pub transactions: i64,
+    pub errors: i64,
+    pub nacks: i64,
+    pub timeouts: i64,
+    pub arbitration_lost: i64,
+    /// The number of milliseconds since unix epoch at which the counters were taken.
+    pub update_time: i64,
+}
+
+impl BusStatus {
+    pub fn new(path: &Path, stats: BusStats, update_time: i64) -> Self {
+        let count = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+        Self {
+            path: path.to_string_lossy().into_owned(),
+            transactions: count(stats.transactions),
+            errors: count(stats.errors),
+            nacks: count(stats.nacks),
+            timeouts: count(stats.timeouts),
+            arbitration_lost: count(stats.arbitration_lost),
+            update_time,
+        }
+    }
+}
+
+struct BusState {
+    adapter: Arc<dyn Adapter>,
+    /// The selected channel of each multiplexer on the bus.
+    selected: HashMap<u8, u8>,
+}
+
+/// An I2C adapter that is shared by all devices on it.
+///
+/// Transactions of different devices are serialized, so that neither multiplexer channels nor
+/// combined read-after-write sequences can be interleaved.
+pub struct I2cBus {
+    path: PathBuf,
+    state: Mutex<BusState>,
+    stats: std::sync::Mutex<BusStats>,
+}
+
+impl I2cBus {
+    fn new(path: &Path, adapter: Arc<dyn Adapter>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(BusState {
+                adapter,
+                selected: HashMap::new(),
+            }),
+            stats: Default::default(),
+        }
+    }
+
+    /// Returns the bus of the adapter at the given path, opening it on first use.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Arc<Self>, I2cError> {
+        let path = path.as_ref();
+        if let Some(bus) = BUSES.lock().unwrap().get(path) {
+            return Ok(bus.clone());
+        }
+
         let dev = OpenOptions::new()
             .read(true)
             .write(true)
-            .open(i2c_path)
+            .open(path)
             .await
-            .map_err(|err| I2cError::Open { err })?;
-
-        if unsafe {
-            libc::ioctl(
-                dev.as_raw_fd(),
-                REQ_SLAVE,
-                libc::c_ulong::from(slave_address),
-            )
-        } == -1
+            .map_err(|err| I2cError::Open { err })?
+            .into_std()
+            .await;
+
+        let mut funcs: libc::c_ulong = 0;
+        if unsafe { libc::ioctl(dev.as_raw_fd(), REQ_FUNCS, &mut funcs) } == -1
+            || funcs & FUNC_I2C == 0
         {
-            return Err(I2cError::SlaveAddr(slave_address));
+            return Err(I2cError::Unsupported);
+        }
+
+        let bus = BUSES
+            .lock()
+            .unwrap()
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Arc::new(Self::new(path, Arc::new(DevAdapter(dev)))))
+            .clone();
+
+        Ok(bus)
+    }
+
+    /// Registers an adapter under a path, devices that are created with the path use it instead
+    /// of opening the path.
+    pub fn attach(path: impl AsRef<Path>, adapter: Arc<dyn Adapter>) -> Arc<Self> {
+        let path = path.as_ref();
+        let bus = Arc::new(Self::new(path, adapter));
+        BUSES
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), bus.clone());
+
+        bus
+    }
+
+    /// Returns all buses that have been opened.
+    pub fn all() -> Vec<Arc<Self>> {
+        BUSES.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn stats(&self) -> BusStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Locks the bus for a sequence of transactions.
+    async fn lock(&self) -> BusGuard<'_> {
+        BusGuard {
+            bus: self,
+            state: self.state.lock().await,
+        }
+    }
+}
+
+struct BusGuard<'a> {
+    bus: &'a I2cBus,
+    state: tokio::sync::MutexGuard<'a, BusState>,
+}
+
+impl BusGuard<'_> {
+    async fn raw_transfer(
+        &mut self,
+        address: u8,
+        mut messages: Vec<Message>,
+    ) -> io::Result<Vec<Message>> {
+        let adapter = self.state.adapter.clone();
+        let (res, messages) = spawn_blocking(move || {
+            let res = adapter.transfer(address, &mut messages);
+            (res, messages)
+        })
+        .await
+        .map_err(io::Error::other)?;
+
+        let stats = {
+            let mut stats = self.bus.stats.lock().unwrap();
+            stats.record(&res);
+            *stats
+        };
+        if let Err(err) = &res {
+            warn!(
+                "I2C transaction with 0x{address:02x} on {:?} failed: {err}, {stats}",
+                self.bus.path
+            );
+        }
+
+        res.map(|_| messages)
+    }
+
+    /// Disables the channels of all multiplexers on the bus except the given one, so that
+    /// devices with the same address don't collide.
+    async fn deselect_others(&mut self, mux_address: Option<u8>) -> Result<(), I2cError> {
+        let others = self
+            .state
+            .selected
+            .keys()
+            .copied()
+            .filter(|a| Some(*a) != mux_address)
+            .collect::<Vec<_>>();
+        for other in others {
+            // forget the selection before writing, the state of the mux is unknown on failure
+            let other_channel = self.state.selected.remove(&other).unwrap_or_default();
+            self.raw_transfer(other, vec![Message::Write(vec![0])])
+                .await
+                .map_err(|err| I2cError::SelectChannel {
+                    channel: other_channel,
+                    err,
+                })?;
         }
 
-        Ok(Self { dev })
+        Ok(())
+    }
+
+    /// Selects the channel of the multiplexer and disables the channels of all other
+    /// multiplexers on the bus.
+    async fn select(&mut self, mux_address: u8, channel: u8) -> Result<(), I2cError> {
+        let mask = 1u8
+            .checked_shl(u32::from(channel))
+            .ok_or_else(|| I2cError::SelectChannel {
+                channel,
+                err: io::Error::from(io::ErrorKind::InvalidInput),
+            })?;
+        self.deselect_others(Some(mux_address)).await?;
+
+        if self.state.selected.get(&mux_address) != Some(&channel) {
+            self.state.selected.remove(&mux_address);
+            self.raw_transfer(mux_address, vec![Message::Write(vec![mask])])
+                .await
+                .map_err(|err| I2cError::SelectChannel { channel, err })?;
+            self.state.selected.insert(mux_address, channel);
+        }
+
+        Ok(())
+    }
+
+    async fn transfer(
+        &mut self,
+        address: I2cAddress,
+        messages: Vec<Message>,
+    ) -> Result<Vec<Message>, I2cError> {
+        match address.mux {
+            Some((mux_address, channel)) => self.select(mux_address, channel).await?,
+            // a device on the bus itself must not collide with devices behind a mux
+            None => self.deselect_others(None).await?,
+        }
+
+        let reads = messages.iter().any(|m| matches!(m, Message::Read(_)));
+        self.raw_transfer(address.address, messages)
+            .await
+            .map_err(|err| match reads {
+                true => I2cError::Read(err),
+                false => I2cError::Write(err),
+            })
+    }
+
+    async fn write(&mut self, address: I2cAddress, bytes: Vec<u8>) -> Result<(), I2cError> {
+        self.transfer(address, vec![Message::Write(bytes)])
+            .await
+            .map(|_| ())
+    }
+
+    /// Writes the bytes and reads into the buffer after a repeated start.
+    async fn write_read(
+        &mut self,
+        address: I2cAddress,
+        bytes: Vec<u8>,
+        buf: &mut [u8],
+    ) -> Result<(), I2cError> {
+        let mut messages = Vec::with_capacity(2);
+        if !bytes.is_empty() {
+            messages.push(Message::Write(bytes));
+        }
+        messages.push(Message::Read(vec![0; buf.len()]));
+
+        if let Some(Message::Read(data)) = self.transfer(address, messages).await?.pop() {
+            buf.copy_from_slice(&data);
+        }
+
+        Ok(())
+    }
+}
+
+/// A device on a shared I2C bus.
+pub struct I2C {
+    bus: Arc<I2cBus>,
+    address: I2cAddress,
+}
+
+impl I2C {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+    ) -> Result<Self, I2cError> {
+        let bus = I2cBus::open(i2c_path).await?;
+        Self::with_bus(bus, address)
+    }
+
+    /// Creates a device on a bus that is already open, e.g. one with a custom adapter.
+    pub fn with_bus(bus: Arc<I2cBus>, address: impl Into<I2cAddress>) -> Result<Self, I2cError> {
+        let address = address.into();
+        if let Some((_, channel)) = address.mux {
+            if channel >= MUX_CHANNELS {
+                return Err(I2cError::MuxChannel(channel));
+            }
+        }
+
+        Ok(Self { bus, address })
     }
 
     pub async fn read_reg_byte(&mut self, address: u8) -> Result<u8, I2cError> {
-        self.dev.write_u8(address).await.map_err(I2cError::Write)?;
-        self.dev.read_u8().await.map_err(I2cError::Read)
+        let mut buf = [0; 1];
+        self.read_reg_bytes(address, &mut buf).await?;
+        Ok(buf[0])
     }
 
     pub async fn write_reg_byte(&mut self, address: u8, data: u8) -> Result<(), I2cError> {
-        self.dev
-            .write_all(&[address, data])
-            .await
-            .map_err(I2cError::Write)
+        self.write_bytes(&[address, data]).await
     }
 
     pub async fn set_reg_bits(&mut self, address: u8, mask: u8) -> Result<(), I2cError> {
-        let data = self.read_reg_byte(address).await?;
-        self.write_reg_byte(address, data | mask).await
+        let mut bus = self.bus.lock().await;
+        let mut buf = [0; 1];
+        bus.write_read(self.address, vec![address], &mut buf)
+            .await?;
+        bus.write(self.address, vec![address, buf[0] | mask]).await
     }
 
     pub async fn read_reg_u16(&mut self, address: u8) -> Result<u16, I2cError> {
-        self.dev.write_u8(address).await.map_err(I2cError::Write)?;
-        self.dev.read_u16().await.map_err(I2cError::Read)
+        let mut buf = [0; 2];
+        self.read_reg_bytes(address, &mut buf).await?;
+        Ok(u16::from_be_bytes(buf))
     }
 
     pub async fn write_reg_u16(&mut self, address: u8, data: u16) -> Result<(), I2cError> {
         let data = data.to_be_bytes();
-        self.dev
-            .write_all(&[address, data[0], data[1]])
-            .await
-            .map_err(I2cError::Write)
+        self.write_bytes(&[address, data[0], data[1]]).await
     }
 
     pub async fn read_reg_bytes(&mut self, address: u8, buf: &mut [u8]) -> Result<usize, I2cError> {
-        self.dev.write_u8(address).await.map_err(I2cError::Write)?;
-        self.dev.read(buf).await.map_err(I2cError::Read)
+        self.bus
+            .lock()
+            .await
+            .write_read(self.address, vec![address], buf)
+            .await?;
+        Ok(buf.len())
+    }
+
+    /// Reads a little endian word with the SMBus read word protocol and checks the packet
+    /// error code that follows it.
+    pub async fn read_word_pec(&mut self, command: u8) -> Result<u16, I2cError> {
+        let mut buf = [0; 3];
+        self.read_reg_bytes(command, &mut buf).await?;
+
+        let address = self.address.address << 1;
+        let expected = smbus_pec(&[address, command, address | 1, buf[0], buf[1]]);
+        if buf[2] != expected {
+            return Err(I2cError::Pec {
+                expected,
+                found: buf[2],
+            });
+        }
+
+        Ok(u16::from_le_bytes([buf[0], buf[1]]))
     }
 
     pub async fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, I2cError> {
-        self.dev.read(buf).await.map_err(I2cError::Read)
+        self.bus
+            .lock()
+            .await
+            .write_read(self.address, Vec::new(), buf)
+            .await?;
+        Ok(buf.len())
     }
 
     pub async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), I2cError> {
-        self.dev.write_all(bytes).await.map_err(I2cError::Write)
+        self.bus
+            .lock()
+            .await
+            .write(self.address, bytes.to_vec())
+            .await
+    }
+
+    pub async fn read_reg16_byte(&mut self, address: u16) -> Result<u8, I2cError> {
+        let mut buf = [0; 1];
+        self.read_reg16_bytes(address, &mut buf).await?;
+        Ok(buf[0])
+    }
+
+    pub async fn write_reg16_byte(&mut self, address: u16, data: u8) -> Result<(), I2cError> {
+        self.write_reg16_bytes(address, &[data]).await
+    }
+
+    pub async fn read_reg16_u16(&mut self, address: u16) -> Result<u16, I2cError> {
+        let mut buf = [0; 2];
+        self.read_reg16_bytes(address, &mut buf).await?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    pub async fn write_reg16_u16(&mut self, address: u16, data: u16) -> Result<(), I2cError> {
+        self.write_reg16_bytes(address, &data.to_be_bytes()).await
+    }
+
+    pub async fn write_reg16_u32(&mut self, address: u16, data: u32) -> Result<(), I2cError> {
+        self.write_reg16_bytes(address, &data.to_be_bytes()).await
+    }
+
+    pub async fn read_reg16_bytes(&mut self, address: u16, buf: &mut [u8]) -> Result<(), I2cError> {
+        self.bus
+            .lock()
+            .await
+            .write_read(self.address, address.to_be_bytes().to_vec(), buf)
+            .await
+    }
+
+    pub async fn write_reg16_bytes(&mut self, address: u16, data: &[u8]) -> Result<(), I2cError> {
+        let mut bytes = Vec::with_capacity(data.len() + 2);
+        bytes.extend_from_slice(&address.to_be_bytes());
+        bytes.extend_from_slice(data);
+        self.bus.lock().await.write(self.address, bytes).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator::{Device, Emulator, RegisterMap};
+
+    /// Records the channel masks that are written to a multiplexer.
+    #[derive(Clone, Default)]
+    struct Mux(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Device for Mux {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(())
+        }
+
+        fn read(&mut self, _: &mut [u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Answers every read with the address of the device.
+    struct Echo;
+
+    impl Adapter for Echo {
+        fn transfer(&self, address: u8, messages: &mut [Message]) -> io::Result<()> {
+            for message in messages {
+                if let Message::Read(buf) = message {
+                    buf.fill(address);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bus_stats_ok() {
+        let mut stats = BusStats::default();
+        stats.record(&Ok(()));
+        stats.record(&Err(io::Error::from_raw_os_error(libc::EREMOTEIO)));
+        stats.record(&Err(io::Error::from_raw_os_error(libc::ETIMEDOUT)));
+        stats.record(&Err(io::Error::from_raw_os_error(libc::EIO)));
+
+        assert_eq!(
+            stats,
+            BusStats {
+                transactions: 4,
+                errors: 3,
+                nacks: 1,
+                timeouts: 1,
+                arbitration_lost: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn transfer_ok() {
+        let device = RegisterMap::new().set(0x10, &[0xAB, 0xCD]);
+        let path = Emulator::new().device(0x40, device.clone()).attach();
+        let mut i2c = I2C::new(&path, 0x40).await.unwrap();
+
+        assert_eq!(i2c.read_reg_u16(0x10).await.unwrap(), 0xABCD);
+        i2c.write_reg_byte(0x20, 0x05).await.unwrap();
+        i2c.set_reg_bits(0x20, 0x02).await.unwrap();
+        assert_eq!(device.get(0x20), 0x07);
+        assert_eq!(device.writes(), vec![(0x20, 0x05), (0x20, 0x07)]);
+    }
+
+    #[tokio::test]
+    async fn with_bus_ok() {
+        let bus = I2cBus::attach("/custom/i2c-0", Arc::new(Echo));
+        let mut i2c = I2C::with_bus(bus.clone(), 0x42).unwrap();
+        assert_eq!(i2c.read_reg_byte(0x00).await.unwrap(), 0x42);
+        assert!(matches!(
+            I2C::with_bus(bus.clone(), I2cAddress::new(0x42).mux(0x70, 8)),
+            Err(I2cError::MuxChannel(8))
+        ));
+
+        // devices that are created with the path share the bus
+        let opened = I2cBus::open("/custom/i2c-0").await.unwrap();
+        assert!(Arc::ptr_eq(&opened, &bus));
+    }
+
+    #[test]
+    fn smbus_pec_ok() {
+        assert_eq!(smbus_pec(&[]), 0x00);
+        // the check value of CRC-8/SMBUS
+        assert_eq!(smbus_pec(b"123456789"), 0xF4);
+    }
+
+    #[tokio::test]
+    async fn read_word_pec_ok() {
+        let pec = smbus_pec(&[0xB4, 0x07, 0xB5, 0xD3, 0x3A]);
+        let device = RegisterMap::new().set(0x07, &[0xD3, 0x3A, pec]);
+        let path = Emulator::new().device(0x5A, device.clone()).attach();
+        let mut i2c = I2C::new(&path, 0x5A).await.unwrap();
+
+        assert_eq!(i2c.read_word_pec(0x07).await.unwrap(), 0x3AD3);
+
+        device.set(0x09, &[pec ^ 0x01]);
+        assert!(matches!(
+            i2c.read_word_pec(0x07).await,
+            Err(I2cError::Pec { found, .. }) if found == pec ^ 0x01
+        ));
+    }
+
+    #[tokio::test]
+    async fn transfer_err() {
+        let device = RegisterMap::new();
+        let path = Emulator::new().device(0x40, device.clone()).attach();
+
+        let mut absent = I2C::new(&path, 0x41).await.unwrap();
+        let err = absent.read_reg_byte(0x00).await.unwrap_err();
+        assert!(matches!(err, I2cError::Read(ref err) if err.raw_os_error() == Some(libc::ENXIO)));
+        assert!(err.is_nack());
+
+        let mut i2c = I2C::new(&path, 0x40).await.unwrap();
+        device.fail(Some(libc::ETIMEDOUT));
+        let err = i2c.write_reg_byte(0x00, 0x01).await.unwrap_err();
+        assert!(matches!(err, I2cError::Write(_)));
+        assert!(!err.is_nack());
+        device.fail(None);
+        i2c.write_reg_byte(0x00, 0x01).await.unwrap();
+
+        let stats = I2cBus::open(&path).await.unwrap().stats();
+        assert_eq!(stats.transactions, 3);
+        assert_eq!(stats.nacks, 1);
+        assert_eq!(stats.timeouts, 1);
+
+        assert!(matches!(
+            I2C::new(&path, I2cAddress::new(0x40).mux(0x70, 8)).await,
+            Err(I2cError::MuxChannel(8))
+        ));
+    }
+
+    #[tokio::test]
+    async fn mux_select_ok() {
+        let (mux1, mux2) = (Mux::default(), Mux::default());
+        let path = Emulator::new()
+            .device(0x40, RegisterMap::new())
+            .device(0x70, mux1.clone())
+            .device(0x71, mux2.clone())
+            .attach();
+        let mut left = I2C::new(&path, I2cAddress::new(0x40).mux(0x70, 1))
+            .await
+            .unwrap();
+        let mut right = I2C::new(&path, I2cAddress::new(0x40).mux(0x70, 2))
+            .await
+            .unwrap();
+        let mut other = I2C::new(&path, I2cAddress::new(0x40).mux(0x71, 0))
+            .await
+            .unwrap();
+
+        left.read_reg_byte(0x00).await.unwrap();
+        left.read_reg_byte(0x00).await.unwrap();
+        right.read_reg_byte(0x00).await.unwrap();
+        other.read_reg_byte(0x00).await.unwrap();
+
+        // the channel is only selected when it changes, other muxes are disabled
+        assert_eq!(*mux1.0.lock().unwrap(), vec![0b010, 0b100, 0]);
+        assert_eq!(*mux2.0.lock().unwrap(), vec![0b001]);
+
+        // all muxes are disabled before a device on the bus itself is accessed
+        let mut direct = I2C::new(&path, I2cAddress::new(0x40)).await.unwrap();
+        direct.read_reg_byte(0x00).await.unwrap();
+        direct.read_reg_byte(0x00).await.unwrap();
+        assert_eq!(*mux2.0.lock().unwrap(), vec![0b001, 0]);
+        left.read_reg_byte(0x00).await.unwrap();
+        direct.read_reg_byte(0x00).await.unwrap();
+        assert_eq!(*mux1.0.lock().unwrap(), vec![0b010, 0b100, 0, 0b010, 0]);
     }
 }
@@ -0,0 +1,203 @@
+//! An in-process I2C bus with emulated devices, used to test drivers without hardware.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use nix::libc;
+
+use super::{Adapter, I2cBus, Message};
+
+static NEXT_BUS: AtomicUsize = AtomicUsize::new(0);
+
+/// A device model on an emulated bus.
+pub trait Device: Send {
+    /// Handles a write message of a transaction.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Handles a read message of a transaction by filling the whole buffer.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<()>;
+}
+
+/// An I2C bus with emulated devices. Devices that are not present don't acknowledge their
+/// address.
+#[derive(Default)]
+pub struct Emulator {
+    devices: Mutex<HashMap<u8, Box<dyn Device>>>,
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a device at the given address.
+    pub fn device(self, address: u8, device: impl Device + 'static) -> Self {
+        self.devices
+            .lock()
+            .unwrap()
+            .insert(address, Box::new(device));
+        self
+    }
+
+    /// Registers the bus under a unique path, which drivers open instead of an adapter of the
+    /// kernel.
+    pub fn attach(self) -> PathBuf {
+        let id = NEXT_BUS.fetch_add(1, Ordering::Relaxed);
+        let path = PathBuf::from(format!("/emulated/i2c-{id}"));
+        I2cBus::attach(&path, Arc::new(self));
+
+        path
+    }
+}
+
+impl Adapter for Emulator {
+    fn transfer(&self, address: u8, messages: &mut [Message]) -> io::Result<()> {
+        let mut devices = self.devices.lock().unwrap();
+        let device = devices
+            .get_mut(&address)
+            .ok_or(io::Error::from_raw_os_error(libc::ENXIO))?;
+
+        for message in messages {
+            match message {
+                Message::Write(bytes) => device.write(bytes)?,
+                Message::Read(buf) => device.read(buf)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct RegisterState {
+    pointer: u8,
+    registers: HashMap<u8, u8>,
+    scripts: HashMap<u8, VecDeque<u8>>,
+    triggers: HashMap<(u8, u8), Vec<(u8, u8)>>,
+    writes: Vec<(u8, u8)>,
+    error: Option<i32>,
+}
+
+/// A device with a map of 8 bit registers.
+///
+/// The first byte of a write sets the register pointer, the remaining bytes are written to
+/// consecutive registers. Reads return consecutive registers starting at the pointer, unset
+/// registers read as 0. The map is shared between clones, so that it can be inspected after it
+/// was added to an emulator.
+#[derive(Clone, Default)]
+pub struct RegisterMap {
+    state: Arc<Mutex<RegisterState>>,
+}
+
+impl RegisterMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets consecutive registers starting at the given one.
+    pub fn set(self, register: u8, values: &[u8]) -> Self {
+        let mut state = self.state.lock().unwrap();
+        for (i, value) in values.iter().enumerate() {
+            state
+                .registers
+                .insert(register.wrapping_add(i as u8), *value);
+        }
+        drop(state);
+        self
+    }
+
+    /// Returns the values on successive reads of the register, before falling back to its
+    /// stored value.
+    pub fn script(self, register: u8, values: &[u8]) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .scripts
+            .entry(register)
+            .or_default()
+            .extend(values);
+        self
+    }
+
+    /// Sets registers whenever the value is written to the register, e.g. to complete a
+    /// triggered conversion.
+    pub fn on_write(self, register: u8, value: u8, effects: &[(u8, u8)]) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .triggers
+            .insert((register, value), effects.to_vec());
+        self
+    }
+
+    /// Fails all following transactions with the given OS error, `None` recovers the device.
+    pub fn fail(&self, errno: Option<i32>) {
+        self.state.lock().unwrap().error = errno;
+    }
+
+    /// Returns the stored value of a register.
+    pub fn get(&self, register: u8) -> u8 {
+        let state = self.state.lock().unwrap();
+        state.registers.get(&register).copied().unwrap_or_default()
+    }
+
+    /// Returns all registers and values that have been written, in order.
+    pub fn writes(&self) -> Vec<(u8, u8)> {
+        self.state.lock().unwrap().writes.clone()
+    }
+}
+
+impl Device for RegisterMap {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(errno) = state.error {
+            return Err(io::Error::from_raw_os_error(errno));
+        }
+
+        let Some((&pointer, values)) = bytes.split_first() else {
+            return Err(io::Error::from_raw_os_error(libc::EIO));
+        };
+
+        state.pointer = pointer;
+        for value in values {
+            let register = state.pointer;
+            state.registers.insert(register, *value);
+            state.writes.push((register, *value));
+            if let Some(effects) = state.triggers.get(&(register, *value)).cloned() {
+                state.registers.extend(effects);
+            }
+            state.pointer = register.wrapping_add(1);
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(errno) = state.error {
+            return Err(io::Error::from_raw_os_error(errno));
+        }
+
+        for byte in buf {
+            let register = state.pointer;
+            *byte = match state
+                .scripts
+                .get_mut(&register)
+                .and_then(VecDeque::pop_front)
+            {
+                Some(value) => value,
+                None => state.registers.get(&register).copied().unwrap_or_default(),
+            };
+            state.pointer = register.wrapping_add(1);
+        }
+
+        Ok(())
+    }
+}
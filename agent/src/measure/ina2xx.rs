@@ -0,0 +1,576 @@
+use super::{
+    i2c::{I2cAddress, I2C},
+    Measure, PowerMeasurement, SensorError,
+};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use std::{path::Path, time::Duration};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_SHUNT_VOLTAGE: u8 = 0x01;
+const REG_BUS_VOLTAGE: u8 = 0x02;
+const REG_POWER: u8 = 0x03;
+const REG_CURRENT: u8 = 0x04;
+const REG_CALIBRATION: u8 = 0x05;
+const REG_MASK_ENABLE: u8 = 0x06;
+const REG_MANUFACTURER_ID: u8 = 0xFE;
+const REG_DIE_ID: u8 = 0xFF;
+
+const CONFIG_RESET: u16 = 0x8000;
+const MODE_TRIGGERED: u16 = 0b011;
+
+const INA219_CONFIG_DEFAULT: u16 = 0x399F;
+const INA219_BUS_RANGE_32V: u16 = 1 << 13;
+const INA219_MASK_CNVR: u16 = 0x02;
+const INA219_MASK_OVF: u16 = 0x01;
+/// The full scale shunt voltages of the programmable gains /1, /2, /4 and /8.
+const INA219_SHUNT_RANGES: [f64; 4] = [0.04, 0.08, 0.16, 0.32];
+const INA219_AVERAGES: [u16; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+const INA219_CONVERSION_TIME_US: u64 = 532;
+
+const INA226_MANUFACTURER_ID: u16 = 0x5449;
+const INA226_DEVICE_ID: u16 = 0x226;
+const INA226_CONFIG_FIXED: u16 = 0x4000;
+/// 1.1ms conversion time of the shunt and bus voltage.
+const INA226_CONVERSION_TIME: u16 = 0b100;
+const INA226_MASK_CVRF: u16 = 0x08;
+const INA226_MASK_OVF: u16 = 0x04;
+const INA226_SHUNT_RANGE: f64 = 0.08192;
+const INA226_AVERAGES: [u16; 8] = [1, 4, 16, 64, 128, 256, 512, 1024];
+const INA226_CONVERSION_TIME_US: u64 = 1100;
+
+const POLL_DURATION: Duration = Duration::from_millis(2);
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+const DEFAULT_SHUNT_RESISTANCE: f64 = 0.1;
+
+/// The model of the current and power monitor.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Variant {
+    Ina219,
+    Ina226,
+}
+
+impl Variant {
+    /// The largest shunt voltage that can be measured in volts.
+    fn shunt_range(&self) -> f64 {
+        match self {
+            Variant::Ina219 => INA219_SHUNT_RANGES[3],
+            Variant::Ina226 => INA226_SHUNT_RANGE,
+        }
+    }
+
+    /// The number of samples that can be averaged.
+    fn averages(&self) -> &'static [u16; 8] {
+        match self {
+            Variant::Ina219 => &INA219_AVERAGES,
+            Variant::Ina226 => &INA226_AVERAGES,
+        }
+    }
+}
+
+/// The shunt resistor of a monitor and the settings derived from it.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Shunt {
+    /// The resistance in ohms.
+    resistance: f64,
+    /// The value of the calibration register.
+    calibration: u16,
+    /// The current of the least significant bit of the current register in amperes.
+    current_lsb: f64,
+    /// The programmable gain of an INA219, the configuration bits are the index.
+    gain: u16,
+}
+
+impl Shunt {
+    /// Computes the calibration for a shunt with the given resistance in ohms, so that the
+    /// current register covers currents up to `max_current` amperes. By default, the whole
+    /// shunt voltage range of the monitor is used.
+    pub fn new(variant: Variant, resistance: f64, max_current: Option<f64>) -> Result<Self> {
+        if !(resistance.is_finite() && resistance > 0.) {
+            bail!("Shunt resistance must be positive");
+        }
+        let max_current = max_current.unwrap_or(variant.shunt_range() / resistance);
+        if !(max_current.is_finite() && max_current > 0.) {
+            bail!("Maximum current must be positive");
+        }
+        let max_shunt_voltage = max_current * resistance;
+        if max_shunt_voltage > variant.shunt_range() + f64::EPSILON {
+            bail!(
+                "Maximum current of {max_current} A exceeds the shunt voltage range of {} mV",
+                variant.shunt_range() * 1000.
+            );
+        }
+
+        let (factor, max_calibration, gain) = match variant {
+            Variant::Ina219 => {
+                let gain = INA219_SHUNT_RANGES
+                    .iter()
+                    .position(|&r| max_shunt_voltage <= r + f64::EPSILON)
+                    .unwrap_or(INA219_SHUNT_RANGES.len() - 1);
+                // the lowest bit of the calibration register is not used
+                (0.04096, 0xFFFE, gain as u16)
+            }
+            Variant::Ina226 => (0.00512, 0x7FFF, 0),
+        };
+        let current_lsb = max_current / 32768.;
+        let calibration = (factor / (current_lsb * resistance)).min(max_calibration as f64) as u16;
+        let calibration = match variant {
+            Variant::Ina219 => calibration & !1,
+            Variant::Ina226 => calibration,
+        };
+
+        Ok(Self {
+            resistance,
+            calibration,
+            // the resolution is limited by the calibration register for small currents
+            current_lsb: factor / (f64::from(calibration) * resistance),
+            gain,
+        })
+    }
+}
+
+/// Texas Instruments INA219 or INA226 current and power monitor
+pub struct Ina2xx {
+    i2c: I2C,
+    label: String,
+    variant: Variant,
+    shunt: Shunt,
+    averages: u16,
+}
+
+impl Ina2xx {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+        variant: Variant,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut i2c = I2C::new(i2c_path, address).await?;
+        Self::init(&mut i2c, variant)
+            .await
+            .with_context(|| format!("Failed to initialize {variant:?} at address {address}"))?;
+
+        Ok(Self {
+            i2c,
+            label,
+            variant,
+            shunt: Shunt::new(variant, DEFAULT_SHUNT_RESISTANCE, None)?,
+            averages: 1,
+        })
+    }
+
+    pub fn shunt(mut self, shunt: Shunt) -> Self {
+        self.shunt = shunt;
+        self
+    }
+
+    /// Sets the number of samples that are averaged in a single measurement, which is rounded
+    /// down to a number the monitor supports.
+    pub fn averages(mut self, averages: u16) -> Self {
+        self.averages = self
+            .variant
+            .averages()
+            .iter()
+            .copied()
+            .filter(|&a| a <= averages)
+            .max()
+            .unwrap_or(1);
+        self
+    }
+
+    async fn init(i2c: &mut I2C, variant: Variant) -> Result<()> {
+        if variant == Variant::Ina226 {
+            let manufacturer_id = i2c.read_reg_u16(REG_MANUFACTURER_ID).await?;
+            let device_id = i2c.read_reg_u16(REG_DIE_ID).await? >> 4;
+            if manufacturer_id != INA226_MANUFACTURER_ID || device_id != INA226_DEVICE_ID {
+                return Err(SensorError::WrongChipId {
+                    expected: INA226_DEVICE_ID.into(),
+                    found: device_id.into(),
+                }
+                .into());
+            }
+        }
+
+        i2c.write_reg_u16(REG_CONFIG, CONFIG_RESET).await?;
+        // the INA219 has no ID register, but a known configuration after reset
+        if variant == Variant::Ina219 {
+            let config = i2c.read_reg_u16(REG_CONFIG).await?;
+            if config != INA219_CONFIG_DEFAULT {
+                return Err(SensorError::WrongChipId {
+                    expected: INA219_CONFIG_DEFAULT.into(),
+                    found: config.into(),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the configuration that triggers a single conversion of shunt and bus voltage.
+    fn config(&self) -> u16 {
+        // the index of the number of averages in the supported ones is its configuration
+        let averages = self
+            .variant
+            .averages()
+            .iter()
+            .position(|&a| a == self.averages)
+            .unwrap_or_default() as u16;
+
+        match self.variant {
+            Variant::Ina219 => {
+                // 12 bit resolution without averaging, averaging modes start at 0b1001
+                let adc = if averages == 0 {
+                    0b0011
+                } else {
+                    0b1000 | averages
+                };
+                INA219_BUS_RANGE_32V
+                    | (self.shunt.gain << 11)
+                    | (adc << 7)
+                    | (adc << 3)
+                    | MODE_TRIGGERED
+            }
+            Variant::Ina226 => {
+                INA226_CONFIG_FIXED
+                    | (averages << 9)
+                    | (INA226_CONVERSION_TIME << 6)
+                    | (INA226_CONVERSION_TIME << 3)
+                    | MODE_TRIGGERED
+            }
+        }
+    }
+
+    /// Returns the time a conversion of shunt and bus voltage takes.
+    fn conversion_time(&self) -> Duration {
+        let micros = match self.variant {
+            Variant::Ina219 => INA219_CONVERSION_TIME_US,
+            Variant::Ina226 => INA226_CONVERSION_TIME_US,
+        };
+
+        Duration::from_micros(2 * micros * u64::from(self.averages))
+    }
+
+    /// Waits until the triggered conversion is complete and returns whether the current or
+    /// power calculation overflowed.
+    async fn wait_conversion(&mut self, cancel_token: &CancellationToken) -> Result<bool> {
+        let mut delay = self.conversion_time();
+        let deadline = Instant::now() + delay + POLL_TIMEOUT;
+        loop {
+            tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    return Err(SensorError::Cancelled.into());
+                }
+                _ = tokio::time::sleep(delay) => {}
+            }
+
+            let (ready, overflow) = match self.variant {
+                Variant::Ina219 => {
+                    let bus = self.i2c.read_reg_u16(REG_BUS_VOLTAGE).await?;
+                    (bus & INA219_MASK_CNVR != 0, bus & INA219_MASK_OVF != 0)
+                }
+                Variant::Ina226 => {
+                    let flags = self.i2c.read_reg_u16(REG_MASK_ENABLE).await?;
+                    (flags & INA226_MASK_CVRF != 0, flags & INA226_MASK_OVF != 0)
+                }
+            };
+            if ready {
+                return Ok(overflow);
+            }
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout("a conversion".into()).into());
+            }
+            delay = POLL_DURATION;
+        }
+    }
+}
+
+impl Measure for Ina2xx {
+    type Measurement = PowerMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        // the calibration is lost when the monitor loses power, so it's written every time
+        self.i2c
+            .write_reg_u16(REG_CALIBRATION, self.shunt.calibration)
+            .await?;
+        self.i2c.write_reg_u16(REG_CONFIG, self.config()).await?;
+        let overflow = self.wait_conversion(&cancel_token).await?;
+        let measure_time = Utc::now().timestamp_millis();
+        let (shunt_lsb, bus_lsb, power_lsb) = match self.variant {
+            Variant::Ina219 => (10e-6, 0.004, 20. * self.shunt.current_lsb),
+            Variant::Ina226 => (2.5e-6, 0.00125, 25. * self.shunt.current_lsb),
+        };
+
+        // the current exceeds the maximum current of the calibration
+        if overflow {
+            let shunt_voltage = self.i2c.read_reg_u16(REG_SHUNT_VOLTAGE).await? as i16;
+            return Err(SensorError::OutOfRange {
+                quantity: "current",
+                value: f64::from(shunt_voltage) * shunt_lsb / self.shunt.resistance,
+            });
+        }
+
+        let bus = self.i2c.read_reg_u16(REG_BUS_VOLTAGE).await?;
+        let bus = match self.variant {
+            // the lowest bits are flags
+            Variant::Ina219 => bus >> 3,
+            Variant::Ina226 => bus,
+        };
+        let current = self.i2c.read_reg_u16(REG_CURRENT).await? as i16;
+        let power = self.i2c.read_reg_u16(REG_POWER).await?;
+        let current = f64::from(current) * self.shunt.current_lsb;
+
+        Ok(PowerMeasurement::new(measure_time, self.label.clone())
+            .voltage(f64::from(bus) * bus_lsb)
+            .current(current)
+            .power(f64::from(power) * power_lsb))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        Ok(Self::init(&mut self.i2c, self.variant).await?)
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::i2c::emulator::{Device, Emulator};
+    use std::{
+        collections::HashMap,
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    const ADDRESS: u8 = 0x40;
+
+    #[derive(Default)]
+    struct MonitorState {
+        pointer: u8,
+        registers: HashMap<u8, u16>,
+        conversions: usize,
+    }
+
+    /// Emulates a monitor with a constant load, which computes current and power from the
+    /// calibration like the chip does.
+    #[derive(Clone)]
+    struct Monitor {
+        variant: Variant,
+        resistance: f64,
+        current: f64,
+        voltage: f64,
+        state: Arc<Mutex<MonitorState>>,
+    }
+
+    impl Monitor {
+        fn new(variant: Variant, resistance: f64, current: f64, voltage: f64) -> Self {
+            let monitor = Self {
+                variant,
+                resistance,
+                current,
+                voltage,
+                state: Default::default(),
+            };
+            monitor.reset();
+            monitor
+        }
+
+        fn reset(&self) {
+            let mut state = self.state.lock().unwrap();
+            state.registers.clear();
+            match self.variant {
+                Variant::Ina219 => {
+                    state.registers.insert(REG_CONFIG, INA219_CONFIG_DEFAULT);
+                }
+                Variant::Ina226 => {
+                    state.registers.insert(REG_CONFIG, 0x4127);
+                    state
+                        .registers
+                        .insert(REG_MANUFACTURER_ID, INA226_MANUFACTURER_ID);
+                    state.registers.insert(REG_DIE_ID, 0x2260);
+                }
+            }
+        }
+
+        fn convert(&self, state: &mut MonitorState) {
+            let register = |r: u8| state.registers.get(&r).copied().unwrap_or_default();
+            let calibration = f64::from(register(REG_CALIBRATION));
+            let (shunt_lsb, bus_lsb, current_divisor, power_divisor) = match self.variant {
+                Variant::Ina219 => (10e-6, 0.004, 4096., 5000.),
+                Variant::Ina226 => (2.5e-6, 0.00125, 2048., 20000.),
+            };
+            let shunt = (self.current * self.resistance / shunt_lsb)
+                .round()
+                .clamp(i16::MIN.into(), i16::MAX.into());
+            let bus = (self.voltage / bus_lsb).round();
+            let current = (shunt * calibration / current_divisor).trunc();
+            let power = (current.abs() * bus / power_divisor).trunc();
+            let overflow = current.abs() > f64::from(i16::MAX);
+
+            state
+                .registers
+                .insert(REG_SHUNT_VOLTAGE, shunt as i16 as u16);
+            state.registers.insert(REG_CURRENT, current as i16 as u16);
+            state.registers.insert(REG_POWER, power as u16);
+            match self.variant {
+                Variant::Ina219 => {
+                    let flags = INA219_MASK_CNVR | u16::from(overflow);
+                    state
+                        .registers
+                        .insert(REG_BUS_VOLTAGE, ((bus as u16) << 3) | flags);
+                }
+                Variant::Ina226 => {
+                    let flags = INA226_MASK_CVRF | if overflow { INA226_MASK_OVF } else { 0 };
+                    state.registers.insert(REG_BUS_VOLTAGE, bus as u16);
+                    state.registers.insert(REG_MASK_ENABLE, flags);
+                }
+            }
+            state.conversions += 1;
+        }
+    }
+
+    impl Device for Monitor {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+            match *bytes {
+                [pointer] => self.state.lock().unwrap().pointer = pointer,
+                [REG_CONFIG, high, low] if u16::from_be_bytes([high, low]) & CONFIG_RESET != 0 => {
+                    self.reset()
+                }
+                [pointer, high, low] => {
+                    let value = u16::from_be_bytes([high, low]);
+                    let mut state = self.state.lock().unwrap();
+                    state.pointer = pointer;
+                    state.registers.insert(pointer, value);
+                    if pointer == REG_CONFIG && value & 0b111 == MODE_TRIGGERED {
+                        self.convert(&mut state);
+                    }
+                }
+                _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+            }
+
+            Ok(())
+        }
+
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+            let state = self.state.lock().unwrap();
+            let value = state
+                .registers
+                .get(&state.pointer)
+                .copied()
+                .unwrap_or_default();
+            buf.copy_from_slice(&value.to_be_bytes()[..buf.len()]);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shunt_ok() {
+        let shunt = Shunt::new(Variant::Ina219, 0.1, None).unwrap();
+        assert_eq!(shunt.calibration, 4194);
+        assert_eq!(shunt.gain, 3);
+        assert!((shunt.current_lsb - 3.2 / 32768.).abs() < 1e-8);
+
+        let shunt = Shunt::new(Variant::Ina219, 0.1, Some(0.5)).unwrap();
+        assert_eq!(shunt.gain, 1);
+        assert_eq!(shunt.calibration, 26843 & !1);
+
+        let shunt = Shunt::new(Variant::Ina226, 0.002, Some(20.)).unwrap();
+        assert_eq!(shunt.calibration, 4194);
+        assert!((shunt.current_lsb - 20. / 32768.).abs() < 1e-6);
+
+        // the calibration register limits the resolution of small currents
+        let shunt = Shunt::new(Variant::Ina226, 0.1, Some(0.01)).unwrap();
+        assert_eq!(shunt.calibration, 0x7FFF);
+        assert!((shunt.current_lsb - 0.00512 / (0x7FFF as f64 * 0.1)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shunt_err() {
+        assert!(Shunt::new(Variant::Ina219, 0., None).is_err());
+        assert!(Shunt::new(Variant::Ina219, 0.1, Some(-1.)).is_err());
+        // 0.1 V across the shunt exceed the ±81.92 mV of an INA226
+        assert!(Shunt::new(Variant::Ina226, 0.1, Some(1.)).is_err());
+        assert!(Shunt::new(Variant::Ina219, 0.1, Some(3.2)).is_ok());
+        assert!(Shunt::new(Variant::Ina219, 0.1, Some(3.3)).is_err());
+    }
+
+    #[tokio::test]
+    async fn measure_ok() {
+        let ina219 = Monitor::new(Variant::Ina219, 0.1, 1.5, 12.);
+        let ina226 = Monitor::new(Variant::Ina226, 0.002, -10., 24.);
+        let path = Emulator::new()
+            .device(ADDRESS, ina219.clone())
+            .device(ADDRESS + 1, ina226)
+            .attach();
+
+        let mut sensor = Ina2xx::new(&path, ADDRESS, "light".into(), Variant::Ina219)
+            .await
+            .unwrap()
+            .shunt(Shunt::new(Variant::Ina219, 0.1, Some(2.)).unwrap())
+            .averages(100);
+        assert_eq!(sensor.averages, 64);
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(measurement.voltage, Some(12.));
+        assert!((measurement.current.unwrap() - 1.5).abs() < 1e-3);
+        assert!((measurement.power.unwrap() - 18.).abs() < 0.01);
+        assert_eq!(ina219.state.lock().unwrap().conversions, 1);
+
+        let mut sensor = Ina2xx::new(&path, ADDRESS + 1, "pump".into(), Variant::Ina226)
+            .await
+            .unwrap()
+            .shunt(Shunt::new(Variant::Ina226, 0.002, Some(20.)).unwrap());
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(measurement.voltage, Some(24.));
+        // the power is unsigned, even if the current flows in reverse
+        assert!((measurement.current.unwrap() + 10.).abs() < 1e-2);
+        assert!((measurement.power.unwrap() - 240.).abs() < 0.1);
+    }
+
+    #[tokio::test]
+    async fn measure_err() {
+        let path = Emulator::new()
+            .device(ADDRESS, Monitor::new(Variant::Ina219, 0.1, 2., 12.))
+            .device(ADDRESS + 1, Monitor::new(Variant::Ina219, 0.1, 1., 12.))
+            .attach();
+
+        // the current exceeds the range of the calibration
+        let mut sensor = Ina2xx::new(&path, ADDRESS, "light".into(), Variant::Ina219)
+            .await
+            .unwrap()
+            .shunt(Shunt::new(Variant::Ina219, 0.1, Some(1.)).unwrap());
+        assert!(matches!(
+            sensor.measure(CancellationToken::new()).await,
+            Err(SensorError::OutOfRange { .. })
+        ));
+
+        // an INA219 has no manufacturer ID
+        let err = Ina2xx::new(&path, ADDRESS + 1, "pump".into(), Variant::Ina226)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(SensorError::WrongChipId { .. })
+        ));
+
+        let mut sensor = Ina2xx::new(&path, ADDRESS + 1, "fan".into(), Variant::Ina219)
+            .await
+            .unwrap();
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        assert!(matches!(
+            sensor.measure(cancel_token).await,
+            Err(SensorError::Cancelled)
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+use super::{Measure, RangeStatus, SensorError, WaterLevelMeasurement};
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use futures::{FutureExt, StreamExt};
+use gpio_cdev::{
+    AsyncLineEventHandle, Chip, EventRequestFlags, EventType, LineHandle, LineRequestFlags,
+};
+use nix::sys::termios::{self, BaudRate, FlushArg, SetArg, SpecialCharacterIndices};
+use std::{os::unix::fs::OpenOptionsExt, path::Path, time::Duration};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+const GPIO_CONSUMER: &str = "grow-agent";
+
+// the sensor responds with a frame of a header, the distance in mm and a checksum
+const UART_TRIGGER: u8 = 0x55;
+const UART_FRAME_HEADER: u8 = 0xFF;
+const UART_FRAME_SIZE: usize = 4;
+const UART_TIMEOUT: Duration = Duration::from_millis(500);
+
+// the sensor needs a trigger pulse of at least 10 µs
+const TRIGGER_DURATION: Duration = Duration::from_micros(20);
+// the echo of the maximum distance takes about 27 ms
+const ECHO_TIMEOUT: Duration = Duration::from_millis(100);
+// the speed of sound at 20 degree celsius is 343 m/s, halved for the round trip
+const MM_PER_NS: f64 = 343. / 1_000_000. / 2.;
+
+// the sensor can't measure distances within its blind zone or beyond its maximum range
+const DISTANCE_MIN: u32 = 200;
+const DISTANCE_MAX: u32 = 4500;
+
+enum Interface {
+    Uart(File),
+    Gpio {
+        trigger: LineHandle,
+        echo: AsyncLineEventHandle,
+    },
+}
+
+/// JSN-SR04T waterproof ultrasonic distance sensor
+///
+/// The sensor is either read via UART, which requires the board to be configured for the
+/// controlled serial mode, or by measuring the duration of the echo pulse on a GPIO.
+pub struct JsnSr04T {
+    interface: Interface,
+    label: String,
+}
+
+impl JsnSr04T {
+    /// Creates a sensor that is read via the serial port at the given path.
+    pub fn uart(serial_path: impl AsRef<Path>, label: String) -> Result<Self> {
+        let serial_path = serial_path.as_ref();
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(nix::libc::O_NOCTTY)
+            .open(serial_path)
+            .with_context(|| format!("Failed to open serial port {serial_path:?}"))?;
+
+        // raw 9600 8N1, reads return after 100 ms without data
+        let mut attrs =
+            termios::tcgetattr(&file).context("Failed to get serial port attributes")?;
+        termios::cfmakeraw(&mut attrs);
+        termios::cfsetspeed(&mut attrs, BaudRate::B9600).context("Failed to set baud rate")?;
+        attrs.control_chars[SpecialCharacterIndices::VMIN as usize] = 0;
+        attrs.control_chars[SpecialCharacterIndices::VTIME as usize] = 1;
+        termios::tcsetattr(&file, SetArg::TCSANOW, &attrs)
+            .context("Failed to set serial port attributes")?;
+        termios::tcflush(&file, FlushArg::TCIOFLUSH).context("Failed to flush serial port")?;
+
+        Ok(Self {
+            interface: Interface::Uart(File::from_std(file)),
+            label,
+        })
+    }
+
+    /// Creates a sensor that is triggered and read via GPIOs.
+    pub fn gpio(
+        gpio_path: impl AsRef<Path>,
+        trigger_pin: u32,
+        echo_pin: u32,
+        label: String,
+    ) -> Result<Self> {
+        let mut chip = Chip::new(gpio_path).context("Failed to open GPIO chip")?;
+        let trigger = chip
+            .get_line(trigger_pin)
+            .with_context(|| format!("Failed to get handle to GPIO line {trigger_pin}"))?
+            .request(LineRequestFlags::OUTPUT, 0, GPIO_CONSUMER)
+            .with_context(|| format!("Failed to get access to GPIO {trigger_pin}"))?;
+        let echo = chip
+            .get_line(echo_pin)
+            .with_context(|| format!("Failed to get handle to GPIO line {echo_pin}"))?
+            .events(
+                LineRequestFlags::INPUT,
+                EventRequestFlags::BOTH_EDGES,
+                GPIO_CONSUMER,
+            )
+            .with_context(|| format!("Failed to get access to GPIO {echo_pin}"))?;
+        let echo = AsyncLineEventHandle::new(echo)
+            .with_context(|| format!("Failed to watch GPIO {echo_pin}"))?;
+
+        Ok(Self {
+            interface: Interface::Gpio { trigger, echo },
+            label,
+        })
+    }
+
+    async fn read_uart(serial: &mut File) -> Result<u32> {
+        serial
+            .write_all(&[UART_TRIGGER])
+            .await
+            .context("Failed to write to serial port")?;
+
+        let mut buf = Vec::with_capacity(2 * UART_FRAME_SIZE);
+        let read = async {
+            loop {
+                let mut chunk = [0; UART_FRAME_SIZE];
+                let n = serial
+                    .read(&mut chunk)
+                    .await
+                    .context("Failed to read from serial port")?;
+                buf.extend_from_slice(&chunk[..n]);
+
+                if let Some(distance) = parse_frame(&buf) {
+                    return Ok(distance);
+                }
+            }
+        };
+
+        tokio::time::timeout(UART_TIMEOUT, read)
+            .await
+            .map_err(|_| SensorError::Timeout("a response".into()))?
+    }
+
+    async fn read_gpio(trigger: &mut LineHandle, echo: &mut AsyncLineEventHandle) -> Result<u32> {
+        // discard events of previous measurements
+        while let Some(Some(_)) = echo.next().now_or_never() {}
+
+        trigger.set_value(1).context("Failed to set trigger")?;
+        std::thread::sleep(TRIGGER_DURATION);
+        trigger.set_value(0).context("Failed to reset trigger")?;
+
+        let read = async {
+            let mut rising_edge = None;
+            while let Some(event) = echo.next().await {
+                let event = event.context("Failed to read echo event")?;
+                match (event.event_type(), rising_edge) {
+                    (EventType::RisingEdge, _) => rising_edge = Some(event.timestamp()),
+                    (EventType::FallingEdge, Some(start)) => {
+                        let duration = event.timestamp().saturating_sub(start);
+                        return Ok((duration as f64 * MM_PER_NS).round() as u32);
+                    }
+                    (EventType::FallingEdge, None) => {}
+                }
+            }
+
+            bail!("Echo events ended unexpectedly")
+        };
+
+        tokio::time::timeout(ECHO_TIMEOUT, read)
+            .await
+            .map_err(|_| SensorError::Timeout("the echo".into()))?
+    }
+}
+
+/// Returns the distance of the first complete frame with a valid checksum.
+fn parse_frame(buf: &[u8]) -> Option<u32> {
+    buf.windows(UART_FRAME_SIZE).find_map(|frame| {
+        let checksum = frame[..3].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        (frame[0] == UART_FRAME_HEADER && frame[3] == checksum)
+            .then(|| u32::from(u16::from_be_bytes([frame[1], frame[2]])))
+    })
+}
+
+impl Measure for JsnSr04T {
+    type Measurement = WaterLevelMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        let distance = tokio::select! {
+            _ = cancel_token.cancelled() => {
+                return Err(SensorError::Cancelled);
+            }
+            distance = async {
+                match &mut self.interface {
+                    Interface::Uart(serial) => Self::read_uart(serial).await,
+                    Interface::Gpio { trigger, echo } => Self::read_gpio(trigger, echo).await,
+                }
+            } => distance?,
+        };
+        let measure_time = Utc::now().timestamp_millis();
+
+        let range_status = if distance < DISTANCE_MIN {
+            RangeStatus::MinRangeFail
+        } else if distance > DISTANCE_MAX {
+            RangeStatus::SignalFail
+        } else {
+            RangeStatus::Valid
+        };
+
+        let measurement =
+            WaterLevelMeasurement::new(measure_time, self.label.clone()).range_status(range_status);
+        if range_status != RangeStatus::Valid {
+            debug!("Discarding distance of {distance} mm with range status {range_status:?}");
+            return Ok(measurement);
+        }
+
+        Ok(measurement.distance(distance))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        if let Interface::Uart(serial) = &self.interface {
+            termios::tcflush(serial, FlushArg::TCIOFLUSH).context("Failed to flush serial port")?;
+        }
+
+        Ok(())
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_frame_ok() {
+        assert_eq!(parse_frame(&[0xFF, 0x07, 0xA1, 0xA7]), Some(1953));
+        // skip garbage before the header
+        assert_eq!(parse_frame(&[0x12, 0xFF, 0x01, 0x00, 0x00]), Some(256));
+        assert_eq!(parse_frame(&[0xFF, 0x07, 0xA1, 0xA8]), None);
+        assert_eq!(parse_frame(&[0xFF, 0x07, 0xA1]), None);
+    }
+}
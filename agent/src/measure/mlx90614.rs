@@ -0,0 +1,243 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    i2c::{I2cAddress, I2C},
+    LeafMeasurement, Measure, SensorError,
+};
+
+const RAM_AMBIENT: u8 = 0x06;
+const RAM_OBJECT1: u8 = 0x07;
+const EEPROM_EMISSIVITY: u8 = 0x24;
+
+/// The temperature resolution in kelvin.
+const TEMPERATURE_LSB: f64 = 0.02;
+const ZERO_CELSIUS: f64 = 273.15;
+/// The MSB of an object temperature is set if the sensor failed to compute it.
+const MASK_ERROR: u16 = 0x8000;
+
+/// MLX90614 infrared thermometer, which measures the temperature of the object in its field
+/// of view and of its own package.
+pub struct Mlx90614 {
+    i2c: I2C,
+    label: String,
+    /// The emissivity the sensor compensates for, as configured in its EEPROM.
+    device_emissivity: f64,
+    emissivity: Option<f64>,
+}
+
+impl Mlx90614 {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut i2c = I2C::new(i2c_path, address).await?;
+        let device_emissivity = Self::init(&mut i2c)
+            .await
+            .with_context(|| format!("Failed to initialize MLX90614 at address {address}"))?;
+
+        Ok(Self {
+            i2c,
+            label,
+            device_emissivity,
+            emissivity: None,
+        })
+    }
+
+    /// Sets the emissivity of the measured surface, e.g. about 0.95 for leaves. The object
+    /// temperature is corrected in software, so that the EEPROM of the sensor isn't written.
+    pub fn emissivity(mut self, emissivity: f64) -> Self {
+        self.emissivity = Some(emissivity);
+        self
+    }
+
+    /// Reads the emissivity from the EEPROM, which also verifies that the sensor responds
+    /// with valid packet error codes.
+    async fn init(i2c: &mut I2C) -> Result<f64> {
+        let emissivity = i2c.read_word_pec(EEPROM_EMISSIVITY).await?;
+        if emissivity == 0 {
+            bail!("Invalid emissivity of 0 in EEPROM");
+        }
+
+        Ok(f64::from(emissivity) / f64::from(u16::MAX))
+    }
+
+    /// Converts a raw temperature into degree celsius.
+    fn temperature(raw: u16) -> f64 {
+        f64::from(raw) * TEMPERATURE_LSB - ZERO_CELSIUS
+    }
+
+    /// Corrects an object temperature that the sensor computed for another emissivity, all
+    /// radiation that is not emitted by the object is assumed to be reflected from the
+    /// surroundings at ambient temperature.
+    fn correct_emissivity(object: f64, ambient: f64, from: f64, to: f64) -> f64 {
+        let object = (object + ZERO_CELSIUS).powi(4);
+        let ambient = (ambient + ZERO_CELSIUS).powi(4);
+        let radiation = from * object + (1. - from) * ambient;
+
+        ((radiation - (1. - to) * ambient) / to).max(0.).powf(0.25) - ZERO_CELSIUS
+    }
+}
+
+impl Measure for Mlx90614 {
+    type Measurement = LeafMeasurement;
+
+    async fn measure(
+        &mut self,
+        _cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        // the sensor measures continuously, the results are read from its RAM
+        let ambient = self.i2c.read_word_pec(RAM_AMBIENT).await?;
+        let object = self.i2c.read_word_pec(RAM_OBJECT1).await?;
+        let measure_time = Utc::now().timestamp_millis();
+        if object & MASK_ERROR != 0 {
+            return Err(SensorError::Other(anyhow!(
+                "Sensor reports an error of the object temperature"
+            )));
+        }
+
+        let ambient = Self::temperature(ambient);
+        let mut object = Self::temperature(object);
+        if let Some(emissivity) = self.emissivity {
+            object = Self::correct_emissivity(object, ambient, self.device_emissivity, emissivity);
+        }
+
+        Ok(LeafMeasurement::new(measure_time, self.label.clone())
+            .object_temperature(object)
+            .ambient_temperature(ambient))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        self.device_emissivity = Self::init(&mut self.i2c).await?;
+        Ok(())
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::i2c::{
+        emulator::{Device, Emulator},
+        smbus_pec,
+    };
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    const ADDRESS: u8 = 0x5A;
+
+    /// Emulates the RAM and EEPROM words of a thermometer, which are read with PEC.
+    #[derive(Clone)]
+    struct Thermometer {
+        words: Arc<Mutex<[u16; 0x40]>>,
+        command: u8,
+    }
+
+    impl Thermometer {
+        fn new(ambient: f64, object: f64) -> Self {
+            let device = Self {
+                words: Arc::new(Mutex::new([0; 0x40])),
+                command: 0,
+            };
+            device.set(RAM_AMBIENT, raw(ambient));
+            device.set(RAM_OBJECT1, raw(object));
+            device.set(EEPROM_EMISSIVITY, u16::MAX);
+            device
+        }
+
+        fn set(&self, command: u8, word: u16) {
+            self.words.lock().unwrap()[usize::from(command)] = word;
+        }
+    }
+
+    fn raw(temperature: f64) -> u16 {
+        ((temperature + ZERO_CELSIUS) / TEMPERATURE_LSB).round() as u16
+    }
+
+    impl Device for Thermometer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+            match bytes {
+                [command] if usize::from(*command) < 0x40 => {
+                    self.command = *command;
+                    Ok(())
+                }
+                _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+            }
+        }
+
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+            let [lsb, msb] = self.words.lock().unwrap()[usize::from(self.command)].to_le_bytes();
+            let address = ADDRESS << 1;
+            let pec = smbus_pec(&[address, self.command, address | 1, lsb, msb]);
+            buf.copy_from_slice(&[lsb, msb, pec][..buf.len()]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn temperature_ok() {
+        assert!((Mlx90614::temperature(0x3AD3) - 28.03).abs() < 1e-9);
+        assert!((Mlx90614::temperature(0x2DE4) + 38.19).abs() < 1e-9);
+    }
+
+    #[test]
+    fn correct_emissivity_ok() {
+        // unchanged emissivity and object at ambient temperature
+        assert!((Mlx90614::correct_emissivity(21.5, 25., 1., 1.) - 21.5).abs() < 1e-9);
+        assert!((Mlx90614::correct_emissivity(25., 25., 1., 0.9) - 25.).abs() < 1e-9);
+
+        // a cooler object reflects radiation of the warmer surroundings
+        let object = Mlx90614::correct_emissivity(21.5, 25., 1., 0.95);
+        assert!(object < 21.5 && object > 21.);
+        let back = Mlx90614::correct_emissivity(object, 25., 0.95, 1.);
+        assert!((back - 21.5).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn measure_ok() {
+        let device = Thermometer::new(25., 22.5);
+        let path = Emulator::new().device(ADDRESS, device.clone()).attach();
+        let mut sensor = Mlx90614::new(&path, ADDRESS, "canopy".into())
+            .await
+            .unwrap();
+
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert!((measurement.object_temperature.unwrap() - 22.5).abs() < 0.01);
+        assert!((measurement.ambient_temperature.unwrap() - 25.).abs() < 0.01);
+
+        let mut sensor = sensor.emissivity(0.95);
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        let object = measurement.object_temperature.unwrap();
+        assert!(object < 22.5 && object > 22.);
+    }
+
+    #[tokio::test]
+    async fn measure_err() {
+        let device = Thermometer::new(25., 22.5);
+        let path = Emulator::new().device(ADDRESS, device.clone()).attach();
+        assert!(Mlx90614::new(&path, ADDRESS + 1, "absent".into())
+            .await
+            .is_err());
+
+        let mut sensor = Mlx90614::new(&path, ADDRESS, "canopy".into())
+            .await
+            .unwrap();
+        device.set(RAM_OBJECT1, MASK_ERROR | 0x0100);
+        assert!(matches!(
+            sensor.measure(CancellationToken::new()).await,
+            Err(SensorError::Other(_))
+        ));
+
+        device.set(EEPROM_EMISSIVITY, 0);
+        assert!(sensor.reinit().await.is_err());
+    }
+}
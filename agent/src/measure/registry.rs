@@ -0,0 +1,470 @@
+//! Sensor models that are configured, sampled and stored generically.
+//!
+//! A driver registers its model by implementing [`Model`] and adding [`register`] of it to
+//! [`MODELS`]. The model declares its name, the settings it's created with and the quantities
+//! it measures. Readings of registered models are stored by quantity in the `readings` table.
+
+use std::{
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use anyhow::{Context, Result};
+use futures::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::prelude::FromRow;
+use tokio_util::sync::CancellationToken;
+
+use super::{command::Command, I2cAddress, Measure, Quantities, SensorError};
+
+/// The sensor models that can be configured in the `generic` section.
+pub static MODELS: &[Registration] = &[register::<Command>()];
+
+/// The type of the values of a quantity.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuantityType {
+    #[default]
+    Float,
+    /// Values are rounded to whole numbers, also after they're combined or calibrated.
+    Integer,
+}
+
+/// A quantity measured by a sensor model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Quantity {
+    pub name: &'static str,
+    pub unit: &'static str,
+    #[serde(rename = "type")]
+    pub ty: QuantityType,
+}
+
+impl Quantity {
+    pub const fn float(name: &'static str, unit: &'static str) -> Self {
+        Self {
+            name,
+            unit,
+            ty: QuantityType::Float,
+        }
+    }
+
+    pub const fn integer(name: &'static str, unit: &'static str) -> Self {
+        Self {
+            name,
+            unit,
+            ty: QuantityType::Integer,
+        }
+    }
+}
+
+/// Returns quantities that are only known at runtime, e.g. from the settings of a sensor, with
+/// the same lifetime as the declared quantities of a model. Each distinct list of quantities is
+/// only allocated once.
+pub fn intern(
+    quantities: impl IntoIterator<Item = (String, String, QuantityType)>,
+) -> &'static [Quantity] {
+    static STRINGS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    static LISTS: Mutex<Vec<&'static [Quantity]>> = Mutex::new(Vec::new());
+
+    let mut strings = STRINGS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut intern_str = |s: String| match strings.iter().find(|i| **i == s) {
+        Some(interned) => *interned,
+        None => {
+            let interned: &'static str = Box::leak(s.into_boxed_str());
+            strings.push(interned);
+            interned
+        }
+    };
+    let quantities = quantities
+        .into_iter()
+        .map(|(name, unit, ty)| Quantity {
+            name: intern_str(name),
+            unit: intern_str(unit),
+            ty,
+        })
+        .collect::<Vec<_>>();
+
+    let mut lists = LISTS.lock().unwrap_or_else(PoisonError::into_inner);
+    match lists.iter().find(|l| **l == quantities.as_slice()) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static [Quantity] = Box::leak(quantities.into_boxed_slice());
+            lists.push(interned);
+            interned
+        }
+    }
+}
+
+/// What a sensor of a registered model is created from.
+#[derive(Debug, Clone)]
+pub struct Setup<C> {
+    pub label: String,
+    /// The address of the sensor, only used by I2C sensors.
+    pub address: I2cAddress,
+    pub i2c_path: PathBuf,
+    pub gpio_path: PathBuf,
+    /// The settings of the model.
+    pub config: C,
+}
+
+/// A sensor model that can be registered.
+#[trait_variant::make(Send)]
+pub trait Model: Measure<Measurement = Reading> + Sized + Send + 'static {
+    /// The name of the model in the config.
+    const NAME: &'static str;
+    /// The quantities the model measures, empty if they're configured per sensor.
+    const QUANTITIES: &'static [Quantity];
+    /// The settings of the model, deserialized from the `settings` of a sensor.
+    type Config: DeserializeOwned;
+
+    /// Returns the quantities a sensor measures with the given settings and checks the
+    /// settings beyond their format.
+    fn quantities(_config: &Self::Config) -> Result<&'static [Quantity]> {
+        Ok(Self::QUANTITIES)
+    }
+
+    async fn init(setup: Setup<Self::Config>) -> Result<Self>;
+}
+
+/// A registered sensor model.
+#[derive(Serialize)]
+pub struct Registration {
+    pub name: &'static str,
+    pub quantities: &'static [Quantity],
+    #[serde(skip)]
+    validate: fn(&Value) -> Result<&'static [Quantity]>,
+    #[serde(skip)]
+    init: fn(Setup<Value>) -> BoxFuture<'static, Result<Sensor>>,
+}
+
+/// Registers a sensor model.
+pub const fn register<M: Model>() -> Registration {
+    Registration {
+        name: M::NAME,
+        quantities: M::QUANTITIES,
+        validate: validate::<M>,
+        init: init::<M>,
+    }
+}
+
+impl Registration {
+    /// Returns the registered model with the given name.
+    pub fn find(name: &str) -> Result<&'static Self> {
+        Self::find_in(MODELS, name)
+    }
+
+    fn find_in(models: &'static [Self], name: &str) -> Result<&'static Self> {
+        models.iter().find(|m| m.name == name).with_context(|| {
+            let names = models.iter().map(|m| m.name).collect::<Vec<_>>();
+            format!(
+                "Unknown sensor model {name:?}, expected one of {}",
+                names.join(", ")
+            )
+        })
+    }
+
+    /// Checks the settings of a sensor without creating it and returns the quantities it
+    /// measures.
+    pub fn validate(&self, settings: &Value) -> Result<&'static [Quantity]> {
+        (self.validate)(settings)
+    }
+
+    pub async fn init(&self, setup: Setup<Value>) -> Result<Sensor> {
+        (self.init)(setup).await
+    }
+}
+
+/// Sensors without settings have a `null` config, which is treated like an empty object.
+fn parse_settings<C: DeserializeOwned>(settings: &Value) -> Result<C> {
+    let settings = match settings {
+        Value::Null => Value::Object(Default::default()),
+        settings => settings.clone(),
+    };
+
+    serde_json::from_value(settings).context("Invalid sensor settings")
+}
+
+fn validate<M: Model>(settings: &Value) -> Result<&'static [Quantity]> {
+    M::quantities(&parse_settings(settings)?)
+}
+
+fn init<M: Model>(setup: Setup<Value>) -> BoxFuture<'static, Result<Sensor>> {
+    async move {
+        let config = parse_settings(&setup.config)?;
+        let setup = Setup {
+            label: setup.label,
+            address: setup.address,
+            i2c_path: setup.i2c_path,
+            gpio_path: setup.gpio_path,
+            config,
+        };
+        let sensor = M::init(setup).await?;
+
+        Ok(Sensor(Box::new(sensor)))
+    }
+    .boxed()
+}
+
+/// [`Measure`] of a sensor whose model isn't known at compile time.
+trait DynMeasure: Send {
+    fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> BoxFuture<'_, Result<Reading, SensorError>>;
+    fn reinit(&mut self) -> BoxFuture<'_, Result<(), SensorError>>;
+    fn label(&self) -> &str;
+}
+
+impl<M: Model> DynMeasure for M {
+    fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> BoxFuture<'_, Result<Reading, SensorError>> {
+        Measure::measure(self, cancel_token).boxed()
+    }
+
+    fn reinit(&mut self) -> BoxFuture<'_, Result<(), SensorError>> {
+        Measure::reinit(self).boxed()
+    }
+
+    fn label(&self) -> &str {
+        Measure::label(self)
+    }
+}
+
+/// A sensor of any registered model.
+pub struct Sensor(Box<dyn DynMeasure>);
+
+impl Measure for Sensor {
+    type Measurement = Reading;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        self.0.measure(cancel_token).await
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        self.0.reinit().await
+    }
+
+    fn label(&self) -> &str {
+        self.0.label()
+    }
+}
+
+/// A single measurement of a sensor of a registered model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    /// The number of milliseconds since unix epoch, taken at the end of the conversion.
+    pub measure_time: i64,
+    /// The label of the sensor that took this measurement.
+    pub label: String,
+    pub model: &'static str,
+    quantities: &'static [Quantity],
+    values: Vec<Option<f64>>,
+    raw_values: Vec<Option<f64>>,
+}
+
+impl Reading {
+    pub fn new<M: Model>(measure_time: i64, label: String) -> Self {
+        Self::with_quantities::<M>(measure_time, label, M::QUANTITIES)
+    }
+
+    /// Creates a reading of a model whose quantities depend on the settings of the sensor.
+    pub fn with_quantities<M: Model>(
+        measure_time: i64,
+        label: String,
+        quantities: &'static [Quantity],
+    ) -> Self {
+        Self {
+            measure_time,
+            label,
+            model: M::NAME,
+            quantities,
+            values: vec![None; quantities.len()],
+            raw_values: vec![None; quantities.len()],
+        }
+    }
+
+    /// Sets the value of a quantity of the model, values of unknown quantities are ignored.
+    pub fn value(mut self, name: &str, value: f64) -> Self {
+        self.set_quantity(name, Some(value));
+        self
+    }
+
+    /// Returns the quantities of the model with their values and the values before calibration.
+    pub fn values(
+        &self,
+    ) -> impl Iterator<Item = (&'static Quantity, Option<f64>, Option<f64>)> + '_ {
+        self.quantities
+            .iter()
+            .zip(&self.values)
+            .zip(&self.raw_values)
+            .map(|((q, v), r)| (q, *v, *r))
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.quantities.iter().position(|q| q.name == name)
+    }
+
+    fn round(&self, index: usize, value: Option<f64>) -> Option<f64> {
+        match self.quantities[index].ty {
+            QuantityType::Float => value,
+            QuantityType::Integer => value.map(f64::round),
+        }
+    }
+}
+
+impl Quantities for Reading {
+    // the quantities depend on the model, see `quantity_names`
+    const QUANTITIES: &'static [&'static str] = &[];
+    const CALIBRATABLE: &'static [&'static str] = &[];
+
+    fn quantity_names(&self) -> Vec<&'static str> {
+        self.quantities.iter().map(|q| q.name).collect()
+    }
+
+    fn quantity(&self, name: &str) -> Option<f64> {
+        self.index(name).and_then(|i| self.values[i])
+    }
+
+    fn set_quantity(&mut self, name: &str, value: Option<f64>) {
+        if let Some(i) = self.index(name) {
+            self.values[i] = self.round(i, value);
+        }
+    }
+
+    fn set_raw_quantity(&mut self, name: &str, value: Option<f64>) {
+        if let Some(i) = self.index(name) {
+            self.raw_values[i] = self.round(i, value);
+        }
+    }
+}
+
+/// The stored value of a single quantity of a reading.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+pub struct ReadingValue {
+    /// The number of milliseconds since unix epoch.
+    pub measure_time: i64,
+    pub label: String,
+    pub model: String,
+    pub quantity: String,
+    pub unit: String,
+    pub value: Option<f64>,
+    /// The value before calibration, only set if it's calibrated.
+    pub raw_value: Option<f64>,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    /// Counts up by the configured step with every measurement.
+    pub(crate) struct Counter {
+        label: String,
+        step: u32,
+        count: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub(crate) struct CounterConfig {
+        #[serde(default = "default_step")]
+        step: u32,
+    }
+
+    fn default_step() -> u32 {
+        1
+    }
+
+    impl Measure for Counter {
+        type Measurement = Reading;
+
+        async fn measure(&mut self, _: CancellationToken) -> Result<Reading, SensorError> {
+            self.count += self.step;
+            Ok(
+                Reading::new::<Self>(Utc::now().timestamp_millis(), self.label.clone())
+                    .value("count", f64::from(self.count))
+                    .value("rate", 0.5),
+            )
+        }
+
+        async fn reinit(&mut self) -> Result<(), SensorError> {
+            self.count = 0;
+            Ok(())
+        }
+
+        fn label(&self) -> &str {
+            &self.label
+        }
+    }
+
+    impl Model for Counter {
+        const NAME: &'static str = "Counter";
+        const QUANTITIES: &'static [Quantity] = &[
+            Quantity::integer("count", ""),
+            Quantity::float("rate", "1/s"),
+        ];
+        type Config = CounterConfig;
+
+        async fn init(setup: Setup<CounterConfig>) -> Result<Self> {
+            Ok(Self {
+                label: setup.label,
+                step: setup.config.step,
+                count: 0,
+            })
+        }
+    }
+
+    static TEST_MODELS: &[Registration] = &[register::<Counter>()];
+
+    fn setup(config: Value) -> Setup<Value> {
+        Setup {
+            label: "counter".into(),
+            address: I2cAddress::new(0x10),
+            i2c_path: "/dev/null".into(),
+            gpio_path: "/dev/null".into(),
+            config,
+        }
+    }
+
+    #[tokio::test]
+    async fn register_ok() {
+        let model = Registration::find_in(TEST_MODELS, "Counter").unwrap();
+        assert!(Registration::find_in(TEST_MODELS, "Unknown").is_err());
+        assert_eq!(model.validate(&Value::Null).unwrap(), Counter::QUANTITIES);
+        assert!(model.validate(&json!({ "step": 2 })).is_ok());
+        assert!(model.validate(&json!({ "step": "two" })).is_err());
+
+        let mut sensor = model.init(setup(json!({ "step": 2 }))).await.unwrap();
+        let reading = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(sensor.label(), "counter");
+        assert_eq!(reading.model, "Counter");
+        assert_eq!(reading.quantity("count"), Some(2.));
+        assert_eq!(reading.quantity("rate"), Some(0.5));
+    }
+
+    #[test]
+    fn quantities_ok() {
+        let mut reading = Reading::new::<Counter>(0, "counter".into()).value("count", 2.4);
+        assert_eq!(reading.quantity("count"), Some(2.));
+        assert_eq!(reading.quantity("rate"), None);
+        assert_eq!(reading.quantity("unknown"), None);
+
+        reading.set_raw_quantity("count", Some(6.7));
+        reading.set_quantity("rate", Some(1.25));
+        reading.set_quantity("unknown", Some(1.));
+        let values = reading
+            .values()
+            .map(|(q, v, r)| (q.name, v, r))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [("count", Some(2.), Some(7.)), ("rate", Some(1.25), None)]
+        );
+    }
+}
@@ -1,13 +1,39 @@
-use super::{i2c::I2C, Measure, WaterLevelMeasurement};
+use super::{
+    i2c::{I2cAddress, I2C},
+    Measure, RangeStatus, SensorError, WaterLevelMeasurement,
+};
 use anyhow::{bail, Context, Result};
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use std::{path::Path, time::Duration};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
+use tracing::debug;
 
 const IDENTIFICATION_MODEL_ID: u8 = 0xEE;
 const RANGE_SEQUENCE_STEP_DSS: u8 = 0x28;
 const RANGE_SEQUENCE_STEP_PRE_RANGE: u8 = 0x40;
 const RANGE_SEQUENCE_STEP_FINAL_RANGE: u8 = 0x80;
+const RANGE_SEQUENCE_STEP_TCC: u8 = 0x10;
+const RANGE_SEQUENCE_STEP_MSRC: u8 = 0x04;
+
+// distances the sensor reports when it couldn't determine a range
+const DISTANCE_INVALID: u16 = 8190;
+
+// the time the sensor may take to respond, in addition to the timing budget of a measurement
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+
+// overheads of the sequence steps in microseconds
+const TIMING_BUDGET_MIN_US: u64 = 20000;
+/// Longer timing budgets hardly improve the accuracy.
+const TIMING_BUDGET_MAX_US: u64 = 1_000_000;
+const OVERHEAD_START_US: u64 = 1910;
+const OVERHEAD_END_US: u64 = 960;
+const OVERHEAD_MSRC_US: u64 = 660;
+const OVERHEAD_TCC_US: u64 = 590;
+const OVERHEAD_DSS_US: u64 = 690;
+const OVERHEAD_PRE_RANGE_US: u64 = 660;
+const OVERHEAD_FINAL_RANGE_US: u64 = 550;
 
 // register addresses
 const REG_IDENTIFICATION_MODEL_ID: u8 = 0xC0;
@@ -21,32 +47,121 @@ const REG_SYSRANGE_START: u8 = 0x00;
 const REG_RESULT_RANGE_STATUS: u8 = 0x14;
 const REG_MSRC_CONFIG_CONTROL: u8 = 0x60;
 const REG_FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT: u8 = 0x44;
+const REG_MSRC_CONFIG_TIMEOUT_MACROP: u8 = 0x46;
+const REG_PRE_RANGE_CONFIG_VCSEL_PERIOD: u8 = 0x50;
+const REG_PRE_RANGE_CONFIG_TIMEOUT_MACROP_HI: u8 = 0x51;
+const REG_FINAL_RANGE_CONFIG_VCSEL_PERIOD: u8 = 0x70;
+const REG_FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI: u8 = 0x71;
+
+/// A set of ranging settings for a common use case.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum RangingProfile {
+    /// 33 ms timing budget with a signal rate limit of 0.25 MCPS.
+    #[default]
+    Default,
+    /// Lowers the signal rate limit to 0.1 MCPS to measure longer distances, at the cost of
+    /// accuracy and a higher sensitivity to ambient light.
+    LongRange,
+    /// Increases the timing budget to 200 ms.
+    HighAccuracy,
+    /// Decreases the timing budget to 20 ms.
+    HighSpeed,
+}
+
+impl RangingProfile {
+    /// Returns the measurement timing budget of the profile.
+    pub fn timing_budget(&self) -> Duration {
+        match self {
+            RangingProfile::Default | RangingProfile::LongRange => Duration::from_millis(33),
+            RangingProfile::HighAccuracy => Duration::from_millis(200),
+            RangingProfile::HighSpeed => Duration::from_millis(20),
+        }
+    }
+
+    /// Returns the return signal rate limit of the profile in mega counts per second.
+    pub fn signal_rate_limit(&self) -> f64 {
+        match self {
+            RangingProfile::LongRange => 0.1,
+            RangingProfile::Default | RangingProfile::HighAccuracy | RangingProfile::HighSpeed => {
+                0.25
+            }
+        }
+    }
+}
+
+/// The enabled steps of the ranging sequence.
+struct SequenceSteps {
+    tcc: bool,
+    msrc: bool,
+    dss: bool,
+    pre_range: bool,
+    final_range: bool,
+}
+
+impl SequenceSteps {
+    fn new(sequence_config: u8) -> Self {
+        Self {
+            tcc: sequence_config & RANGE_SEQUENCE_STEP_TCC != 0,
+            msrc: sequence_config & RANGE_SEQUENCE_STEP_MSRC != 0,
+            dss: sequence_config & RANGE_SEQUENCE_STEP_DSS != 0,
+            pre_range: sequence_config & RANGE_SEQUENCE_STEP_PRE_RANGE != 0,
+            final_range: sequence_config & RANGE_SEQUENCE_STEP_FINAL_RANGE != 0,
+        }
+    }
+}
 
 // VL53L0X
 pub struct Vl53L0X {
     i2c: I2C,
     label: String,
     stop_variable: u8,
+    timing_budget: Duration,
+    signal_rate_limit: f64,
 }
 
 impl Vl53L0X {
-    pub async fn new(i2c_path: impl AsRef<Path>, address: u8, label: String) -> Result<Self> {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
         let mut i2c = I2C::new(i2c_path, address).await?;
         let stop_variable = Self::init(&mut i2c).await.with_context(|| {
-            format!("Failed to initialize stop variable of VL53L0X at address 0x{address:02x}")
+            format!("Failed to initialize stop variable of VL53L0X at address {address}")
         })?;
 
         Ok(Self {
             i2c,
             label,
             stop_variable,
+            timing_budget: RangingProfile::Default.timing_budget(),
+            signal_rate_limit: RangingProfile::Default.signal_rate_limit(),
         })
     }
 
+    /// Sets the time a single range measurement may take, at least 20 ms. A higher timing budget
+    /// increases the accuracy.
+    pub fn timing_budget(mut self, timing_budget: Duration) -> Self {
+        self.timing_budget = timing_budget;
+        self
+    }
+
+    /// Sets the minimum return signal rate in mega counts per second for a range to be reported
+    /// as valid. Lower values increase the range but also the chance of invalid readings.
+    pub fn signal_rate_limit(mut self, signal_rate_limit: f64) -> Self {
+        self.signal_rate_limit = signal_rate_limit;
+        self
+    }
+
     async fn init(i2c: &mut I2C) -> Result<u8> {
         let device_id = i2c.read_reg_byte(REG_IDENTIFICATION_MODEL_ID).await?;
         if device_id != IDENTIFICATION_MODEL_ID {
-            bail!("Failed to identify VL53L0X sensor");
+            return Err(SensorError::WrongChipId {
+                expected: IDENTIFICATION_MODEL_ID.into(),
+                found: device_id.into(),
+            }
+            .into());
         }
 
         let stop_variable = Vl53L0X::init_data(i2c).await?;
@@ -86,12 +201,6 @@ impl Vl53L0X {
         // disable SIGNAL_RATE_MSRC (bit 1) and SIGNAL_RATE_PRE_RANGE (bit 4) limit checks
         i2c.set_reg_bits(REG_MSRC_CONFIG_CONTROL, 0x12).await?;
 
-        // set final range signal rate limit to 0.25 million counts per second
-        // Q9.7 fixed point format (9 integer bits, 7 fractional bits)
-        //   writeReg16Bit(FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT, limit_Mcps * (1 << 7));
-        i2c.write_reg_u16(REG_FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT, 208)
-            .await?;
-
         Ok(stop_variable)
     }
 
@@ -227,9 +336,13 @@ impl Vl53L0X {
         i2c.write_reg_byte(REG_SYSRANGE_START, sysrange_start)
             .await?;
 
+        let deadline = Instant::now() + POLL_TIMEOUT;
         let mut interrupt_status = i2c.read_reg_byte(REG_RESULT_INTERRUPT_STATUS).await?;
 
         while (interrupt_status & 0x07) == 0 {
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout("the reference calibration".into()).into());
+            }
             tokio::time::sleep(Duration::from_millis(10)).await;
             interrupt_status = i2c.read_reg_byte(REG_RESULT_INTERRUPT_STATUS).await?;
         }
@@ -239,22 +352,190 @@ impl Vl53L0X {
 
         Ok(())
     }
+
+    /// Makes sure that the final range signal rate limit matches the configured one.
+    async fn ensure_signal_rate_limit(&mut self) -> Result<()> {
+        let limit = encode_signal_rate_limit(self.signal_rate_limit);
+        let current = self
+            .i2c
+            .read_reg_u16(REG_FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT)
+            .await?;
+
+        if current != limit {
+            self.i2c
+                .write_reg_u16(REG_FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT, limit)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure that the final range timeout matches the configured timing budget. The
+    /// final range gets the part of the budget that is not used by the other sequence steps.
+    async fn ensure_timing_budget(&mut self) -> Result<()> {
+        let budget_us = u64::try_from(self.timing_budget.as_micros()).unwrap_or(u64::MAX);
+        if !(TIMING_BUDGET_MIN_US..=TIMING_BUDGET_MAX_US).contains(&budget_us) {
+            bail!(
+                "Timing budget must be between {TIMING_BUDGET_MIN_US} and {TIMING_BUDGET_MAX_US} µs, \
+                 got {budget_us} µs"
+            );
+        }
+
+        let steps = SequenceSteps::new(self.i2c.read_reg_byte(REG_SYSTEM_SEQUENCE_CONFIG).await?);
+        if !steps.final_range {
+            return Ok(());
+        }
+
+        let pre_range_vcsel_period = decode_vcsel_period(
+            self.i2c
+                .read_reg_byte(REG_PRE_RANGE_CONFIG_VCSEL_PERIOD)
+                .await?,
+        );
+        let msrc_dss_tcc_mclks = u64::from(
+            self.i2c
+                .read_reg_byte(REG_MSRC_CONFIG_TIMEOUT_MACROP)
+                .await?,
+        ) + 1;
+        let msrc_dss_tcc_us = timeout_mclks_to_us(msrc_dss_tcc_mclks, pre_range_vcsel_period);
+        let pre_range_timeout = self
+            .i2c
+            .read_reg_u16(REG_PRE_RANGE_CONFIG_TIMEOUT_MACROP_HI)
+            .await?;
+        let pre_range_mclks = decode_timeout(pre_range_timeout)
+            .with_context(|| format!("Invalid pre range timeout {pre_range_timeout:#06x}"))?;
+        let pre_range_us = timeout_mclks_to_us(pre_range_mclks, pre_range_vcsel_period);
+        let final_range_vcsel_period = decode_vcsel_period(
+            self.i2c
+                .read_reg_byte(REG_FINAL_RANGE_CONFIG_VCSEL_PERIOD)
+                .await?,
+        );
+
+        let mut used_us = OVERHEAD_START_US + OVERHEAD_END_US + OVERHEAD_FINAL_RANGE_US;
+        if steps.tcc {
+            used_us += msrc_dss_tcc_us + OVERHEAD_TCC_US;
+        }
+        if steps.dss {
+            used_us += 2 * (msrc_dss_tcc_us + OVERHEAD_DSS_US);
+        } else if steps.msrc {
+            used_us += msrc_dss_tcc_us + OVERHEAD_MSRC_US;
+        }
+        if steps.pre_range {
+            used_us += pre_range_us + OVERHEAD_PRE_RANGE_US;
+        }
+        if used_us > budget_us {
+            bail!("Timing budget of {budget_us} µs is too short, the sequence steps need {used_us} µs");
+        }
+
+        // the final range timeout includes the pre range timeout
+        let mut final_range_mclks =
+            timeout_us_to_mclks(budget_us - used_us, final_range_vcsel_period);
+        if steps.pre_range {
+            final_range_mclks += pre_range_mclks;
+        }
+
+        let timeout = encode_timeout(final_range_mclks);
+        let current = self
+            .i2c
+            .read_reg_u16(REG_FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI)
+            .await?;
+        if current != timeout {
+            self.i2c
+                .write_reg_u16(REG_FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI, timeout)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a signal rate limit in mega counts per second into the Q9.7 fixed point format.
+fn encode_signal_rate_limit(limit: f64) -> u16 {
+    (limit.clamp(0., 511.99) * f64::from(1 << 7)) as u16
+}
+
+/// Decodes the VCSEL pulse period in PCLKs from its register value.
+fn decode_vcsel_period(value: u8) -> u32 {
+    (u32::from(value) + 1) << 1
+}
+
+/// Returns the macro period in nanoseconds for the given VCSEL period.
+fn macro_period_ns(vcsel_period_pclks: u32) -> u64 {
+    (2304 * u64::from(vcsel_period_pclks) * 1655 + 500) / 1000
+}
+
+fn timeout_mclks_to_us(timeout_mclks: u64, vcsel_period_pclks: u32) -> u64 {
+    (timeout_mclks * macro_period_ns(vcsel_period_pclks) + 500) / 1000
+}
+
+fn timeout_us_to_mclks(timeout_us: u64, vcsel_period_pclks: u32) -> u64 {
+    let macro_period_ns = macro_period_ns(vcsel_period_pclks);
+    (timeout_us * 1000 + macro_period_ns / 2) / macro_period_ns
+}
+
+/// Decodes a sequence step timeout in MCLKs from the register format
+/// "(LSByte * 2^MSByte) + 1". Returns `None` for timeouts that don't fit into 32 bits, which
+/// the sensor doesn't use.
+fn decode_timeout(value: u16) -> Option<u64> {
+    let (ls_byte, ms_byte) = (u64::from(value & 0xFF), value >> 8);
+    if ms_byte >= 32 || ls_byte << ms_byte > u64::from(u32::MAX) {
+        return None;
+    }
+
+    Some((ls_byte << ms_byte) + 1)
+}
+
+/// Encodes a sequence step timeout in MCLKs into the register format.
+fn encode_timeout(timeout_mclks: u64) -> u16 {
+    if timeout_mclks == 0 {
+        return 0;
+    }
+
+    let mut ls_byte = timeout_mclks - 1;
+    let mut ms_byte = 0;
+    while ls_byte > 0xFF {
+        ls_byte >>= 1;
+        ms_byte += 1;
+    }
+
+    (ms_byte << 8) | (ls_byte as u16)
+}
+
+/// Decodes the status of a range measurement from the range status register.
+fn decode_range_status(value: u8) -> RangeStatus {
+    match (value & 0x78) >> 3 {
+        11 => RangeStatus::Valid,
+        4 => RangeStatus::SignalFail,
+        6 | 9 => RangeStatus::PhaseFail,
+        8 | 10 => RangeStatus::MinRangeFail,
+        1..=3 => RangeStatus::HardwareFail,
+        _ => RangeStatus::Unknown,
+    }
 }
 
 impl Measure for Vl53L0X {
     type Measurement = WaterLevelMeasurement;
 
-    async fn measure(&mut self, cancel_token: CancellationToken) -> Result<Self::Measurement> {
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        self.ensure_signal_rate_limit().await?;
+        self.ensure_timing_budget().await?;
+
         // stop any ongoing measurement
         self.stop_measurement(self.stop_variable).await?;
         // trigger new range measurement
         self.i2c.write_reg_byte(REG_SYSRANGE_START, 0x01).await?;
 
         // wait for the measurement to start
+        let deadline = Instant::now() + POLL_TIMEOUT;
         loop {
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout("the measurement to start".into()));
+            }
             tokio::select! {
                 _ = cancel_token.cancelled() => {
-                    bail!("Measurement cancelled");
+                    return Err(SensorError::Cancelled);
                 },
                 _ = tokio::time::sleep(Duration::from_millis(10)) => {
                     let sysrange_start = self.i2c.read_reg_byte(REG_SYSRANGE_START).await?;
@@ -266,10 +547,14 @@ impl Measure for Vl53L0X {
         }
 
         // wait for the measurement to finish
+        let deadline = Instant::now() + self.timing_budget + POLL_TIMEOUT;
         loop {
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout("the measurement to finish".into()));
+            }
             tokio::select! {
                 _ = cancel_token.cancelled() => {
-                    bail!("Measurement cancelled");
+                    return Err(SensorError::Cancelled);
                 },
                 _ = tokio::time::sleep(Duration::from_millis(10)) => {
                     let interrupt_status = self.i2c.read_reg_byte(REG_RESULT_INTERRUPT_STATUS).await?;
@@ -279,27 +564,191 @@ impl Measure for Vl53L0X {
                 }
             }
         }
-        let measure_time = Utc::now().timestamp();
+        let measure_time = Utc::now().timestamp_millis();
 
         // read measurement result
-        let distance = self
-            .i2c
-            .read_reg_u16(REG_RESULT_RANGE_STATUS + 10)
-            .await?
-            .into();
+        let mut range_status =
+            decode_range_status(self.i2c.read_reg_byte(REG_RESULT_RANGE_STATUS).await?);
+        let distance = self.i2c.read_reg_u16(REG_RESULT_RANGE_STATUS + 10).await?;
 
         // clear interrupt
         self.i2c
             .write_reg_byte(REG_SYSTEM_INTERRUPT_CLEAR, 0x01)
             .await?;
 
+        if range_status == RangeStatus::Valid && distance >= DISTANCE_INVALID {
+            range_status = RangeStatus::SignalFail;
+        }
+
         let measurement =
-            WaterLevelMeasurement::new(measure_time, self.label.clone()).distance(distance);
+            WaterLevelMeasurement::new(measure_time, self.label.clone()).range_status(range_status);
+        if range_status != RangeStatus::Valid {
+            debug!("Discarding distance of {distance} mm with range status {range_status:?}");
+            return Ok(measurement);
+        }
 
-        Ok(measurement)
+        Ok(measurement.distance(distance.into()))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        self.stop_variable = Self::init(&mut self.i2c).await?;
+        Ok(())
     }
 
     fn label(&self) -> &str {
         &self.label
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::i2c::emulator::{Emulator, RegisterMap};
+
+    const ADDRESS: u8 = 0x29;
+
+    fn device() -> RegisterMap {
+        RegisterMap::new()
+            .set(REG_IDENTIFICATION_MODEL_ID, &[IDENTIFICATION_MODEL_ID])
+            .set(0x91, &[0x3C])
+            .set(REG_RESULT_INTERRUPT_STATUS, &[0x07])
+            .set(REG_RESULT_RANGE_STATUS, &[0x58])
+            // a started measurement is picked up by the sensor right away
+            .on_write(REG_SYSRANGE_START, 0x01, &[(REG_SYSRANGE_START, 0x00)])
+    }
+
+    #[test]
+    fn decode_range_status_ok() {
+        assert_eq!(decode_range_status(0x5E), RangeStatus::Valid);
+        assert_eq!(decode_range_status(0x24), RangeStatus::SignalFail);
+        assert_eq!(decode_range_status(0x4C), RangeStatus::PhaseFail);
+        assert_eq!(decode_range_status(0x40), RangeStatus::MinRangeFail);
+        assert_eq!(decode_range_status(0x08), RangeStatus::HardwareFail);
+        assert_eq!(decode_range_status(0x00), RangeStatus::Unknown);
+    }
+
+    #[test]
+    fn timeout_encoding_ok() {
+        assert_eq!(decode_timeout(encode_timeout(1)), Some(1));
+        assert_eq!(decode_timeout(encode_timeout(256)), Some(256));
+        // precision is lost for large timeouts
+        assert_eq!(encode_timeout(1000), 0x02F9);
+        assert_eq!(decode_timeout(0x02F9), Some(997));
+        assert_eq!(encode_timeout(0), 0);
+    }
+
+    #[test]
+    fn timeout_encoding_err() {
+        assert_eq!(decode_timeout(0xFFFF), None);
+        assert_eq!(decode_timeout(0x19FF), None);
+        assert_eq!(decode_timeout(0x1801), Some((1 << 24) + 1));
+        // a timing budget of ten seconds doesn't overflow
+        assert_eq!(timeout_us_to_mclks(10_000_000, 10), 262_254);
+    }
+
+    #[test]
+    fn timeout_conversion_ok() {
+        // final range VCSEL period of 10 PCLKs as set by the default tuning settings
+        let vcsel_period = decode_vcsel_period(0x04);
+        assert_eq!(vcsel_period, 10);
+        assert_eq!(macro_period_ns(vcsel_period), 38131);
+        assert_eq!(timeout_us_to_mclks(20000, vcsel_period), 525);
+        assert_eq!(timeout_mclks_to_us(525, vcsel_period), 20019);
+    }
+
+    #[test]
+    fn signal_rate_limit_ok() {
+        assert_eq!(encode_signal_rate_limit(0.25), 32);
+        assert_eq!(encode_signal_rate_limit(0.1), 12);
+        assert_eq!(encode_signal_rate_limit(-1.), 0);
+    }
+
+    #[tokio::test]
+    async fn measure_ok() {
+        // the reference calibration has to wait for the interrupt once
+        let device = device()
+            .script(REG_RESULT_INTERRUPT_STATUS, &[0x00])
+            .script(REG_RESULT_RANGE_STATUS + 10, &[0x01, 0x1F])
+            .script(REG_RESULT_RANGE_STATUS + 11, &[0xF4, 0xFE]);
+        let path = Emulator::new().device(ADDRESS, device.clone()).attach();
+        let mut sensor = Vl53L0X::new(&path, ADDRESS, "main".into()).await.unwrap();
+        assert_eq!(sensor.stop_variable, 0x3C);
+
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(measurement.range_status, Some(RangeStatus::Valid));
+        assert_eq!(measurement.distance, Some(500));
+        assert_eq!(
+            device.get(REG_FINAL_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT + 1),
+            32
+        );
+        // 645 MCLKs with the periods and timeouts of the default tuning settings
+        assert_eq!(device.get(REG_FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI), 0x02);
+        assert_eq!(
+            device.get(REG_FINAL_RANGE_CONFIG_TIMEOUT_MACROP_HI + 1),
+            0xA1
+        );
+
+        // the sensor reports an invalid distance as valid
+        let measurement = sensor.measure(CancellationToken::new()).await.unwrap();
+        assert_eq!(measurement.range_status, Some(RangeStatus::SignalFail));
+        assert_eq!(measurement.distance, None);
+    }
+
+    #[tokio::test]
+    async fn measure_err() {
+        let path = Emulator::new().device(ADDRESS, device()).attach();
+        let mut sensor = Vl53L0X::new(&path, ADDRESS, "main".into())
+            .await
+            .unwrap()
+            .timing_budget(Duration::from_millis(10));
+        let err = sensor.measure(CancellationToken::new()).await.unwrap_err();
+        assert!(err.to_string().contains("Timing budget must be between"));
+        let mut sensor = sensor.timing_budget(Duration::from_secs(5));
+        assert!(sensor.measure(CancellationToken::new()).await.is_err());
+
+        let mut sensor = sensor.timing_budget(Duration::from_millis(33));
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        assert!(matches!(
+            sensor.measure(cancel_token).await,
+            Err(SensorError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn timeout_err() {
+        // the interrupt is only raised by the reference calibration
+        let device = device()
+            .set(REG_RESULT_INTERRUPT_STATUS, &[0x00])
+            .script(REG_RESULT_INTERRUPT_STATUS, &[0x07, 0x07]);
+        let path = Emulator::new().device(ADDRESS, device.clone()).attach();
+        let mut sensor = Vl53L0X::new(&path, ADDRESS, "main".into()).await.unwrap();
+
+        assert!(matches!(
+            sensor.measure(CancellationToken::new()).await,
+            Err(SensorError::Timeout(_))
+        ));
+        assert!(matches!(
+            sensor.reinit().await,
+            Err(SensorError::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn init_err() {
+        let path = Emulator::new()
+            .device(ADDRESS, device().set(REG_IDENTIFICATION_MODEL_ID, &[0xEA]))
+            .attach();
+        let err = Vl53L0X::new(&path, ADDRESS, "main".into())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(SensorError::WrongChipId {
+                expected: 0xEE,
+                found: 0xEA
+            })
+        ));
+    }
+}
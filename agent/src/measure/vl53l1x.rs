@@ -0,0 +1,373 @@
+use super::{
+    i2c::{I2cAddress, I2C},
+    Measure, RangeStatus, SensorError, WaterLevelMeasurement,
+};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+const MODEL_ID: u16 = 0xEACC;
+const BOOT_RETRIES: u8 = 100;
+const POLL_DURATION: Duration = Duration::from_millis(10);
+// twice the longest timing budget
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+// register addresses
+const REG_VHV_CONFIG_TIMEOUT_MACROP_LOOP_BOUND: u16 = 0x0008;
+const REG_VHV_CONFIG_INIT: u16 = 0x000B;
+const REG_DEFAULT_CONFIGURATION: u16 = 0x002D;
+const REG_GPIO_HV_MUX_CTRL: u16 = 0x0030;
+const REG_GPIO_TIO_HV_STATUS: u16 = 0x0031;
+const REG_PHASECAL_CONFIG_TIMEOUT_MACROP: u16 = 0x004B;
+const REG_RANGE_CONFIG_TIMEOUT_MACROP_A_HI: u16 = 0x005E;
+const REG_RANGE_CONFIG_VCSEL_PERIOD_A: u16 = 0x0060;
+const REG_RANGE_CONFIG_TIMEOUT_MACROP_B_HI: u16 = 0x0061;
+const REG_RANGE_CONFIG_VCSEL_PERIOD_B: u16 = 0x0063;
+const REG_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT_MCPS: u16 = 0x0066;
+const REG_RANGE_CONFIG_VALID_PHASE_HIGH: u16 = 0x0069;
+const REG_SYSTEM_INTERMEASUREMENT_PERIOD: u16 = 0x006C;
+const REG_SD_CONFIG_WOI_SD0: u16 = 0x0078;
+const REG_SD_CONFIG_INITIAL_PHASE_SD0: u16 = 0x007A;
+const REG_SYSTEM_INTERRUPT_CLEAR: u16 = 0x0086;
+const REG_SYSTEM_MODE_START: u16 = 0x0087;
+const REG_RESULT_RANGE_STATUS: u16 = 0x0089;
+const REG_RESULT_FINAL_CROSSTALK_CORRECTED_RANGE_MM_SD0: u16 = 0x0096;
+const REG_RESULT_OSC_CALIBRATE_VAL: u16 = 0x00DE;
+const REG_FIRMWARE_SYSTEM_STATUS: u16 = 0x00E5;
+const REG_IDENTIFICATION_MODEL_ID: u16 = 0x010F;
+
+const MODE_START_RANGING: u8 = 0x40;
+const MODE_STOP_RANGING: u8 = 0x00;
+
+/// Default configuration of the registers 0x2D to 0x87 from the ST ultra lite driver.
+const DEFAULT_CONFIGURATION: [u8; 91] = [
+    0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x02, 0x08, 0x00, 0x08, 0x10, 0x01, 0x01, 0x00, 0x00, 0x00,
+    0x00, 0xFF, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x0B, 0x00, 0x00, 0x02, 0x0A, 0x21,
+    0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0xC8, 0x00, 0x00, 0x38, 0xFF, 0x01, 0x00, 0x08, 0x00,
+    0x00, 0x01, 0xCC, 0x0F, 0x01, 0xF1, 0x0D, 0x01, 0x68, 0x00, 0x80, 0x08, 0xB8, 0x00, 0x00, 0x00,
+    0x00, 0x0F, 0x89, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x0F, 0x0D, 0x0E, 0x0E, 0x00,
+    0x00, 0x02, 0xC7, 0xFF, 0x9B, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00,
+];
+
+/// Timing budgets in ms and the corresponding range timeouts A and B in short distance mode.
+const TIMING_BUDGETS_SHORT: [(u64, u16, u16); 7] = [
+    (15, 0x001D, 0x0027),
+    (20, 0x0051, 0x006E),
+    (33, 0x00D6, 0x006E),
+    (50, 0x01AE, 0x01E8),
+    (100, 0x02E1, 0x0388),
+    (200, 0x03E1, 0x0496),
+    (500, 0x0591, 0x05C1),
+];
+
+/// Timing budgets in ms and the corresponding range timeouts A and B in long distance mode.
+const TIMING_BUDGETS_LONG: [(u64, u16, u16); 6] = [
+    (20, 0x001E, 0x0022),
+    (33, 0x0060, 0x006E),
+    (50, 0x00AD, 0x00C6),
+    (100, 0x01CC, 0x01EA),
+    (200, 0x02D9, 0x02F8),
+    (500, 0x048F, 0x04A4),
+];
+
+/// The distance mode of a VL53L1X.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum DistanceMode {
+    /// Up to 1.3 m, with a better ambient light immunity.
+    Short,
+    /// Up to 4 m in the dark.
+    #[default]
+    Long,
+}
+
+// VL53L1X
+pub struct Vl53L1X {
+    i2c: I2C,
+    label: String,
+    distance_mode: DistanceMode,
+    timing_budget: Duration,
+    signal_rate_limit: Option<f64>,
+    configured: bool,
+}
+
+impl Vl53L1X {
+    pub async fn new(
+        i2c_path: impl AsRef<Path>,
+        address: impl Into<I2cAddress>,
+        label: String,
+    ) -> Result<Self> {
+        let address = address.into();
+        let mut i2c = I2C::new(i2c_path, address).await?;
+        Self::init(&mut i2c)
+            .await
+            .with_context(|| format!("Failed to initialize VL53L1X at address {address}"))?;
+
+        Ok(Self {
+            i2c,
+            label,
+            distance_mode: DistanceMode::default(),
+            timing_budget: Duration::from_millis(100),
+            signal_rate_limit: None,
+            configured: false,
+        })
+    }
+
+    pub fn distance_mode(mut self, distance_mode: DistanceMode) -> Self {
+        self.distance_mode = distance_mode;
+        self
+    }
+
+    /// Sets the time a single range measurement may take. The sensor supports timing budgets of
+    /// 15 (short distance mode only), 20, 33, 50, 100, 200 and 500 ms, other values are rounded
+    /// up to the next supported one.
+    pub fn timing_budget(mut self, timing_budget: Duration) -> Self {
+        self.timing_budget = timing_budget;
+        self
+    }
+
+    /// Sets the minimum return signal rate in mega counts per second for a range to be reported
+    /// as valid.
+    pub fn signal_rate_limit(mut self, signal_rate_limit: f64) -> Self {
+        self.signal_rate_limit = Some(signal_rate_limit);
+        self
+    }
+
+    async fn init(i2c: &mut I2C) -> Result<()> {
+        let mut booted = false;
+        for _ in 0..BOOT_RETRIES {
+            if i2c.read_reg16_byte(REG_FIRMWARE_SYSTEM_STATUS).await? & 0x01 == 0x01 {
+                booted = true;
+                break;
+            }
+            tokio::time::sleep(POLL_DURATION).await;
+        }
+        if !booted {
+            return Err(SensorError::Timeout("the sensor to boot".into()).into());
+        }
+
+        let model_id = i2c.read_reg16_u16(REG_IDENTIFICATION_MODEL_ID).await?;
+        if model_id != MODEL_ID {
+            return Err(SensorError::WrongChipId {
+                expected: MODEL_ID.into(),
+                found: model_id.into(),
+            }
+            .into());
+        }
+
+        i2c.write_reg16_bytes(REG_DEFAULT_CONFIGURATION, &DEFAULT_CONFIGURATION)
+            .await?;
+
+        // the first range measurement calibrates the VHV
+        let cancel_token = CancellationToken::new();
+        i2c.write_reg16_byte(REG_SYSTEM_MODE_START, MODE_START_RANGING)
+            .await?;
+        Self::wait_data_ready(i2c, &cancel_token).await?;
+        i2c.write_reg16_byte(REG_SYSTEM_INTERRUPT_CLEAR, 0x01)
+            .await?;
+        i2c.write_reg16_byte(REG_SYSTEM_MODE_START, MODE_STOP_RANGING)
+            .await?;
+
+        // two bounds VHV and start VHV from the previous temperature
+        i2c.write_reg16_byte(REG_VHV_CONFIG_TIMEOUT_MACROP_LOOP_BOUND, 0x09)
+            .await?;
+        i2c.write_reg16_byte(REG_VHV_CONFIG_INIT, 0x00).await?;
+
+        Ok(())
+    }
+
+    async fn configure(&mut self) -> Result<()> {
+        let (phasecal_timeout, vcsel_period_a, vcsel_period_b, valid_phase_high, woi, phase) =
+            match self.distance_mode {
+                DistanceMode::Short => (0x14, 0x07, 0x05, 0x38, 0x0705, 0x0606),
+                DistanceMode::Long => (0x0A, 0x0F, 0x0D, 0xB8, 0x0F0D, 0x0E0E),
+            };
+
+        self.i2c
+            .write_reg16_byte(REG_PHASECAL_CONFIG_TIMEOUT_MACROP, phasecal_timeout)
+            .await?;
+        self.i2c
+            .write_reg16_byte(REG_RANGE_CONFIG_VCSEL_PERIOD_A, vcsel_period_a)
+            .await?;
+        self.i2c
+            .write_reg16_byte(REG_RANGE_CONFIG_VCSEL_PERIOD_B, vcsel_period_b)
+            .await?;
+        self.i2c
+            .write_reg16_byte(REG_RANGE_CONFIG_VALID_PHASE_HIGH, valid_phase_high)
+            .await?;
+        self.i2c.write_reg16_u16(REG_SD_CONFIG_WOI_SD0, woi).await?;
+        self.i2c
+            .write_reg16_u16(REG_SD_CONFIG_INITIAL_PHASE_SD0, phase)
+            .await?;
+
+        let (budget_ms, timeout_a, timeout_b) =
+            timing_budget_timeouts(self.distance_mode, self.timing_budget);
+        self.i2c
+            .write_reg16_u16(REG_RANGE_CONFIG_TIMEOUT_MACROP_A_HI, timeout_a)
+            .await?;
+        self.i2c
+            .write_reg16_u16(REG_RANGE_CONFIG_TIMEOUT_MACROP_B_HI, timeout_b)
+            .await?;
+
+        // the inter measurement period must not be shorter than the timing budget
+        let clock_pll = self
+            .i2c
+            .read_reg16_u16(REG_RESULT_OSC_CALIBRATE_VAL)
+            .await?
+            & 0x3FF;
+        let period = (f64::from(clock_pll) * (budget_ms as f64) * 1.075) as u32;
+        self.i2c
+            .write_reg16_u32(REG_SYSTEM_INTERMEASUREMENT_PERIOD, period)
+            .await?;
+
+        if let Some(limit) = self.signal_rate_limit {
+            // the register holds the limit in kcps divided by 8
+            let limit = (limit.clamp(0., 524.) * 1000.) as u32 >> 3;
+            self.i2c
+                .write_reg16_u16(REG_RANGE_CONFIG_MIN_COUNT_RATE_RTN_LIMIT_MCPS, limit as u16)
+                .await?;
+        }
+
+        self.configured = true;
+        Ok(())
+    }
+
+    async fn wait_data_ready(i2c: &mut I2C, cancel_token: &CancellationToken) -> Result<()> {
+        // the interrupt pin is active low if bit 4 is set
+        let interrupt_polarity = (!i2c.read_reg16_byte(REG_GPIO_HV_MUX_CTRL).await? >> 4) & 0x01;
+
+        let deadline = Instant::now() + POLL_TIMEOUT;
+        loop {
+            if i2c.read_reg16_byte(REG_GPIO_TIO_HV_STATUS).await? & 0x01 == interrupt_polarity {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(SensorError::Timeout("range data".into()).into());
+            }
+
+            tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    return Err(SensorError::Cancelled.into());
+                }
+                _ = tokio::time::sleep(POLL_DURATION) => {}
+            }
+        }
+    }
+}
+
+/// Returns the supported timing budget in ms that is closest to the given one and the
+/// corresponding range timeouts.
+fn timing_budget_timeouts(distance_mode: DistanceMode, timing_budget: Duration) -> (u64, u16, u16) {
+    let budgets: &[(u64, u16, u16)] = match distance_mode {
+        DistanceMode::Short => &TIMING_BUDGETS_SHORT,
+        DistanceMode::Long => &TIMING_BUDGETS_LONG,
+    };
+    let budget_ms = timing_budget.as_millis() as u64;
+
+    budgets
+        .iter()
+        .find(|(ms, _, _)| *ms >= budget_ms)
+        .copied()
+        .unwrap_or(budgets[budgets.len() - 1])
+}
+
+/// Decodes the status of a range measurement from the range status register.
+fn decode_range_status(value: u8) -> RangeStatus {
+    match value & 0x1F {
+        9 => RangeStatus::Valid,
+        6 => RangeStatus::SigmaFail,
+        4 => RangeStatus::SignalFail,
+        8 => RangeStatus::MinRangeFail,
+        5 | 7 => RangeStatus::PhaseFail,
+        3 => RangeStatus::HardwareFail,
+        _ => RangeStatus::Unknown,
+    }
+}
+
+impl Measure for Vl53L1X {
+    type Measurement = WaterLevelMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        if !self.configured {
+            self.configure().await?;
+        }
+
+        self.i2c
+            .write_reg16_byte(REG_SYSTEM_MODE_START, MODE_START_RANGING)
+            .await?;
+        Self::wait_data_ready(&mut self.i2c, &cancel_token).await?;
+        let measure_time = Utc::now().timestamp_millis();
+
+        let range_status =
+            decode_range_status(self.i2c.read_reg16_byte(REG_RESULT_RANGE_STATUS).await?);
+        let distance = self
+            .i2c
+            .read_reg16_u16(REG_RESULT_FINAL_CROSSTALK_CORRECTED_RANGE_MM_SD0)
+            .await?;
+
+        self.i2c
+            .write_reg16_byte(REG_SYSTEM_INTERRUPT_CLEAR, 0x01)
+            .await?;
+        self.i2c
+            .write_reg16_byte(REG_SYSTEM_MODE_START, MODE_STOP_RANGING)
+            .await?;
+
+        let measurement =
+            WaterLevelMeasurement::new(measure_time, self.label.clone()).range_status(range_status);
+        if range_status != RangeStatus::Valid {
+            debug!("Discarding distance of {distance} mm with range status {range_status:?}");
+            return Ok(measurement);
+        }
+
+        Ok(measurement.distance(distance.into()))
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        Self::init(&mut self.i2c).await?;
+        self.configured = false;
+        Ok(())
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_budget_timeouts_ok() {
+        assert_eq!(
+            timing_budget_timeouts(DistanceMode::Long, Duration::from_millis(100)),
+            (100, 0x01CC, 0x01EA)
+        );
+        assert_eq!(
+            timing_budget_timeouts(DistanceMode::Long, Duration::from_millis(15)),
+            (20, 0x001E, 0x0022)
+        );
+        assert_eq!(
+            timing_budget_timeouts(DistanceMode::Short, Duration::from_millis(40)),
+            (50, 0x01AE, 0x01E8)
+        );
+        assert_eq!(
+            timing_budget_timeouts(DistanceMode::Short, Duration::from_secs(1)),
+            (500, 0x0591, 0x05C1)
+        );
+    }
+
+    #[test]
+    fn decode_range_status_ok() {
+        assert_eq!(decode_range_status(0x09), RangeStatus::Valid);
+        assert_eq!(decode_range_status(0x04), RangeStatus::SignalFail);
+        assert_eq!(decode_range_status(0x07), RangeStatus::PhaseFail);
+        assert_eq!(decode_range_status(0x1F), RangeStatus::Unknown);
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use futures::future::join_all;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{
+        i2c::sensor_address,
+        nutrient::{
+            NutrientSampleConfig, NutrientSensorConfig, NutrientSensorModel,
+            TemperatureCompensation,
+        },
+    },
+    datastore::DataStore,
+    measure::{
+        ezo::TemperatureSource, ezo_ec::EzoEc, ezo_ph::EzoPh, ezo_rtd::EzoRtd, Measure,
+        NutrientMeasurement, SensorError, SensorKind,
+    },
+    sample::{Sampler, Slot},
+};
+
+pub enum NutrientSensor {
+    Ph(EzoPh),
+    Ec(EzoEc),
+    Rtd(EzoRtd),
+}
+
+impl Measure for NutrientSensor {
+    type Measurement = NutrientMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        match self {
+            NutrientSensor::Ph(sensor) => sensor.measure(cancel_token).await,
+            NutrientSensor::Ec(sensor) => sensor.measure(cancel_token).await,
+            NutrientSensor::Rtd(sensor) => sensor.measure(cancel_token).await,
+        }
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        match self {
+            NutrientSensor::Ph(sensor) => sensor.reinit().await,
+            NutrientSensor::Ec(sensor) => sensor.reinit().await,
+            NutrientSensor::Rtd(sensor) => sensor.reinit().await,
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            NutrientSensor::Ph(sensor) => sensor.label(),
+            NutrientSensor::Ec(sensor) => sensor.label(),
+            NutrientSensor::Rtd(sensor) => sensor.label(),
+        }
+    }
+}
+
+pub struct NutrientSampler {
+    receiver: mpsc::Receiver<Vec<NutrientMeasurement>>,
+    sampler: Sampler<NutrientSensor>,
+    store: DataStore,
+}
+
+impl NutrientSampler {
+    pub async fn new(
+        config: &NutrientSampleConfig,
+        i2c_path: &Path,
+        store: DataStore,
+    ) -> Result<Self> {
+        // temperature sensors publish to senders that exist up front, so that pH and EC sensors
+        // can use them as temperature source even if they're absent
+        let temperature_senders = config
+            .sensors
+            .iter()
+            .filter(|(_, c)| c.model == NutrientSensorModel::EzoRtd)
+            .map(|(label, _)| (label.as_str(), Arc::new(watch::channel(None).0)))
+            .collect::<HashMap<_, _>>();
+        for (label, config) in &config.sensors {
+            Self::validate_sensor(config, label, &temperature_senders)?;
+        }
+
+        let sensors = join_all(config.sensors.iter().map(|(label, config)| {
+            let temperature_source = match &config.temperature_compensation {
+                TemperatureCompensation::Off => None,
+                TemperatureCompensation::Fixed { temperature } => {
+                    Some(TemperatureSource::Fixed(*temperature))
+                }
+                TemperatureCompensation::Sensor { label } => Some(TemperatureSource::Measured(
+                    temperature_senders[label.as_str()].subscribe(),
+                )),
+            };
+            let temperature_sender = temperature_senders.get(label.as_str()).cloned();
+            let (config, sensor_label, i2c_path) =
+                (config.clone(), label.clone(), i2c_path.to_owned());
+            Slot::init(label.clone(), move || {
+                Self::init_sensor(
+                    config.clone(),
+                    sensor_label.clone(),
+                    i2c_path.clone(),
+                    temperature_source.clone(),
+                    temperature_sender.clone(),
+                )
+            })
+        }))
+        .await;
+
+        let (sampler, receiver) =
+            Sampler::from_config(SensorKind::Nutrient, config, sensors, store.clone())
+                .context("Failed to initialize nutrient sampler")?;
+
+        Ok(Self {
+            receiver,
+            sampler,
+            store,
+        })
+    }
+
+    pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
+        let mut sampler_handle = tokio::spawn(self.sampler.run(cancel_token.clone()));
+
+        loop {
+            tokio::select! {
+                Some(measurements) = self.receiver.recv() => {
+                    self.store
+                        .add_nutrient_measurements(measurements)
+                        .await
+                        .context("Failed to store nutrient measurements")?;
+                }
+                res = &mut sampler_handle => {
+                    res.context("Nutrient sampler panicked")?
+                        .context("Failed to run nutrient sampler")?;
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn validate_sensor(
+        config: &NutrientSensorConfig,
+        label: &str,
+        temperature_senders: &HashMap<&str, Arc<watch::Sender<Option<f64>>>>,
+    ) -> Result<()> {
+        if let TemperatureCompensation::Sensor { label: rtd_label } =
+            &config.temperature_compensation
+        {
+            if config.model == NutrientSensorModel::EzoRtd {
+                bail!("Temperature of {label:?} nutrient sensor cannot be compensated");
+            }
+            if !temperature_senders.contains_key(rtd_label.as_str()) {
+                bail!("Failed to find temperature sensor {rtd_label:?} used by {label:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn init_sensor(
+        config: NutrientSensorConfig,
+        label: String,
+        i2c_path: PathBuf,
+        temperature_source: Option<TemperatureSource>,
+        temperature_sender: Option<Arc<watch::Sender<Option<f64>>>>,
+    ) -> Result<NutrientSensor> {
+        let address = sensor_address(config.address, config.mux.as_ref());
+        let sensor =
+            match config.model {
+                NutrientSensorModel::EzoPh => EzoPh::new(i2c_path, address, label.clone())
+                    .await
+                    .map(|s| match temperature_source {
+                        Some(source) => NutrientSensor::Ph(s.temperature_source(source)),
+                        None => NutrientSensor::Ph(s),
+                    }),
+                NutrientSensorModel::EzoEc => EzoEc::new(i2c_path, address, label.clone())
+                    .await
+                    .map(|s| match temperature_source {
+                        Some(source) => NutrientSensor::Ec(s.temperature_source(source)),
+                        None => NutrientSensor::Ec(s),
+                    }),
+                NutrientSensorModel::EzoRtd => EzoRtd::new(i2c_path, address, label.clone())
+                    .await
+                    .map(|s| match temperature_sender {
+                        Some(sender) => NutrientSensor::Rtd(s.temperature_sender(sender)),
+                        None => NutrientSensor::Rtd(s),
+                    }),
+            };
+
+        sensor.with_context(|| format!("Failed to initialize {:?} nutrient sensor", label))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn new_absent_ok() {
+        let config = serde_json::from_value(json!({
+            "sample_rate_secs": 600,
+            "sensors": {
+                "ph": {
+                    "model": "EzoPh",
+                    "address": "0x63",
+                    "phase_offset_secs": 30,
+                    "temperature_compensation": { "mode": "Sensor", "label": "reservoir" }
+                },
+                "reservoir": {
+                    "model": "EzoRtd",
+                    "address": "0x66"
+                }
+            }
+        }))
+        .unwrap();
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+
+        // neither circuit responds, the sampler still starts
+        let sampler = NutrientSampler::new(&config, Path::new("/nonexistent"), store).await;
+        assert!(sampler.is_ok());
+    }
+
+    #[tokio::test]
+    async fn new_err() {
+        let config = serde_json::from_value(json!({
+            "sample_rate_secs": 600,
+            "sensors": {
+                "ph": {
+                    "model": "EzoPh",
+                    "address": "0x63",
+                    "temperature_compensation": { "mode": "Sensor", "label": "reservoir" }
+                }
+            }
+        }))
+        .unwrap();
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+
+        let sampler = NutrientSampler::new(&config, Path::new("/nonexistent"), store).await;
+        assert!(sampler.is_err());
+    }
+}
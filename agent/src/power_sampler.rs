@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use futures::future::join_all;
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{
+        i2c::sensor_address,
+        power::{PowerSampleConfig, PowerSensorConfig, PowerSensorModel},
+    },
+    datastore::DataStore,
+    measure::{
+        ina2xx::{Ina2xx, Shunt, Variant},
+        ControllerKind, Measure, PowerMeasurement, SensorError, SensorKind,
+    },
+    sample::{Sampler, Slot},
+};
+
+/// A power sensor and the controller that switches the load it measures.
+pub struct PowerSensor {
+    sensor: Ina2xx,
+    load: Option<(ControllerKind, watch::Receiver<Option<bool>>)>,
+}
+
+impl Measure for PowerSensor {
+    type Measurement = PowerMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        let measurement = self.sensor.measure(cancel_token).await?;
+
+        Ok(match &self.load {
+            Some((controller, state)) => measurement.load(*controller, *state.borrow()),
+            None => measurement,
+        })
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        self.sensor.reinit().await
+    }
+
+    fn label(&self) -> &str {
+        self.sensor.label()
+    }
+}
+
+pub struct PowerSampler {
+    receiver: mpsc::Receiver<Vec<PowerMeasurement>>,
+    sampler: Sampler<PowerSensor>,
+    store: DataStore,
+}
+
+impl PowerSampler {
+    /// Creates a sampler of the configured power sensors, the controller states are used to
+    /// link measurements to the state of the load.
+    pub async fn new(
+        config: &PowerSampleConfig,
+        i2c_path: &Path,
+        controllers: HashMap<ControllerKind, watch::Receiver<Option<bool>>>,
+        store: DataStore,
+    ) -> Result<Self> {
+        for (label, config) in &config.sensors {
+            Self::validate_sensor(config, label, &controllers)?;
+        }
+        let sensors = join_all(config.sensors.iter().map(|(label, config)| {
+            let (config, sensor_label, i2c_path) =
+                (config.clone(), label.clone(), i2c_path.to_owned());
+            let load = config
+                .controller
+                .map(|controller| (controller, controllers[&controller].clone()));
+            Slot::init(label.clone(), move || {
+                Self::init_sensor(
+                    config.clone(),
+                    sensor_label.clone(),
+                    i2c_path.clone(),
+                    load.clone(),
+                )
+            })
+        }))
+        .await;
+
+        let (sampler, receiver) =
+            Sampler::from_config(SensorKind::Power, config, sensors, store.clone())
+                .context("Failed to initialize power sampler")?;
+
+        Ok(Self {
+            receiver,
+            sampler,
+            store,
+        })
+    }
+
+    pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
+        let mut sampler_handle = tokio::spawn(self.sampler.run(cancel_token.clone()));
+
+        loop {
+            tokio::select! {
+                Some(measurements) = self.receiver.recv() => {
+                    self.store
+                        .add_power_measurements(measurements)
+                        .await
+                        .context("Failed to store power measurements")?;
+                }
+                res = &mut sampler_handle => {
+                    res.context("Power sampler panicked")?
+                        .context("Failed to run power sampler")?;
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn validate_sensor(
+        config: &PowerSensorConfig,
+        label: &str,
+        controllers: &HashMap<ControllerKind, watch::Receiver<Option<bool>>>,
+    ) -> Result<()> {
+        Shunt::new(
+            Self::variant(&config.model),
+            config.shunt_resistance,
+            config.max_current,
+        )
+        .with_context(|| format!("Invalid shunt of {label:?} power sensor"))?;
+        if let Some(controller) = config.controller {
+            if !controllers.contains_key(&controller) {
+                bail!("Unknown controller {controller:?} of {label:?} power sensor");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn variant(model: &PowerSensorModel) -> Variant {
+        match model {
+            PowerSensorModel::Ina219 => Variant::Ina219,
+            PowerSensorModel::Ina226 => Variant::Ina226,
+        }
+    }
+
+    async fn init_sensor(
+        config: PowerSensorConfig,
+        label: String,
+        i2c_path: PathBuf,
+        load: Option<(ControllerKind, watch::Receiver<Option<bool>>)>,
+    ) -> Result<PowerSensor> {
+        let variant = Self::variant(&config.model);
+        let shunt = Shunt::new(variant, config.shunt_resistance, config.max_current)?;
+        let sensor = Ina2xx::new(
+            i2c_path,
+            sensor_address(config.address, config.mux.as_ref()),
+            label.clone(),
+            variant,
+        )
+        .await
+        .with_context(|| format!("Failed to initialize {:?} power sensor", label))?;
+
+        Ok(PowerSensor {
+            sensor: sensor.shunt(shunt).averages(config.averages),
+            load,
+        })
+    }
+}
@@ -1,61 +1,422 @@
-use std::time::Duration;
+use std::{collections::HashMap, future::Future, time::Duration};
 
-use anyhow::{bail, Context, Result};
-use futures::future::join_all;
-use tokio::{sync::mpsc, time::interval};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use futures::{
+    future::BoxFuture,
+    stream::{FuturesUnordered, StreamExt},
+    FutureExt,
+};
+use tokio::{
+    sync::mpsc,
+    time::{sleep, timeout, Instant},
+};
 use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
-use crate::measure::Measure;
+use crate::{
+    aggregate::{combine_readings, Limits},
+    calibration::Calibration,
+    config::{
+        aggregate::AggregateConfig,
+        calibration::CalibrationConfig,
+        retry::RetryConfig,
+        sample::{SampleConfig, SensorConfig as _},
+        schedule::ScheduleConfig,
+    },
+    datastore::DataStore,
+    measure::{Measure, Quantities, Recovery, SampleStatus, SensorError, SensorKind},
+};
 
-pub struct Sampler<M: Measure> {
+/// The time a single measurement or initialization may take if the sensor doesn't set one.
+const MEASURE_TIMEOUT_DEFAULT: Duration = Duration::from_secs(30);
+
+/// The points in time at which a sensor takes measurements.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
     period: Duration,
+    offset: Duration,
+    jitter: Duration,
+}
+
+impl Schedule {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            offset: Duration::ZERO,
+            jitter: Duration::ZERO,
+        }
+    }
+
+    /// Creates the schedule of a sensor, falling back to the sample rate of its section.
+    pub fn from_config(sample_rate_secs: u64, config: &ScheduleConfig) -> Self {
+        let period = config.sample_rate_secs.unwrap_or(sample_rate_secs);
+
+        Self::new(Duration::from_secs(period))
+            .offset(Duration::from_secs(config.phase_offset_secs))
+            .jitter(Duration::from_millis(config.jitter_ms))
+    }
+
+    /// Shifts the ticks by a fixed delay.
+    pub fn offset(mut self, offset: Duration) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Delays every tick by a random duration up to the given bound.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.period.is_zero() {
+            bail!("Sample rate cannot be zero");
+        }
+        if self.offset >= self.period {
+            bail!("Phase offset must be smaller than the sample rate");
+        }
+        if self.jitter >= self.period {
+            bail!("Jitter must be smaller than the sample rate");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first tick after the given unix time in milliseconds. Ticks are full
+    /// multiples of the period since the epoch, shifted by the offset.
+    fn next_tick(&self, after: i64) -> i64 {
+        let period = self.period.as_millis() as i64;
+        let offset = self.offset.as_millis() as i64;
+
+        ((after - offset).div_euclid(period) + 1) * period + offset
+    }
+
+    /// Returns the time of the next measurement after the given unix time in milliseconds.
+    fn next_due(&self, after: i64) -> i64 {
+        let jitter = self.jitter.as_millis() as i64;
+        self.next_tick(after) + fastrand::i64(0..=jitter)
+    }
+}
+
+/// Creates a sensor, called again as long as the sensor is absent.
+type Init<M> = Box<dyn FnMut() -> BoxFuture<'static, Result<M>> + Send>;
+
+/// A configured sensor, which is absent until it responds.
+pub enum Slot<M> {
+    Present(M),
+    Absent {
+        label: String,
+        init: Init<M>,
+        error: String,
+    },
+}
+
+impl<M: Measure> Slot<M> {
+    /// Creates a sensor. If it fails, the sensor is marked absent and created again in the
+    /// background instead of preventing the startup.
+    pub async fn init<F, Fut>(label: String, mut init: F) -> Self
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<M>> + Send + 'static,
+    {
+        match init().await {
+            Ok(sensor) => Self::Present(sensor),
+            Err(err) => {
+                warn!("{label:?} sensor is absent, trying again in the background: {err:#}");
+                Self::Absent {
+                    label,
+                    init: Box::new(move || init().boxed()),
+                    error: format!("{err:#}"),
+                }
+            }
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Slot::Present(sensor) => sensor.label(),
+            Slot::Absent { label, .. } => label,
+        }
+    }
+}
+
+impl<M> From<M> for Slot<M> {
+    fn from(sensor: M) -> Self {
+        Self::Present(sensor)
+    }
+}
+
+struct Scheduled<M> {
+    sensor: Slot<M>,
+    schedule: Schedule,
+    limits: Limits,
+    calibration: Calibration,
+    measure_timeout: Duration,
+    due: i64,
+    consecutive_failures: u32,
+    last_reinit: Option<Instant>,
+    /// Whether the sensor has to be initialized before it measures the next time, regardless of
+    /// the number of failures.
+    needs_reinit: bool,
+}
+
+/// What happened while sampling a single sensor.
+struct Outcome<T> {
+    measurement: Result<T>,
+    retries: u32,
+    reinits: u32,
+}
+
+/// How all sensors of a sampler are sampled.
+struct Settings {
+    kind: SensorKind,
+    store: DataStore,
+    retry: RetryConfig,
+    aggregate: AggregateConfig,
+}
+
+pub struct Sampler<M: Measure> {
     sender: mpsc::Sender<Vec<M::Measurement>>,
-    sensors: Vec<M>,
+    sensors: Vec<Scheduled<M>>,
+    settings: Settings,
 }
 
 impl<M> Sampler<M>
 where
     M: Measure,
-    M::Measurement: Send + Sync + 'static,
+    M::Measurement: Quantities + Send + Sync + 'static,
 {
     pub fn new(
-        sample_rate_secs: u64,
+        kind: SensorKind,
         sender: mpsc::Sender<Vec<M::Measurement>>,
-        sensors: Vec<M>,
+        sensors: Vec<(Slot<M>, Schedule)>,
+        store: DataStore,
     ) -> Result<Self> {
-        let period = Duration::from_secs(sample_rate_secs);
-        if !sensors.is_empty() && period.is_zero() {
-            bail!("Sample rate cannot be zero");
-        }
+        let sensors = sensors
+            .into_iter()
+            .map(|(sensor, schedule)| {
+                schedule
+                    .validate()
+                    .with_context(|| format!("Invalid schedule of {:?} sensor", sensor.label()))?;
+
+                Ok(Scheduled {
+                    sensor,
+                    schedule,
+                    limits: Limits::default(),
+                    calibration: Calibration::default(),
+                    measure_timeout: MEASURE_TIMEOUT_DEFAULT,
+                    due: 0,
+                    consecutive_failures: 0,
+                    last_reinit: None,
+                    needs_reinit: false,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(Self {
             sender,
             sensors,
-            period,
+            settings: Settings {
+                kind,
+                store,
+                retry: RetryConfig::default(),
+                aggregate: AggregateConfig::default(),
+            },
         })
     }
 
+    /// Creates the sampler of the sensors of a config section and returns it together with the
+    /// receiver of their measurements.
+    pub fn from_config(
+        kind: SensorKind,
+        config: &impl SampleConfig,
+        sensors: Vec<Slot<M>>,
+        store: DataStore,
+    ) -> Result<(Self, mpsc::Receiver<Vec<M::Measurement>>)> {
+        let limits = Limits::new::<M::Measurement>(config.limits())
+            .context("Invalid plausibility limits")?;
+
+        Self::assemble(kind, config, sensors, store, limits, |_, calibration| {
+            Calibration::new::<M::Measurement>(calibration)
+        })
+    }
+
+    /// Creates the sampler of sensors whose quantities depend on the sensor model. The limits
+    /// apply to every sensor that measures the quantity, `calibratable` returns the quantities
+    /// that can be calibrated by label of the sensor.
+    pub fn from_config_with_quantities<'a>(
+        kind: SensorKind,
+        config: &impl SampleConfig,
+        sensors: Vec<Slot<M>>,
+        store: DataStore,
+        quantities: &[&'static str],
+        calibratable: impl Fn(&str) -> &'a [&'static str],
+    ) -> Result<(Self, mpsc::Receiver<Vec<M::Measurement>>)> {
+        let limits = Limits::with_quantities(quantities, config.limits())
+            .context("Invalid plausibility limits")?;
+
+        Self::assemble(
+            kind,
+            config,
+            sensors,
+            store,
+            limits,
+            |label, calibration| Calibration::with_quantities(calibratable(label), calibration),
+        )
+    }
+
+    fn assemble<C: SampleConfig>(
+        kind: SensorKind,
+        config: &C,
+        sensors: Vec<Slot<M>>,
+        store: DataStore,
+        limits: Limits,
+        calibration: impl Fn(&str, &HashMap<String, CalibrationConfig>) -> Result<Calibration>,
+    ) -> Result<(Self, mpsc::Receiver<Vec<M::Measurement>>)> {
+        let mut calibrations = HashMap::new();
+        let mut measure_timeouts = HashMap::new();
+        let mut scheduled = Vec::with_capacity(sensors.len());
+        for sensor in sensors {
+            let label = sensor.label().to_owned();
+            let c = config
+                .sensors()
+                .get(&label)
+                .with_context(|| format!("{label:?} sensor is not configured"))?;
+            let sensor_calibration = calibration(&label, c.calibration())
+                .with_context(|| format!("Invalid calibration of {label:?} sensor"))?;
+            calibrations.insert(label.clone(), sensor_calibration);
+            if let Some(timeout) = c.measure_timeout_ms() {
+                measure_timeouts.insert(label, Duration::from_millis(timeout));
+            }
+            let schedule = Schedule::from_config(config.sample_rate_secs(), c.schedule());
+            scheduled.push((sensor, schedule));
+        }
+
+        let (sender, receiver) = mpsc::channel(8);
+        let sampler = Self::new(kind, sender, scheduled, store)?
+            .retry(config.retry().clone())
+            .aggregate(config.aggregate().clone())
+            .limits(limits)
+            .calibrations(calibrations)
+            .measure_timeouts(measure_timeouts);
+
+        Ok((sampler, receiver))
+    }
+
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.settings.retry = retry;
+        self
+    }
+
+    /// Sets how multiple readings per sample period are combined.
+    pub fn aggregate(mut self, aggregate: AggregateConfig) -> Self {
+        self.settings.aggregate = aggregate;
+        self
+    }
+
+    /// Sets the plausible ranges of the measured quantities of every sensor.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        for s in &mut self.sensors {
+            s.limits = limits.clone();
+        }
+        self
+    }
+
+    /// Sets the calibrations of the sensors by label.
+    pub fn calibrations(mut self, mut calibrations: HashMap<String, Calibration>) -> Self {
+        for s in &mut self.sensors {
+            if let Some(calibration) = calibrations.remove(s.sensor.label()) {
+                s.calibration = calibration;
+            }
+        }
+        self
+    }
+
+    /// Sets the time a single measurement or initialization may take by label of the sensor.
+    pub fn measure_timeouts(mut self, mut timeouts: HashMap<String, Duration>) -> Self {
+        for s in &mut self.sensors {
+            if let Some(timeout) = timeouts.remove(s.sensor.label()) {
+                s.measure_timeout = timeout;
+            }
+        }
+        self
+    }
+
     pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
         if self.sensors.is_empty() {
             return Ok(());
         }
 
-        let mut interval = interval(self.period);
+        let now = Utc::now();
+        for s in &mut self.sensors {
+            s.due = s.schedule.next_due(now.timestamp_millis());
+
+            // a sensor may have been absent when the agent ran before
+            let (error, present) = match &s.sensor {
+                Slot::Present(_) => (None, true),
+                Slot::Absent { error, .. } => {
+                    s.last_reinit = Some(Instant::now());
+                    s.consecutive_failures = 1;
+                    (Some(error.as_str()), false)
+                }
+            };
+            if let Err(err) = self
+                .settings
+                .store
+                .update_sensor_status(&SampleStatus {
+                    kind: self.settings.kind,
+                    label: s.sensor.label(),
+                    retries: 0,
+                    reinits: 0,
+                    error,
+                    sample_time: now.timestamp_millis(),
+                    present,
+                })
+                .await
+            {
+                warn!("{err:#}");
+            }
+        }
+
+        let Self {
+            sender,
+            sensors,
+            settings,
+        } = self;
+
+        // every sensor is sampled on its own, so that one that hangs doesn't delay the others
+        let mut sensors = sensors
+            .into_iter()
+            .map(|s| Self::run_sensor(&settings, s, &sender, cancel_token.clone()))
+            .collect::<FuturesUnordered<_>>();
+        while let Some(res) = sensors.next().await {
+            res?;
+        }
+
+        Ok(())
+    }
+
+    /// Samples a single sensor on its schedule and sends each measurement as soon as it's
+    /// taken.
+    async fn run_sensor(
+        settings: &Settings,
+        mut scheduled: Scheduled<M>,
+        sender: &mpsc::Sender<Vec<M::Measurement>>,
+        cancel_token: CancellationToken,
+    ) -> Result<()> {
         loop {
-            tokio::select! {
-                _ = interval.tick() => {
-                    let measurements = join_all(
-                        self.sensors
-                            .iter_mut()
-                            .map(|s| Self::measure(s, cancel_token.clone())),
-                    )
-                    .await
-                    .into_iter()
-                    .collect::<Result<Vec<M::Measurement>>>()
-                    .context("Failed to take measurements")?;
+            let delay = (scheduled.due - Utc::now().timestamp_millis()).max(0) as u64;
 
-                    self.sender
-                        .send(measurements)
+            tokio::select! {
+                _ = sleep(Duration::from_millis(delay)) => {
+                    let Some(measurement) =
+                        Self::sample(settings, &mut scheduled, cancel_token.clone()).await
+                    else {
+                        continue;
+                    };
+                    sender
+                        .send(vec![measurement])
                         .await
                         .context("Failed to send measurements")?;
                 }
@@ -66,10 +427,529 @@ where
         }
     }
 
-    async fn measure(sensor: &mut M, cancel_token: CancellationToken) -> Result<M::Measurement> {
-        sensor
-            .measure(cancel_token)
+    /// Samples a single sensor and records failures. Returns `None` if the sensor failed, so
+    /// that the other sensors keep sampling.
+    async fn sample(
+        settings: &Settings,
+        scheduled: &mut Scheduled<M>,
+        cancel_token: CancellationToken,
+    ) -> Option<M::Measurement> {
+        if !Self::join(settings, scheduled).await {
+            scheduled.due = scheduled.schedule.next_due(Utc::now().timestamp_millis());
+            return None;
+        }
+
+        let outcome = Self::try_sample(settings, scheduled, cancel_token.clone()).await;
+        let sample_time = Utc::now().timestamp_millis();
+        // skip ticks that passed while measuring
+        scheduled.due = scheduled.schedule.next_due(Utc::now().timestamp_millis());
+
+        if cancel_token.is_cancelled() {
+            return outcome.measurement.ok();
+        }
+
+        let label = scheduled.sensor.label();
+        let error = match &outcome.measurement {
+            Ok(_) => {
+                if scheduled.consecutive_failures > 0 {
+                    info!(
+                        "{label:?} sensor recovered after {} failed samples",
+                        scheduled.consecutive_failures
+                    );
+                }
+                None
+            }
+            Err(err) => {
+                warn!(
+                    "Sample of {label:?} sensor failed, {} in a row: {err:#}",
+                    scheduled.consecutive_failures + 1
+                );
+                Some(format!("{err:#}"))
+            }
+        };
+
+        if outcome.retries > 0
+            || outcome.reinits > 0
+            || error.is_some()
+            || scheduled.consecutive_failures > 0
+        {
+            if let Err(err) = settings
+                .store
+                .update_sensor_status(&SampleStatus {
+                    kind: settings.kind,
+                    label,
+                    retries: outcome.retries,
+                    reinits: outcome.reinits,
+                    error: error.as_deref(),
+                    sample_time,
+                    present: true,
+                })
+                .await
+            {
+                warn!("{err:#}");
+            }
+        }
+
+        match outcome.measurement {
+            Ok(measurement) => {
+                scheduled.consecutive_failures = 0;
+                Some(measurement)
+            }
+            Err(_) => {
+                scheduled.consecutive_failures += 1;
+                None
+            }
+        }
+    }
+
+    /// Creates an absent sensor again, at most once per reinit interval. Returns whether the
+    /// sensor is present and can be sampled.
+    async fn join(settings: &Settings, scheduled: &mut Scheduled<M>) -> bool {
+        let Slot::Absent { label, init, .. } = &mut scheduled.sensor else {
+            return true;
+        };
+        let reinit_interval = Duration::from_secs(settings.retry.reinit_interval_secs);
+        if scheduled
+            .last_reinit
+            .is_some_and(|last| last.elapsed() < reinit_interval)
+        {
+            return false;
+        }
+        scheduled.last_reinit = Some(Instant::now());
+
+        let sensor = timeout(scheduled.measure_timeout, init())
             .await
-            .with_context(|| format!("Failed to measure with {:?} sensor", sensor.label()))
+            .unwrap_or_else(|_| Err(SensorError::Timeout("the initialization".into()).into()));
+        match sensor {
+            Ok(sensor) => {
+                info!("{label:?} sensor responded, adding it to sampling");
+                scheduled.sensor = Slot::Present(sensor);
+                true
+            }
+            Err(err) => {
+                warn!("{label:?} sensor is still absent: {err:#}");
+                if let Err(err) = settings
+                    .store
+                    .update_sensor_status(&SampleStatus {
+                        kind: settings.kind,
+                        label,
+                        retries: 0,
+                        reinits: 0,
+                        error: Some(&format!("{err:#}")),
+                        sample_time: Utc::now().timestamp_millis(),
+                        present: false,
+                    })
+                    .await
+                {
+                    warn!("{err:#}");
+                }
+                scheduled.consecutive_failures += 1;
+                false
+            }
+        }
+    }
+
+    /// Initializes the sensor again if it failed too often or stopped responding, takes the
+    /// readings of one sample and combines them. The combined reading is calibrated and
+    /// implausible values are removed.
+    async fn try_sample(
+        settings: &Settings,
+        scheduled: &mut Scheduled<M>,
+        cancel_token: CancellationToken,
+    ) -> Outcome<M::Measurement> {
+        let (retry, aggregate) = (&settings.retry, &settings.aggregate);
+        let mut outcome = Outcome {
+            measurement: Err(SensorError::Cancelled.into()),
+            retries: 0,
+            reinits: 0,
+        };
+
+        let Slot::Present(sensor) = &mut scheduled.sensor else {
+            outcome.measurement = Err(anyhow!("Sensor is absent"));
+            return outcome;
+        };
+
+        let reinit_interval = Duration::from_secs(retry.reinit_interval_secs);
+        if (scheduled.needs_reinit || scheduled.consecutive_failures >= retry.reinit_after)
+            && scheduled
+                .last_reinit
+                .map_or(true, |last| last.elapsed() >= reinit_interval)
+        {
+            scheduled.last_reinit = Some(Instant::now());
+            scheduled.needs_reinit = false;
+            outcome.reinits += 1;
+            info!("Initializing {:?} sensor again", sensor.label());
+
+            let reinit = timeout(scheduled.measure_timeout, sensor.reinit())
+                .await
+                .unwrap_or_else(|_| Err(SensorError::Timeout("the initialization".into())));
+            if let Err(err) = reinit {
+                scheduled.needs_reinit = err.recovery() == Recovery::Reinit;
+                let label = sensor.label();
+                outcome.measurement =
+                    Err(err).with_context(|| format!("Failed to initialize {label:?} sensor"));
+                return outcome;
+            }
+        }
+
+        let mut readings = Vec::with_capacity(aggregate.readings as usize);
+        for i in 0..aggregate.readings.max(1) {
+            if i > 0 {
+                tokio::select! {
+                    _ = sleep(Duration::from_millis(aggregate.interval_ms)) => {}
+                    _ = cancel_token.cancelled() => break,
+                }
+            }
+
+            let reading = Self::measure(
+                retry,
+                sensor,
+                scheduled.measure_timeout,
+                &mut outcome.retries,
+                cancel_token.clone(),
+            )
+            .await;
+            match reading {
+                Ok(mut reading) => {
+                    scheduled
+                        .limits
+                        .check_range(&mut reading, &scheduled.calibration);
+                    readings.push(reading);
+                }
+                Err(err) => {
+                    let recovery = err.recovery();
+                    let label = sensor.label();
+                    outcome.measurement = Err(err)
+                        .with_context(|| format!("Failed to measure with {label:?} sensor"));
+                    match recovery {
+                        Recovery::Retry => {}
+                        Recovery::Reinit => {
+                            scheduled.needs_reinit = true;
+                            break;
+                        }
+                        Recovery::GiveUp => break,
+                    }
+                }
+            }
+        }
+
+        if let Some(mut measurement) = combine_readings(&aggregate.method, readings) {
+            scheduled.calibration.calibrate(&mut measurement);
+            scheduled
+                .limits
+                .check_rate(&mut measurement, Utc::now().timestamp_millis());
+            outcome.measurement = Ok(measurement);
+        }
+
+        outcome
+    }
+
+    /// Takes a single reading within the timeout of the sensor, retrying with exponential backoff
+    /// as long as the failure is likely transient.
+    async fn measure(
+        retry: &RetryConfig,
+        sensor: &mut M,
+        measure_timeout: Duration,
+        retries: &mut u32,
+        cancel_token: CancellationToken,
+    ) -> Result<M::Measurement, SensorError> {
+        let mut backoff = Duration::from_millis(retry.backoff_ms);
+        let mut attempt = 0;
+        loop {
+            let reading = timeout(measure_timeout, sensor.measure(cancel_token.clone()))
+                .await
+                .unwrap_or_else(|_| Err(SensorError::Timeout("the measurement".into())));
+            let err = match reading {
+                Err(err) if err.recovery() == Recovery::Retry && attempt < retry.retries => err,
+                reading => return reading,
+            };
+
+            tokio::select! {
+                _ = sleep(backoff) => {}
+                _ = cancel_token.cancelled() => return Err(err),
+            }
+            backoff *= 2;
+            attempt += 1;
+            *retries += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::light::LightSampleConfig,
+        measure::{I2cError, LightMeasurement},
+    };
+    use nix::libc;
+    use std::{collections::VecDeque, io};
+
+    /// Fails with the given errors before it measures successfully.
+    struct Flaky {
+        label: &'static str,
+        errors: VecDeque<SensorError>,
+        delay: Duration,
+        measures: u32,
+        reinits: u32,
+    }
+
+    impl Flaky {
+        fn new(errors: impl IntoIterator<Item = SensorError>) -> Self {
+            Self {
+                label: "flaky",
+                errors: errors.into_iter().collect(),
+                delay: Duration::ZERO,
+                measures: 0,
+                reinits: 0,
+            }
+        }
+    }
+
+    impl Measure for Flaky {
+        type Measurement = LightMeasurement;
+
+        async fn measure(&mut self, _: CancellationToken) -> Result<LightMeasurement, SensorError> {
+            self.measures += 1;
+            sleep(self.delay).await;
+            match self.errors.pop_front() {
+                Some(err) => Err(err),
+                None => Ok(LightMeasurement::new(0, self.label().into())),
+            }
+        }
+
+        async fn reinit(&mut self) -> Result<(), SensorError> {
+            self.reinits += 1;
+            Ok(())
+        }
+
+        fn label(&self) -> &str {
+            self.label
+        }
+    }
+
+    async fn sampler(sensor: Flaky) -> Sampler<Flaky> {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let (sender, _) = mpsc::channel(1);
+        let schedule = Schedule::new(Duration::from_secs(60));
+
+        Sampler::new(
+            SensorKind::Light,
+            sender,
+            vec![(sensor.into(), schedule)],
+            store,
+        )
+        .unwrap()
+        .retry(RetryConfig {
+            backoff_ms: 1,
+            ..Default::default()
+        })
+    }
+
+    fn present(scheduled: &mut Scheduled<Flaky>) -> &mut Flaky {
+        match &mut scheduled.sensor {
+            Slot::Present(sensor) => sensor,
+            Slot::Absent { .. } => panic!("Sensor is absent"),
+        }
+    }
+
+    #[test]
+    fn next_tick_ok() {
+        let schedule = Schedule::new(Duration::from_secs(60));
+        assert_eq!(schedule.next_tick(0), 60_000);
+        assert_eq!(schedule.next_tick(59_999), 60_000);
+        assert_eq!(schedule.next_tick(60_000), 120_000);
+
+        let schedule = schedule.offset(Duration::from_secs(15));
+        assert_eq!(schedule.next_tick(0), 15_000);
+        assert_eq!(schedule.next_tick(15_000), 75_000);
+        assert_eq!(schedule.next_tick(80_000), 135_000);
+    }
+
+    #[test]
+    fn validate_ok() {
+        let schedule = Schedule::new(Duration::from_secs(60));
+        assert!(schedule.validate().is_ok());
+        assert!(Schedule::new(Duration::ZERO).validate().is_err());
+        assert!(schedule
+            .clone()
+            .offset(Duration::from_secs(60))
+            .validate()
+            .is_err());
+        assert!(schedule.jitter(Duration::from_secs(60)).validate().is_err());
+    }
+
+    fn light_config(sensor: serde_json::Value) -> LightSampleConfig {
+        serde_json::from_value(serde_json::json!({
+            "sample_rate_secs": 60,
+            "sensors": { "flaky": sensor }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn from_config_ok() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let config = light_config(serde_json::json!({
+            "model": "Bh1750Fvi",
+            "address": "0x23",
+            "sample_rate_secs": 30,
+            "measure_timeout_ms": 10
+        }));
+        let (sampler, _) = Sampler::from_config(
+            SensorKind::Light,
+            &config,
+            vec![Flaky::new([]).into()],
+            store,
+        )
+        .unwrap();
+
+        // the sensor settings override the ones of the section
+        let scheduled = &sampler.sensors[0];
+        assert_eq!(scheduled.schedule, Schedule::new(Duration::from_secs(30)));
+        assert_eq!(scheduled.measure_timeout, Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn from_config_err() {
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let config = light_config(serde_json::json!({
+            "model": "Bh1750Fvi",
+            "address": "0x23",
+            "calibration": { "unknown": { "mode": "Linear", "offset": 1.0 } }
+        }));
+        let sensors = vec![Flaky::new([]).into()];
+        assert!(Sampler::from_config(SensorKind::Light, &config, sensors, store.clone()).is_err());
+
+        let config = light_config(serde_json::json!({ "model": "Bh1750Fvi", "address": "0x23" }));
+        let unconfigured = Flaky {
+            label: "unconfigured",
+            ..Flaky::new([])
+        };
+        let sensors = vec![unconfigured.into()];
+        assert!(Sampler::from_config(SensorKind::Light, &config, sensors, store).is_err());
+    }
+
+    #[tokio::test]
+    async fn recovery_ok() {
+        let not_found =
+            SensorError::from(I2cError::Read(io::Error::from_raw_os_error(libc::ENXIO)));
+        let mut sampler = sampler(Flaky::new([SensorError::Crc, not_found])).await;
+        let (settings, scheduled) = (&sampler.settings, &mut sampler.sensors[0]);
+
+        // a checksum mismatch is retried, a sensor that stops responding is not
+        let outcome = Sampler::try_sample(settings, scheduled, CancellationToken::new()).await;
+        assert!(outcome.measurement.is_err());
+        assert_eq!((outcome.retries, outcome.reinits), (1, 0));
+        assert!(scheduled.needs_reinit);
+
+        // it's initialized again before the next sample
+        let outcome = Sampler::try_sample(settings, scheduled, CancellationToken::new()).await;
+        assert!(outcome.measurement.is_ok());
+        assert_eq!((outcome.retries, outcome.reinits), (0, 1));
+        assert!(!scheduled.needs_reinit);
+        let sensor = present(scheduled);
+        assert_eq!((sensor.measures, sensor.reinits), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn give_up_ok() {
+        let wrong_chip = SensorError::WrongChipId {
+            expected: 0x61,
+            found: 0x60,
+        };
+        let mut sampler = sampler(Flaky::new([wrong_chip])).await;
+        let (settings, scheduled) = (&sampler.settings, &mut sampler.sensors[0]);
+
+        let outcome = Sampler::try_sample(settings, scheduled, CancellationToken::new()).await;
+        assert!(outcome.measurement.is_err());
+        assert_eq!((outcome.retries, outcome.reinits), (0, 0));
+        assert!(!scheduled.needs_reinit);
+        assert_eq!(present(scheduled).measures, 1);
+    }
+
+    #[tokio::test]
+    async fn measure_timeout_ok() {
+        let timeouts = HashMap::from([("flaky".into(), Duration::from_millis(10))]);
+        let mut sampler = sampler(Flaky::new([])).await.measure_timeouts(timeouts);
+        let (settings, scheduled) = (&sampler.settings, &mut sampler.sensors[0]);
+        present(scheduled).delay = Duration::from_secs(1);
+
+        // a sensor that hangs is not retried but initialized again
+        let outcome = Sampler::try_sample(settings, scheduled, CancellationToken::new()).await;
+        let err = outcome.measurement.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(SensorError::Timeout(_))));
+        assert_eq!(outcome.retries, 0);
+        assert!(scheduled.needs_reinit);
+    }
+
+    #[tokio::test]
+    async fn hanging_ok() {
+        let hanging = Flaky {
+            label: "hanging",
+            delay: Duration::from_secs(60),
+            ..Flaky::new([])
+        };
+        let healthy = Flaky {
+            label: "healthy",
+            ..Flaky::new([])
+        };
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let (sender, mut receiver) = mpsc::channel(16);
+        let schedule = Schedule::new(Duration::from_millis(100));
+        let sensors = vec![
+            (hanging.into(), schedule.clone()),
+            (healthy.into(), schedule),
+        ];
+        let timeouts = HashMap::from([("hanging".into(), Duration::from_secs(1))]);
+        let sampler = Sampler::new(SensorKind::Light, sender, sensors, store)
+            .unwrap()
+            .measure_timeouts(timeouts);
+
+        let cancel_token = CancellationToken::new();
+        let handle = tokio::spawn(sampler.run(cancel_token.clone()));
+        sleep(Duration::from_millis(550)).await;
+        cancel_token.cancel();
+        handle.await.unwrap().unwrap();
+
+        // the healthy sensor keeps its cadence while the other one hangs
+        let mut labels = Vec::new();
+        while let Some(measurements) = receiver.recv().await {
+            labels.extend(measurements.into_iter().map(|m| m.label));
+        }
+        assert!(labels.len() >= 4, "{labels:?}");
+        assert!(labels.iter().all(|label| label == "healthy"));
+    }
+
+    #[tokio::test]
+    async fn absent_ok() {
+        let mut attempts = 0;
+        let slot = Slot::init("flaky".into(), move || {
+            attempts += 1;
+            async move {
+                match attempts {
+                    1 => bail!("No response"),
+                    _ => Ok(Flaky::new([])),
+                }
+            }
+        })
+        .await;
+        assert!(matches!(slot, Slot::Absent { .. }));
+
+        let store = DataStore::new("sqlite::memory:").await.unwrap();
+        let (sender, _) = mpsc::channel(1);
+        let schedule = Schedule::new(Duration::from_secs(60));
+        let mut sampler = Sampler::new(SensorKind::Light, sender, vec![(slot, schedule)], store)
+            .unwrap()
+            .retry(RetryConfig {
+                reinit_interval_secs: 0,
+                ..Default::default()
+            });
+        let (settings, scheduled) = (&sampler.settings, &mut sampler.sensors[0]);
+
+        // the sensor joins sampling once it responds
+        let measurement = Sampler::sample(settings, scheduled, CancellationToken::new()).await;
+        assert!(measurement.is_some());
+        assert_eq!(present(scheduled).measures, 1);
     }
 }
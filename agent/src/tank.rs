@@ -0,0 +1,189 @@
+use anyhow::{bail, Result};
+
+use crate::config::water_level::{TankConfig, TankShape};
+
+/// Cubic millimetres per litre.
+const MM3_PER_LITRE: f64 = 1_000_000.;
+
+/// How the volume is derived from the water level.
+#[derive(Debug)]
+enum Volume {
+    /// A tank with vertical walls and the given base area in mm².
+    Area(f64),
+    /// Pairs of distance in mm and volume in litres, ordered by distance.
+    Table(Vec<(f64, f64)>),
+}
+
+/// The geometry of a tank that is used to convert the distance between a sensor and the water
+/// surface into a fill level and volume.
+#[derive(Debug)]
+pub struct Tank {
+    empty_distance: f64,
+    full_distance: f64,
+    volume: Volume,
+}
+
+impl Tank {
+    pub fn new(config: &TankConfig) -> Result<Self> {
+        if config.empty_distance <= config.full_distance {
+            bail!("The empty distance of a tank must be greater than the full distance");
+        }
+
+        let volume = match &config.shape {
+            TankShape::Rectangular { length, width } => {
+                if *length <= 0. || *width <= 0. {
+                    bail!("The length and width of a rectangular tank must be positive");
+                }
+                Volume::Area(length * width)
+            }
+            TankShape::Cylinder { diameter } => {
+                if *diameter <= 0. {
+                    bail!("The diameter of a cylindrical tank must be positive");
+                }
+                Volume::Area(std::f64::consts::PI * (diameter / 2.).powi(2))
+            }
+            TankShape::Table { points } => {
+                if points.len() < 2 {
+                    bail!("The calibration table of a tank needs at least two points");
+                }
+                let mut points: Vec<_> = points
+                    .iter()
+                    .map(|p| (p.distance as f64, p.volume))
+                    .collect();
+                points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                Volume::Table(points)
+            }
+        };
+
+        Ok(Self {
+            empty_distance: config.empty_distance as f64,
+            full_distance: config.full_distance as f64,
+            volume,
+        })
+    }
+
+    /// Returns the fill level in percent of the height between the empty and full distance.
+    pub fn fill_level(&self, distance: u32) -> f64 {
+        let level = (self.empty_distance - distance as f64)
+            / (self.empty_distance - self.full_distance)
+            * 100.;
+
+        level.clamp(0., 100.)
+    }
+
+    /// Returns the volume of the water in litres.
+    pub fn volume(&self, distance: u32) -> f64 {
+        let distance = distance as f64;
+
+        match &self.volume {
+            Volume::Area(area) => {
+                let height = (self.empty_distance - distance)
+                    .clamp(0., self.empty_distance - self.full_distance);
+                area * height / MM3_PER_LITRE
+            }
+            Volume::Table(points) => interpolate(points, distance),
+        }
+    }
+}
+
+/// Interpolates linearly between the points, values outside of the table are clamped to the
+/// first or last point.
+fn interpolate(points: &[(f64, f64)], x: f64) -> f64 {
+    let upper = points.partition_point(|(px, _)| *px < x);
+    if upper == 0 {
+        return points[0].1;
+    }
+    if upper == points.len() {
+        return points[points.len() - 1].1;
+    }
+
+    let (x0, y0) = points[upper - 1];
+    let (x1, y1) = points[upper];
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::water_level::TankCalibrationPoint;
+
+    #[test]
+    fn rectangular_ok() {
+        let tank = Tank::new(&TankConfig {
+            empty_distance: 600,
+            full_distance: 100,
+            shape: TankShape::Rectangular {
+                length: 400.,
+                width: 300.,
+            },
+        })
+        .unwrap();
+
+        assert_eq!(tank.fill_level(350), 50.);
+        assert_eq!(tank.volume(350), 30.);
+        assert_eq!(tank.fill_level(700), 0.);
+        assert_eq!(tank.volume(700), 0.);
+        assert_eq!(tank.fill_level(50), 100.);
+        assert_eq!(tank.volume(50), 60.);
+    }
+
+    #[test]
+    fn cylinder_ok() {
+        let tank = Tank::new(&TankConfig {
+            empty_distance: 1000,
+            full_distance: 100,
+            shape: TankShape::Cylinder { diameter: 500. },
+        })
+        .unwrap();
+
+        assert_eq!(tank.fill_level(100), 100.);
+        assert!((tank.volume(100) - 176.715).abs() < 1e-3);
+    }
+
+    #[test]
+    fn table_ok() {
+        let tank = Tank::new(&TankConfig {
+            empty_distance: 800,
+            full_distance: 100,
+            shape: TankShape::Table {
+                points: vec![
+                    TankCalibrationPoint {
+                        distance: 800,
+                        volume: 0.,
+                    },
+                    TankCalibrationPoint {
+                        distance: 100,
+                        volume: 200.,
+                    },
+                    TankCalibrationPoint {
+                        distance: 400,
+                        volume: 120.,
+                    },
+                ],
+            },
+        })
+        .unwrap();
+
+        assert_eq!(tank.volume(250), 160.);
+        assert_eq!(tank.volume(600), 60.);
+        assert_eq!(tank.volume(50), 200.);
+        assert_eq!(tank.volume(900), 0.);
+    }
+
+    #[test]
+    fn invalid_geometry() {
+        let config = TankConfig {
+            empty_distance: 100,
+            full_distance: 100,
+            shape: TankShape::Cylinder { diameter: 500. },
+        };
+        assert!(Tank::new(&config).is_err());
+
+        let config = TankConfig {
+            empty_distance: 500,
+            full_distance: 100,
+            shape: TankShape::Table { points: vec![] },
+        };
+        assert!(Tank::new(&config).is_err());
+    }
+}
@@ -1,24 +1,78 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use crate::{
-    config::water_level::{WaterLevelConfig, WaterLevelSensorConfig, WaterLevelSensorModel},
+    config::{
+        i2c::sensor_address,
+        water_level::{
+            UltrasonicInterface, WaterLevelConfig, WaterLevelSensorConfig, WaterLevelSensorModel,
+        },
+    },
     control::Controller,
     datastore::DataStore,
-    measure::{vl53l0x::Vl53L0X, WaterLevelMeasurement},
-    sample::Sampler,
+    measure::{
+        jsn_sr04t::JsnSr04T, vl53l0x::Vl53L0X, vl53l1x::Vl53L1X, Measure, SensorError, SensorKind,
+        WaterLevelMeasurement,
+    },
+    sample::{Sampler, Slot},
+    tank::Tank,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use futures::future::join_all;
-use tokio::{sync::mpsc, task::JoinSet};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinSet,
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug_span, Instrument};
 
+pub enum WaterLevelSensor {
+    Vl53L0X(Vl53L0X),
+    Vl53L1X(Vl53L1X),
+    JsnSr04T(JsnSr04T),
+}
+
+impl Measure for WaterLevelSensor {
+    type Measurement = WaterLevelMeasurement;
+
+    async fn measure(
+        &mut self,
+        cancel_token: CancellationToken,
+    ) -> Result<Self::Measurement, SensorError> {
+        match self {
+            WaterLevelSensor::Vl53L0X(sensor) => sensor.measure(cancel_token).await,
+            WaterLevelSensor::Vl53L1X(sensor) => sensor.measure(cancel_token).await,
+            WaterLevelSensor::JsnSr04T(sensor) => sensor.measure(cancel_token).await,
+        }
+    }
+
+    async fn reinit(&mut self) -> Result<(), SensorError> {
+        match self {
+            WaterLevelSensor::Vl53L0X(sensor) => sensor.reinit().await,
+            WaterLevelSensor::Vl53L1X(sensor) => sensor.reinit().await,
+            WaterLevelSensor::JsnSr04T(sensor) => sensor.reinit().await,
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            WaterLevelSensor::Vl53L0X(sensor) => sensor.label(),
+            WaterLevelSensor::Vl53L1X(sensor) => sensor.label(),
+            WaterLevelSensor::JsnSr04T(sensor) => sensor.label(),
+        }
+    }
+}
+
 pub struct WaterLevelManager {
     controller: Controller,
     receiver: mpsc::Receiver<Vec<WaterLevelMeasurement>>,
-    sampler: Sampler<Vl53L0X>,
+    sampler: Sampler<WaterLevelSensor>,
     store: DataStore,
+    tanks: HashMap<String, Tank>,
 }
 
 impl WaterLevelManager {
@@ -31,29 +85,55 @@ impl WaterLevelManager {
         let controller = Controller::new(&config.control, &gpio_path)
             .context("Failed to initialize water level controller")?;
 
-        let sensors = join_all(
-            config
-                .sample
-                .sensors
-                .iter()
-                .map(|(label, config)| Self::init_sensor(config, label, i2c_path)),
-        )
-        .await
-        .into_iter()
-        .collect::<Result<Vec<Vl53L0X>>>()?;
+        let mut tanks = HashMap::new();
+        for (label, config) in &config.sample.sensors {
+            if let Some(tank) = &config.tank {
+                let tank = Tank::new(tank).with_context(|| {
+                    format!("Invalid tank geometry of {label:?} water level sensor")
+                })?;
+                tanks.insert(label.clone(), tank);
+            }
+        }
 
-        let (sender, receiver) = mpsc::channel(8);
-        let sampler = Sampler::new(config.sample.sample_rate_secs, sender, sensors)
-            .context("Failed to initialize water level sampler")?;
+        for (label, config) in &config.sample.sensors {
+            Self::validate_sensor(config, label)?;
+        }
+        let sensors = join_all(config.sample.sensors.iter().map(|(label, config)| {
+            let (config, sensor_label) = (config.clone(), label.clone());
+            let (i2c_path, gpio_path) = (i2c_path.to_owned(), gpio_path.as_ref().to_owned());
+            Slot::init(label.clone(), move || {
+                Self::init_sensor(
+                    config.clone(),
+                    sensor_label.clone(),
+                    i2c_path.clone(),
+                    gpio_path.clone(),
+                )
+            })
+        }))
+        .await;
+
+        let (sampler, receiver) = Sampler::from_config(
+            SensorKind::WaterLevel,
+            &config.sample,
+            sensors,
+            store.clone(),
+        )
+        .context("Failed to initialize water level sampler")?;
 
         Ok(Self {
             controller,
             receiver,
             sampler,
             store,
+            tanks,
         })
     }
 
+    /// Returns the state of the water level control pin.
+    pub fn controller_state(&self) -> watch::Receiver<Option<bool>> {
+        self.controller.state()
+    }
+
     pub async fn run(mut self, cancel_token: CancellationToken) -> Result<()> {
         let mut set = JoinSet::new();
         set.spawn(
@@ -79,6 +159,14 @@ impl WaterLevelManager {
                     }
                 }
                 Some(measurements) = self.receiver.recv() => {
+                    let measurements = measurements
+                        .into_iter()
+                        .map(|m| match self.tanks.get(&m.label) {
+                            Some(tank) => m.derive_fill(tank),
+                            None => m,
+                        })
+                        .collect();
+
                     self.store
                         .add_water_level_measurements(measurements)
                         .await
@@ -88,17 +176,98 @@ impl WaterLevelManager {
         }
     }
 
-    async fn init_sensor(
-        config: &WaterLevelSensorConfig,
-        label: &str,
-        i2c_path: impl AsRef<Path>,
-    ) -> Result<Vl53L0X> {
+    fn validate_sensor(config: &WaterLevelSensorConfig, label: &str) -> Result<()> {
+        if let Some(limit) = config.signal_rate_limit {
+            if !(0. ..512.).contains(&limit) {
+                bail!(
+                    "Signal rate limit of {label:?} water level sensor must be between 0 and 512 MCPS"
+                );
+            }
+        }
+
         match config.model {
             WaterLevelSensorModel::Vl53L0X => {
-                Vl53L0X::new(i2c_path, config.address, label.to_owned())
-                    .await
-                    .with_context(|| format!("Failed to initialize {:?} water level sensor", label))
+                if !(Duration::from_millis(20)..=Duration::from_secs(1))
+                    .contains(&Self::timing_budget(config))
+                {
+                    bail!(
+                        "Timing budget of {label:?} water level sensor must be between 20 and \
+                         1000 ms"
+                    );
+                }
+            }
+            WaterLevelSensorModel::Vl53L1X => {}
+            WaterLevelSensorModel::JsnSr04T => {
+                if config.interface.is_none() {
+                    bail!("Interface of {label:?} water level sensor is not configured");
+                }
             }
         }
+
+        Ok(())
+    }
+
+    fn timing_budget(config: &WaterLevelSensorConfig) -> Duration {
+        config
+            .timing_budget_ms
+            .map(Duration::from_millis)
+            .unwrap_or(config.profile.timing_budget())
+    }
+
+    async fn init_sensor(
+        config: WaterLevelSensorConfig,
+        label: String,
+        i2c_path: PathBuf,
+        gpio_path: PathBuf,
+    ) -> Result<WaterLevelSensor> {
+        let timing_budget = Self::timing_budget(&config);
+        let sensor = match config.model {
+            WaterLevelSensorModel::Vl53L0X => {
+                let signal_rate_limit = config
+                    .signal_rate_limit
+                    .unwrap_or(config.profile.signal_rate_limit());
+
+                Vl53L0X::new(
+                    i2c_path,
+                    sensor_address(config.address, config.mux.as_ref()),
+                    label.clone(),
+                )
+                .await
+                .map(|s| {
+                    WaterLevelSensor::Vl53L0X(
+                        s.timing_budget(timing_budget)
+                            .signal_rate_limit(signal_rate_limit),
+                    )
+                })
+            }
+            WaterLevelSensorModel::Vl53L1X => Vl53L1X::new(
+                i2c_path,
+                sensor_address(config.address, config.mux.as_ref()),
+                label.clone(),
+            )
+            .await
+            .map(|s| {
+                let s = s
+                    .distance_mode(config.distance_mode)
+                    .timing_budget(timing_budget);
+                match config.signal_rate_limit {
+                    Some(limit) => WaterLevelSensor::Vl53L1X(s.signal_rate_limit(limit)),
+                    None => WaterLevelSensor::Vl53L1X(s),
+                }
+            }),
+            WaterLevelSensorModel::JsnSr04T => match &config.interface {
+                Some(UltrasonicInterface::Uart { path }) => {
+                    JsnSr04T::uart(path, label.clone()).map(WaterLevelSensor::JsnSr04T)
+                }
+                Some(UltrasonicInterface::Gpio {
+                    trigger_pin,
+                    echo_pin,
+                }) => JsnSr04T::gpio(gpio_path, *trigger_pin, *echo_pin, label.clone())
+                    .map(WaterLevelSensor::JsnSr04T),
+                None => bail!("Interface of {label:?} water level sensor is not configured"),
+            },
+        };
+
+        sensor.with_context(|| format!("Failed to initialize {:?} water level sensor", label))
     }
 }
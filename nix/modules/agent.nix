@@ -49,16 +49,96 @@ let
     };
   };
 
-  mkSampleOptions = models: {
+  # the options of a sample section, sensorOptions are added to the options of each sensor
+  mkSampleOptions = models: sensorOptions: {
     sample_rate_secs = lib.mkOption {
       type = lib.types.ints.unsigned;
       example = 1800;
       description = "Rate in which the sensors will be sampled.";
     };
+    retry = {
+      retries = lib.mkOption {
+        type = lib.types.ints.unsigned;
+        default = 2;
+        description = "How often a failed measurement is retried before the sample is skipped.";
+      };
+      backoff_ms = lib.mkOption {
+        type = lib.types.ints.unsigned;
+        default = 500;
+        description = "The delay before the first retry in milliseconds, doubled with every further retry.";
+      };
+      reinit_after = lib.mkOption {
+        type = lib.types.ints.unsigned;
+        default = 3;
+        description = "The number of consecutive failed samples after which a sensor is initialized again.";
+      };
+      reinit_interval_secs = lib.mkOption {
+        type = lib.types.ints.unsigned;
+        default = 300;
+        description = "The minimum time between two initializations of a failing sensor in seconds.";
+      };
+    };
+    aggregate = {
+      readings = lib.mkOption {
+        type = lib.types.ints.positive;
+        default = 1;
+        description = "The number of readings taken per sample period.";
+      };
+      interval_ms = lib.mkOption {
+        type = lib.types.ints.unsigned;
+        default = 0;
+        description = "The delay between two readings in milliseconds.";
+      };
+      method = lib.mkOption {
+        type = (pkgs.formats.json { }).type;
+        default = "Median";
+        example = {
+          TrimmedMean = 0.2;
+        };
+        description = ''
+          How the readings are combined, either "Median" or the mean after
+          discarding a fraction of the lowest and highest readings.
+        '';
+      };
+    };
+    limits = lib.mkOption {
+      type = lib.types.attrsOf (
+        lib.types.submodule {
+          options = {
+            min = lib.mkOption {
+              type = lib.types.nullOr lib.types.number;
+              default = null;
+              description = "The lowest plausible value.";
+            };
+            max = lib.mkOption {
+              type = lib.types.nullOr lib.types.number;
+              default = null;
+              description = "The highest plausible value.";
+            };
+            max_rate_per_min = lib.mkOption {
+              type = lib.types.nullOr lib.types.number;
+              default = null;
+              description = "The maximum plausible change per minute.";
+            };
+          };
+        }
+      );
+      default = { };
+      example = {
+        distance = {
+          min = 30;
+          max = 1200;
+          max_rate_per_min = 50;
+        };
+      };
+      description = "The plausible ranges of the measured quantities by name.";
+    };
     sensors = lib.mkOption {
       type =
         with lib.types;
         attrsOf (submodule {
+          # allows additional, model specific options
+          freeformType = (pkgs.formats.json { }).type;
           options = {
             model = lib.mkOption {
               type = lib.types.enum models;
@@ -68,7 +148,61 @@ let
               type = lib.types.nonEmptyStr;
               description = "The address of the sensor";
             };
-          };
+            sample_rate_secs = lib.mkOption {
+              type = lib.types.nullOr lib.types.ints.unsigned;
+              default = null;
+              example = 60;
+              description = "Rate in which the sensor will be sampled, overrides the rate of the section.";
+            };
+            phase_offset_secs = lib.mkOption {
+              type = lib.types.ints.unsigned;
+              default = 0;
+              example = 15;
+              description = ''
+                The delay of each measurement after the tick aligned to the sample
+                rate in seconds, must be smaller than the sample rate.
+              '';
+            };
+            jitter_ms = lib.mkOption {
+              type = lib.types.ints.unsigned;
+              default = 0;
+              example = 500;
+              description = "The upper bound of a random delay added to each measurement in milliseconds.";
+            };
+            calibration = lib.mkOption {
+              type = lib.types.attrsOf (pkgs.formats.json { }).type;
+              default = { };
+              example = {
+                temperature = {
+                  mode = "Linear";
+                  offset = -1.3;
+                };
+                humidity = {
+                  mode = "Table";
+                  points = [
+                    {
+                      raw = 33;
+                      value = 30;
+                    }
+                    {
+                      raw = 78;
+                      value = 75;
+                    }
+                  ];
+                };
+              };
+              description = "The calibration of the measured quantities by name.";
+            };
+            measure_timeout_ms = lib.mkOption {
+              type = lib.types.nullOr lib.types.ints.positive;
+              default = null;
+              example = 5000;
+              description = ''
+                The time a single measurement or initialization of the sensor may
+                take in milliseconds, defaults to 30 seconds.
+              '';
+            };
+          } // sensorOptions;
         });
       default = { };
       example = lib.literalExpression ''
@@ -123,24 +257,174 @@ in
 
       air = {
         control = controlOptions;
-        sample = mkSampleOptions [ "Bme680" ];
+        sample = mkSampleOptions [ "Bme680" ] { } // {
+          leaf_temperature_offset = lib.mkOption {
+            type = lib.types.number;
+            default = 0;
+            example = -2;
+            description = ''
+              The difference between leaf and air temperature in degree celsius,
+              used to compute the leaf vapour pressure deficit.
+            '';
+          };
+          iaq = lib.mkOption {
+            type = (pkgs.formats.json { }).type;
+            default = { };
+            example = {
+              burn_in_secs = 600;
+              humidity_baseline = 45;
+            };
+            description = "The configuration of the indoor air quality estimation.";
+          };
+        };
       };
 
       air_pump.control = controlOptions;
       fan.control = controlOptions;
 
       light = {
-        sample = mkSampleOptions [ "Bh1750Fvi" ];
+        sample = mkSampleOptions [
+          "Bh1750Fvi"
+          "As7341"
+        ] { };
         control = controlOptions;
       };
 
       water_level = {
-        sample = mkSampleOptions [ "Vl53L0X" ];
+        sample =
+          mkSampleOptions
+            [
+              "Vl53L0X"
+              "Vl53L1X"
+              "JsnSr04T"
+            ]
+            {
+              address = lib.mkOption {
+                type = lib.types.nullOr lib.types.nonEmptyStr;
+                default = null;
+                description = "The address of the sensor, only used by the I2C models.";
+              };
+              signal_rate_limit = lib.mkOption {
+                type = lib.types.nullOr lib.types.number;
+                default = null;
+                example = 0.1;
+                description = ''
+                  The minimum return signal rate in mega counts per second for a
+                  valid range, overrides the signal rate limit of the profile.
+                '';
+              };
+              distance_mode = lib.mkOption {
+                type = lib.types.enum [
+                  "Short"
+                  "Long"
+                ];
+                default = "Long";
+                description = "The distance mode of a VL53L1X.";
+              };
+              interface = lib.mkOption {
+                type = lib.types.nullOr (
+                  lib.types.submodule {
+                    options = {
+                      mode = lib.mkOption {
+                        type = lib.types.enum [
+                          "Uart"
+                          "Gpio"
+                        ];
+                        description = "How the ultrasonic sensor is connected.";
+                      };
+                      path = lib.mkOption {
+                        type = lib.types.nullOr lib.types.nonEmptyStr;
+                        default = null;
+                        example = "/dev/ttyS0";
+                        description = "The serial port of the sensor in UART mode.";
+                      };
+                      trigger_pin = lib.mkOption {
+                        type = lib.types.nullOr lib.types.ints.unsigned;
+                        default = null;
+                        example = 23;
+                        description = "The GPIO pin that triggers a measurement in GPIO mode.";
+                      };
+                      echo_pin = lib.mkOption {
+                        type = lib.types.nullOr lib.types.ints.unsigned;
+                        default = null;
+                        example = 24;
+                        description = "The GPIO pin of the echo pulse in GPIO mode.";
+                      };
+                    };
+                  }
+                );
+                default = null;
+                description = "How an ultrasonic sensor is connected, required by JsnSr04T.";
+              };
+              tank = lib.mkOption {
+                type = lib.types.nullOr (pkgs.formats.json { }).type;
+                default = null;
+                example = {
+                  empty_distance = 900;
+                  full_distance = 100;
+                  shape = "Cylinder";
+                  diameter = 580;
+                };
+                description = "The geometry of the tank, used to compute the fill level and volume.";
+              };
+            };
         control = controlOptions;
       };
+
+      nutrient.sample = mkSampleOptions [
+        "EzoPh"
+        "EzoEc"
+        "EzoRtd"
+      ] { };
+
+      power.sample = mkSampleOptions [
+        "Ina219"
+        "Ina226"
+      ] { };
+
+      leaf.sample = mkSampleOptions [ "Mlx90614" ] { };
+
+      generic.sample = mkSampleOptions [ "Command" ] {
+        address = lib.mkOption {
+          type = lib.types.nullOr lib.types.nonEmptyStr;
+          default = null;
+          description = "The address of the sensor, only used by I2C models.";
+        };
+        settings = lib.mkOption {
+          type = (pkgs.formats.json { }).type;
+          default = { };
+          example = {
+            program = "curl";
+            args = [
+              "-s"
+              "http://plug.local/cm?cmnd=Status%208"
+            ];
+            quantities.power = {
+              unit = "W";
+              pointer = "/StatusSNS/ENERGY/Power";
+            };
+          };
+          description = "The settings of the model.";
+        };
+      };
     };
   };
 
+  config.assertions = lib.mkIf cfg.enable (
+    lib.flatten (
+      lib.mapAttrsToList (label: sensor: [
+        {
+          assertion = sensor.model == "JsnSr04T" || sensor.address != null;
+          message = "Water level sensor ${label} of model ${sensor.model} needs an address.";
+        }
+        {
+          assertion = sensor.model != "JsnSr04T" || sensor.interface != null;
+          message = "Water level sensor ${label} of model JsnSr04T needs an interface.";
+        }
+      ]) cfg.config.water_level.sample.sensors
+    )
+  );
+
   config.systemd.services.grow-agent =
     let
       mkControlConfig =
@@ -166,13 +450,30 @@ in
         else
           { inherit (opts) mode; };
 
+      # unset optional settings are omitted, so that the agent uses its defaults
+      filterNulls =
+        value:
+        if builtins.isAttrs value then
+          lib.mapAttrs (_: filterNulls) (lib.filterAttrs (_: v: v != null) value)
+        else if builtins.isList value then
+          map filterNulls value
+        else
+          value;
+
       mkSampleConfig =
-        opts: lib.optionalAttrs (opts.sensors != { }) { inherit (opts) sample_rate_secs sensors; };
+        opts:
+        lib.optionalAttrs (opts.sensors != { }) {
+          inherit (opts) sample_rate_secs retry aggregate;
+          limits = filterNulls opts.limits;
+          sensors = filterNulls opts.sensors;
+        };
 
       agentConfig = {
         air = {
           control = mkControlConfig cfg.config.air.control;
-          sample = mkSampleConfig cfg.config.air.sample;
+          sample = mkSampleConfig cfg.config.air.sample // {
+            inherit (cfg.config.air.sample) leaf_temperature_offset iaq;
+          };
         };
         air_pump.control = mkControlConfig cfg.config.air_pump.control;
         fan.control = mkControlConfig cfg.config.fan.control;
@@ -184,6 +485,10 @@ in
           control = mkControlConfig cfg.config.water_level.control;
           sample = mkSampleConfig cfg.config.water_level.sample;
         };
+        nutrient.sample = mkSampleConfig cfg.config.nutrient.sample;
+        power.sample = mkSampleConfig cfg.config.power.sample;
+        leaf.sample = mkSampleConfig cfg.config.leaf.sample;
+        generic.sample = mkSampleConfig cfg.config.generic.sample;
       };
     in
     lib.mkIf cfg.enable {
@@ -1,7 +1,10 @@
 use std::{env, str::FromStr};
 
 use anyhow::{bail, Context, Result};
-use grow_agent::measure::{bh1750fvi::Bh1750Fvi, bme680::Bme680, vl53l0x::Vl53L0X, Measure};
+use grow_agent::measure::{
+    as7341::As7341, bh1750fvi::Bh1750Fvi, bme680::Bme680, ezo_ec::EzoEc, ezo_ph::EzoPh, ezo_rtd::EzoRtd,
+    ina2xx::{self, Ina2xx}, mlx90614::Mlx90614, vl53l0x::Vl53L0X, vl53l1x::Vl53L1X, Measure,
+};
 use tokio_util::sync::CancellationToken;
 
 const I2C_PATH: &str = "/dev/i2c-1";
@@ -10,6 +13,7 @@ const I2C_PATH: &str = "/dev/i2c-1";
 struct Config {
     address: u8,
     variant: Variant,
+    calibration: Option<Calibration>,
 }
 
 #[derive(Debug)]
@@ -17,6 +21,20 @@ enum Variant {
     Bme680,
     Bh1750Fvi,
     Vl53L0X,
+    Vl53L1X,
+    As7341,
+    EzoPh,
+    EzoEc,
+    EzoRtd,
+    Ina219,
+    Ina226,
+    Mlx90614,
+}
+
+#[derive(Debug)]
+struct Calibration {
+    point: String,
+    value: Option<f64>,
 }
 
 impl FromStr for Variant {
@@ -27,6 +45,14 @@ impl FromStr for Variant {
             "bme680" => Ok(Self::Bme680),
             "bh1750fvi" => Ok(Self::Bh1750Fvi),
             "vl53l0x" => Ok(Self::Vl53L0X),
+            "vl53l1x" => Ok(Self::Vl53L1X),
+            "as7341" => Ok(Self::As7341),
+            "ezoph" => Ok(Self::EzoPh),
+            "ezoec" => Ok(Self::EzoEc),
+            "ezortd" => Ok(Self::EzoRtd),
+            "ina219" => Ok(Self::Ina219),
+            "ina226" => Ok(Self::Ina226),
+            "mlx90614" => Ok(Self::Mlx90614),
             arg => bail!("Unrecognized sensor model: {arg}"),
         }
     }
@@ -52,12 +78,44 @@ impl Config {
         let address = u8::from_str_radix(address.strip_prefix("0x").unwrap_or(&address), 16)
             .context("Failed to parse sensor address")?;
 
-        Ok(Config { address, variant })
+        let calibration = match args.next() {
+            Some(arg) if arg == "cal" => {
+                let Some(point) = args.next() else {
+                    print_usage(&name);
+                    bail!("No calibration point specified");
+                };
+                let value = args
+                    .next()
+                    .map(|v| v.parse())
+                    .transpose()
+                    .context("Failed to parse calibration value")?;
+
+                Some(Calibration { point, value })
+            }
+            Some(arg) => {
+                print_usage(&name);
+                bail!("Unknown argument {arg:?}");
+            }
+            None => None,
+        };
+
+        Ok(Config {
+            address,
+            variant,
+            calibration,
+        })
+    }
+}
+
+impl Calibration {
+    fn value(&self) -> Result<f64> {
+        self.value
+            .with_context(|| format!("No value specified for calibration point {:?}", self.point))
     }
 }
 
 fn print_usage(name: &str) {
-    println!("Usage: {name} <variant> <sensor_address>");
+    println!("Usage: {name} <variant> <sensor_address> [cal <point> [value]]");
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -76,7 +134,7 @@ async fn main() -> Result<(), anyhow::Error> {
             println!("{measurement:?}");
         }
         Variant::Bh1750Fvi => {
-            let mut sensor = Vl53L0X::new(I2C_PATH, config.address, "test".into())
+            let mut sensor = Bh1750Fvi::new(I2C_PATH, config.address, "test".into())
                 .await
                 .with_context(|| {
                     format!(
@@ -88,7 +146,7 @@ async fn main() -> Result<(), anyhow::Error> {
             println!("{measurement:?}");
         }
         Variant::Vl53L0X => {
-            let mut sensor = Bh1750Fvi::new(I2C_PATH, config.address, "test".into())
+            let mut sensor = Vl53L0X::new(I2C_PATH, config.address, "test".into())
                 .await
                 .with_context(|| {
                     format!("Failed to initialize VL53L0X sensor at {}", config.address)
@@ -96,6 +154,107 @@ async fn main() -> Result<(), anyhow::Error> {
             let measurement = sensor.measure(token).await?;
             println!("{measurement:?}");
         }
+        Variant::Vl53L1X => {
+            let mut sensor = Vl53L1X::new(I2C_PATH, config.address, "test".into())
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize VL53L1X sensor at {}", config.address)
+                })?;
+            let measurement = sensor.measure(token).await?;
+            println!("{measurement:?}");
+        }
+        Variant::As7341 => {
+            let mut sensor = As7341::new(I2C_PATH, config.address, "test".into())
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize AS7341 sensor at {}", config.address)
+                })?;
+            let measurement = sensor.measure(token).await?;
+            println!("{measurement:?}");
+        }
+        Variant::EzoPh => {
+            let mut sensor = EzoPh::new(I2C_PATH, config.address, "test".into())
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize EZO-pH sensor at {}", config.address)
+                })?;
+
+            match &config.calibration {
+                Some(cal) => {
+                    match cal.point.as_str() {
+                        "mid" => sensor.calibrate_mid(cal.value()?).await?,
+                        "low" => sensor.calibrate_low(cal.value()?).await?,
+                        "high" => sensor.calibrate_high(cal.value()?).await?,
+                        "clear" => sensor.clear_calibration().await?,
+                        point => bail!("Unknown pH calibration point {point:?}"),
+                    }
+                    let points = sensor.calibration_points().await?;
+                    println!("Calibrated points: {points}");
+                }
+                None => {
+                    let measurement = sensor.measure(token).await?;
+                    println!("{measurement:?}");
+                }
+            }
+        }
+        Variant::EzoEc => {
+            let mut sensor = EzoEc::new(I2C_PATH, config.address, "test".into())
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize EZO-EC sensor at {}", config.address)
+                })?;
+
+            match &config.calibration {
+                Some(cal) => {
+                    match cal.point.as_str() {
+                        "dry" => sensor.calibrate_dry().await?,
+                        "single" => sensor.calibrate_single(cal.value()?).await?,
+                        "low" => sensor.calibrate_low(cal.value()?).await?,
+                        "high" => sensor.calibrate_high(cal.value()?).await?,
+                        "clear" => sensor.clear_calibration().await?,
+                        point => bail!("Unknown EC calibration point {point:?}"),
+                    }
+                    let points = sensor.calibration_points().await?;
+                    println!("Calibrated points: {points}");
+                }
+                None => {
+                    let measurement = sensor.measure(token).await?;
+                    println!("{measurement:?}");
+                }
+            }
+        }
+        Variant::EzoRtd => {
+            let mut sensor = EzoRtd::new(I2C_PATH, config.address, "test".into())
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize EZO-RTD sensor at {}", config.address)
+                })?;
+            let measurement = sensor.measure(token).await?;
+            println!("{measurement:?}");
+        }
+        Variant::Ina219 | Variant::Ina226 => {
+            let variant = match config.variant {
+                Variant::Ina219 => ina2xx::Variant::Ina219,
+                _ => ina2xx::Variant::Ina226,
+            };
+            // assumes the 0.1 ohm shunt of common breakout boards
+            let mut sensor = Ina2xx::new(I2C_PATH, config.address, "test".into(), variant)
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize {variant:?} sensor at {}", config.address)
+                })?;
+            let measurement = sensor.measure(token).await?;
+            println!("{measurement:?}");
+        }
+        Variant::Mlx90614 => {
+            let mut sensor = Mlx90614::new(I2C_PATH, config.address, "test".into())
+                .await
+                .with_context(|| {
+                    format!("Failed to initialize MLX90614 sensor at {}", config.address)
+                })?;
+            let measurement = sensor.measure(token).await?;
+            println!("{measurement:?}");
+        }
     }
 
     Ok(())
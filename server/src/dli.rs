@@ -0,0 +1,136 @@
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::integrate::{integrate_daily, Sample};
+
+/// A light sample as stored by the agent.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct LightSample {
+    /// The number of milliseconds since unix epoch.
+    pub measure_time: i64,
+    pub label: String,
+    pub illuminance: Option<f64>,
+    pub ppfd: Option<f64>,
+}
+
+/// The Daily Light Integral of a single sensor on a single day.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyLightIntegral {
+    /// The start of the (UTC) day in milliseconds since unix epoch.
+    pub time: i64,
+    /// The label of the sensor.
+    pub label: String,
+    /// The Daily Light Integral in mol/m²/day.
+    pub dli: f64,
+    /// The fraction of the day that is covered by samples.
+    pub coverage: f64,
+}
+
+impl Sample for LightSample {
+    fn measure_time(&self) -> i64 {
+        self.measure_time
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+/// Computes the Daily Light Integral per label and day from samples that are ordered by label
+/// and measure time.
+///
+/// The PPFD is integrated like any other value, see [`integrate_daily`]. Samples without a
+/// stored PPFD value are converted from the illuminance with `ppfd_factor`.
+pub fn daily_light_integrals(
+    samples: &[LightSample],
+    ppfd_factor: f64,
+    max_gap_secs: i64,
+) -> Vec<DailyLightIntegral> {
+    let ppfd = |s: &LightSample| s.ppfd.or(s.illuminance.map(|i| i * ppfd_factor));
+
+    integrate_daily(samples, |a, b| Some((ppfd(a)?, ppfd(b)?)), max_gap_secs)
+        .into_iter()
+        .map(|((label, time), day)| DailyLightIntegral {
+            time,
+            label: label.to_owned(),
+            // from µmol/m² to mol/m²
+            dli: day.integral / 1_000_000.,
+            coverage: day.coverage(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrate::{MILLIS_PER_DAY, MILLIS_PER_SEC, SECS_PER_DAY};
+
+    fn sample(secs: i64, label: &str, ppfd: f64) -> LightSample {
+        LightSample {
+            measure_time: secs * MILLIS_PER_SEC,
+            label: label.into(),
+            illuminance: None,
+            ppfd: Some(ppfd),
+        }
+    }
+
+    #[test]
+    fn constant_ppfd_ok() {
+        // 500 µmol/m²/s for 12 hours, sampled unevenly
+        let samples = [
+            sample(0, "a", 500.),
+            sample(600, "a", 500.),
+            sample(7200, "a", 500.),
+            sample(43200, "a", 500.),
+        ];
+        let dli = daily_light_integrals(&samples, 0., SECS_PER_DAY);
+
+        assert_eq!(dli.len(), 1);
+        assert!((dli[0].dli - 21.6).abs() < 1e-9);
+        assert!((dli[0].coverage - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn split_at_midnight_ok() {
+        let samples = [
+            sample(SECS_PER_DAY - 3600, "a", 100.),
+            sample(SECS_PER_DAY + 3600, "a", 300.),
+        ];
+        let dli = daily_light_integrals(&samples, 0., SECS_PER_DAY);
+
+        assert_eq!(dli.len(), 2);
+        assert_eq!(dli[0].time, 0);
+        assert_eq!(dli[1].time, MILLIS_PER_DAY);
+        // linear from 100 to 200 and from 200 to 300 µmol/m²/s
+        assert!((dli[0].dli - 150. * 3600. / 1e6).abs() < 1e-9);
+        assert!((dli[1].dli - 250. * 3600. / 1e6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gaps_and_labels_ok() {
+        let samples = [
+            sample(0, "a", 100.),
+            sample(100, "a", 100.),
+            sample(10000, "a", 100.),
+            LightSample {
+                measure_time: 200_000,
+                label: "b".into(),
+                illuminance: Some(10000.),
+                ppfd: None,
+            },
+            LightSample {
+                measure_time: 300_000,
+                label: "b".into(),
+                illuminance: Some(10000.),
+                ppfd: None,
+            },
+        ];
+        let dli = daily_light_integrals(&samples, 0.02, 3600);
+
+        assert_eq!(dli.len(), 2);
+        assert_eq!(dli[0].label, "a");
+        assert!((dli[0].dli - 100. * 100. / 1e6).abs() < 1e-9);
+        assert_eq!(dli[1].label, "b");
+        assert!((dli[1].dli - 200. * 100. / 1e6).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+
+use grow_agent::measure::ControllerKind;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::integrate::{integrate_daily, Integral, Sample, MILLIS_PER_DAY};
+
+const SECS_PER_HOUR: f64 = 3600.;
+
+/// A power sample as stored by the agent.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct PowerSample {
+    /// The number of milliseconds since unix epoch.
+    pub measure_time: i64,
+    pub label: String,
+    pub controller: Option<ControllerKind>,
+    /// Whether the controller activated the load, unknown while it's disabled.
+    pub load_active: Option<bool>,
+    pub power: Option<f64>,
+}
+
+/// The energy consumed by the load of a single sensor on a single day.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyEnergy {
+    /// The start of the (UTC) day in milliseconds since unix epoch.
+    pub time: i64,
+    /// The label of the sensor.
+    pub label: String,
+    /// The controller that switches the load, as of the last sample of the day.
+    pub controller: Option<ControllerKind>,
+    /// The energy in Wh.
+    pub energy: f64,
+    /// The energy in Wh consumed while the controller activated the load.
+    pub active_energy: f64,
+    /// The energy in Wh consumed while the controller deactivated the load, e.g. the standby
+    /// power of a driver.
+    pub inactive_energy: f64,
+    /// The fraction of the day that is covered by samples.
+    pub coverage: f64,
+}
+
+impl Sample for PowerSample {
+    fn measure_time(&self) -> i64 {
+        self.measure_time
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+/// Computes the consumed energy per label and day from samples that are ordered by label and
+/// measure time.
+///
+/// The power is integrated like any other value, see [`integrate_daily`]. The energy of an
+/// interval is attributed to the state of the load at its start, intervals with an unknown state
+/// only count towards the total energy.
+pub fn daily_energies(samples: &[PowerSample], max_gap_secs: i64) -> Vec<DailyEnergy> {
+    let power = |a: &PowerSample, b: &PowerSample| Some((a.power?, b.power?));
+    let in_state = |active| {
+        move |a: &PowerSample, b: &PowerSample| {
+            power(a, b).filter(|_| a.load_active == Some(active))
+        }
+    };
+    let days = integrate_daily(samples, power, max_gap_secs);
+    let active = integrate_daily(samples, in_state(true), max_gap_secs);
+    let inactive = integrate_daily(samples, in_state(false), max_gap_secs);
+
+    let mut controllers = BTreeMap::new();
+    for s in samples {
+        let day = s.measure_time.div_euclid(MILLIS_PER_DAY) * MILLIS_PER_DAY;
+        if let Some(controller) = s.controller {
+            controllers.insert((s.label.as_str(), day), controller);
+        }
+    }
+
+    let energy = |days: &BTreeMap<(&str, i64), Integral>, key| {
+        days.get(key).map_or(0., |day| day.integral / SECS_PER_HOUR)
+    };
+    days.iter()
+        .map(|(key @ (label, time), day)| DailyEnergy {
+            time: *time,
+            label: (*label).to_owned(),
+            controller: controllers.get(key).copied(),
+            energy: day.integral / SECS_PER_HOUR,
+            active_energy: energy(&active, key),
+            inactive_energy: energy(&inactive, key),
+            coverage: day.coverage(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrate::{MILLIS_PER_SEC, SECS_PER_DAY};
+
+    fn sample(secs: i64, label: &str, power: f64) -> PowerSample {
+        PowerSample {
+            measure_time: secs * MILLIS_PER_SEC,
+            label: label.into(),
+            controller: None,
+            load_active: None,
+            power: Some(power),
+        }
+    }
+
+    #[test]
+    fn constant_power_ok() {
+        // a 250 W light for 12 hours, sampled unevenly
+        let samples = [
+            sample(0, "light", 250.),
+            sample(60, "light", 250.),
+            sample(7200, "light", 250.),
+            sample(43200, "light", 250.),
+        ];
+        let energy = daily_energies(&samples, SECS_PER_DAY);
+
+        assert_eq!(energy.len(), 1);
+        assert!((energy[0].energy - 3000.).abs() < 1e-9);
+        assert!((energy[0].coverage - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn split_at_midnight_ok() {
+        let samples = [
+            sample(SECS_PER_DAY - 3600, "fan", 10.),
+            sample(SECS_PER_DAY + 3600, "fan", 30.),
+        ];
+        let energy = daily_energies(&samples, SECS_PER_DAY);
+
+        assert_eq!(energy.len(), 2);
+        assert_eq!(energy[0].time, 0);
+        assert_eq!(energy[1].time, MILLIS_PER_DAY);
+        // linear from 10 to 20 W and from 20 to 30 W
+        assert!((energy[0].energy - 15.).abs() < 1e-9);
+        assert!((energy[1].energy - 25.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gaps_and_labels_ok() {
+        let mut samples = vec![
+            sample(0, "fan", 20.),
+            sample(1800, "fan", 20.),
+            sample(18000, "fan", 20.),
+        ];
+        samples.extend([0, 3600].map(|secs| PowerSample {
+            controller: Some(ControllerKind::Light),
+            ..sample(secs, "light", 100.)
+        }));
+        let energy = daily_energies(&samples, 3600);
+
+        assert_eq!(energy.len(), 2);
+        assert_eq!(energy[0].label, "fan");
+        assert_eq!(energy[0].controller, None);
+        assert!((energy[0].energy - 10.).abs() < 1e-9);
+        assert_eq!(energy[1].label, "light");
+        assert_eq!(energy[1].controller, Some(ControllerKind::Light));
+        assert!((energy[1].energy - 100.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn load_state_ok() {
+        // a light that is switched off after two hours and draws 2 W in standby
+        let samples = [(0, true, 200.), (7200, false, 2.), (10800, false, 2.)].map(
+            |(secs, active, power)| PowerSample {
+                controller: Some(ControllerKind::Light),
+                load_active: Some(active),
+                ..sample(secs, "light", power)
+            },
+        );
+        let energy = daily_energies(&samples, 7200);
+
+        assert_eq!(energy.len(), 1);
+        assert!((energy[0].active_energy - 202.).abs() < 1e-9);
+        assert!((energy[0].inactive_energy - 2.).abs() < 1e-9);
+        assert!((energy[0].energy - 204.).abs() < 1e-9);
+    }
+}
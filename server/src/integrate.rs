@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+pub const SECS_PER_DAY: i64 = 86400;
+pub const MILLIS_PER_SEC: i64 = 1000;
+pub const MILLIS_PER_DAY: i64 = SECS_PER_DAY * MILLIS_PER_SEC;
+
+/// A sample of a single sensor at a point in time.
+pub trait Sample {
+    /// The number of milliseconds since unix epoch.
+    fn measure_time(&self) -> i64;
+    fn label(&self) -> &str;
+}
+
+/// The integral of a value of a single sensor over a single day.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Integral {
+    /// The integral of the value over time in seconds.
+    pub integral: f64,
+    /// The number of seconds of the day that are covered by samples.
+    pub covered: f64,
+}
+
+impl Integral {
+    /// The fraction of the day that is covered by samples.
+    pub fn coverage(&self) -> f64 {
+        self.covered / (SECS_PER_DAY as f64)
+    }
+}
+
+/// Integrates a value per label and UTC day from samples that are ordered by label and measure
+/// time. The days are keyed by label and their start in milliseconds since unix epoch.
+///
+/// `values` returns the values at the start and end of the interval between two consecutive
+/// samples, or `None` if the interval doesn't contribute. The value between two samples is
+/// interpolated linearly, which copes with uneven sample spacing. Intervals longer than
+/// `max_gap_secs` are treated as gaps without data and don't contribute to the integral.
+pub fn integrate_daily<S: Sample>(
+    samples: &[S],
+    values: impl Fn(&S, &S) -> Option<(f64, f64)>,
+    max_gap_secs: i64,
+) -> BTreeMap<(&str, i64), Integral> {
+    let mut days: BTreeMap<(&str, i64), Integral> = BTreeMap::new();
+
+    for pair in samples.windows(2) {
+        let (first, second) = (&pair[0], &pair[1]);
+        if first.label() != second.label() {
+            continue;
+        }
+
+        let Some((v0, v1)) = values(first, second) else {
+            continue;
+        };
+
+        let (t0, t1) = (first.measure_time(), second.measure_time());
+        if t1 <= t0 || t1 - t0 > max_gap_secs * MILLIS_PER_SEC {
+            continue;
+        }
+
+        // split the interval at day boundaries
+        let slope = (v1 - v0) / ((t1 - t0) as f64);
+        let mut start = t0;
+        while start < t1 {
+            let day = start.div_euclid(MILLIS_PER_DAY) * MILLIS_PER_DAY;
+            let end = t1.min(day + MILLIS_PER_DAY);
+            let v_start = v0 + slope * ((start - t0) as f64);
+            let v_end = v0 + slope * ((end - t0) as f64);
+            let duration = (end - start) as f64 / MILLIS_PER_SEC as f64;
+
+            let entry = days.entry((first.label(), day)).or_default();
+            entry.integral += (v_start + v_end) / 2. * duration;
+            entry.covered += duration;
+            start = end;
+        }
+    }
+
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Point(i64, f64);
+
+    impl Sample for Point {
+        fn measure_time(&self) -> i64 {
+            self.0 * MILLIS_PER_SEC
+        }
+
+        fn label(&self) -> &str {
+            "a"
+        }
+    }
+
+    #[test]
+    fn integrate_daily_ok() {
+        let samples = [Point(0, 1.), Point(10, 3.), Point(20, 3.), Point(100, 3.)];
+        let days = integrate_daily(&samples, |a, b| Some((a.1, b.1)), 60);
+
+        // the last interval is a gap
+        assert_eq!(
+            days.into_iter().collect::<Vec<_>>(),
+            vec![(
+                ("a", 0),
+                Integral {
+                    integral: 50.,
+                    covered: 20.
+                }
+            )]
+        );
+
+        // intervals can be skipped based on their samples
+        let days = integrate_daily(&samples, |a, b| (a.1 == b.1).then_some((a.1, b.1)), 60);
+        assert_eq!(days[&("a", 0)].integral, 30.);
+    }
+}
@@ -4,6 +4,9 @@ use server::Server;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod config;
+mod dli;
+mod energy;
+mod integrate;
 mod server;
 
 #[tokio::main]
@@ -13,14 +13,25 @@ use axum::{
     routing::get,
     Json, Router,
 };
-use grow_agent::measure::{AirMeasurement, LightMeasurement, WaterLevelMeasurement};
+use grow_agent::{
+    config::light::LightSource,
+    measure::{
+        registry::{ReadingValue, Registration, MODELS},
+        AirMeasurement, BusStatus, LeafMeasurement, LightMeasurement, NutrientMeasurement,
+        PowerMeasurement, SensorStatus, WaterLevelMeasurement,
+    },
+};
 use serde::Deserialize;
 use sqlx::SqlitePool;
 use thiserror::Error;
 use tokio::sync::{RwLock, RwLockWriteGuard};
 use tower_http::trace::TraceLayer;
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    dli::{daily_light_integrals, DailyLightIntegral, LightSample},
+    energy::{daily_energies, DailyEnergy, PowerSample},
+};
 
 #[derive(Debug, Clone)]
 struct ServerState {
@@ -59,13 +70,53 @@ impl Display for ServerError {
     }
 }
 
+const MILLIS_PER_SEC: i64 = 1000;
+
 #[derive(Debug, Deserialize)]
 struct TimeParams {
+    /// The start of the time range in seconds since unix epoch.
     from: i64,
+    /// The end of the time range in seconds since unix epoch.
     to: i64,
     interval_ms: i64,
 }
 
+impl TimeParams {
+    /// Returns the time range in milliseconds since unix epoch, including the whole last second.
+    fn range_millis(&self) -> (i64, i64) {
+        (
+            self.from * MILLIS_PER_SEC,
+            self.to * MILLIS_PER_SEC + MILLIS_PER_SEC - 1,
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DliParams {
+    from: i64,
+    to: i64,
+    /// The factor to convert lux to µmol/m²/s for measurements without a PPFD value.
+    ppfd_factor: Option<f64>,
+    /// The maximum duration between two measurements that is still interpolated.
+    max_gap_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EnergyParams {
+    from: i64,
+    to: i64,
+    /// The maximum duration between two measurements that is still interpolated.
+    max_gap_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadingParams {
+    /// Only return readings of the sensor with this label.
+    label: Option<String>,
+    /// Only return values of this quantity.
+    quantity: Option<String>,
+}
+
 pub struct Server {
     config: Config,
 }
@@ -88,12 +139,24 @@ impl Server {
         };
         let router = Router::new()
             .route("/grows", get(grows))
+            .route("/models", get(models))
             .route("/:grow_id/air_measurements", get(air_measurements))
             .route("/:grow_id/light_measurements", get(light_measurements))
+            .route("/:grow_id/daily_light_integral", get(daily_light_integral))
             .route(
                 "/:grow_id/water_level_measurements",
                 get(water_level_measurements),
             )
+            .route(
+                "/:grow_id/nutrient_measurements",
+                get(nutrient_measurements),
+            )
+            .route("/:grow_id/power_measurements", get(power_measurements))
+            .route("/:grow_id/daily_energy", get(daily_energy))
+            .route("/:grow_id/leaf_measurements", get(leaf_measurements))
+            .route("/:grow_id/readings", get(readings))
+            .route("/:grow_id/sensor_status", get(sensor_status))
+            .route("/:grow_id/bus_status", get(bus_status))
             .layer(TraceLayer::new_for_http())
             .with_state(state);
 
@@ -185,7 +248,8 @@ async fn air_measurements(
             source,
             code: StatusCode::NOT_FOUND,
         })?;
-    let interval = time_params.interval_ms / 1000;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
 
     let measurements = sqlx::query_as::<_, AirMeasurement>(
         r#"
@@ -195,15 +259,23 @@ async fn air_measurements(
         temperature,
         humidity,
         pressure,
-        resistance FROM air_measurements
+        resistance,
+        iaq,
+        vpd,
+        dew_point,
+        absolute_humidity,
+        raw_temperature,
+        raw_humidity,
+        raw_pressure,
+        raw_resistance FROM air_measurements
         WHERE measure_time BETWEEN $2 AND $3
         GROUP BY time, label
         ORDER BY measure_time ASC;
     "#,
     )
     .bind(interval)
-    .bind(time_params.from)
-    .bind(time_params.to)
+    .bind(from)
+    .bind(to)
     .fetch_all(pool)
     .await
     .context("Failed to query air measurements")
@@ -228,22 +300,36 @@ async fn light_measurements(
             source,
             code: StatusCode::NOT_FOUND,
         })?;
-    let interval = time_params.interval_ms / 1000;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
 
     let measurements = sqlx::query_as::<_, LightMeasurement>(
         r#"
         SELECT cast(("measure_time" / $1) as int) * $1 AS time,
         measure_time,
         label,
-        illuminance FROM light_measurements
+        illuminance,
+        ppfd,
+        f1,
+        f2,
+        f3,
+        f4,
+        f5,
+        f6,
+        f7,
+        f8,
+        clear,
+        nir,
+        raw_illuminance,
+        raw_ppfd FROM light_measurements
         WHERE measure_time BETWEEN $2 AND $3
         GROUP BY time, label
         ORDER BY measure_time ASC;
     "#,
     )
     .bind(interval)
-    .bind(time_params.from)
-    .bind(time_params.to)
+    .bind(from)
+    .bind(to)
     .fetch_all(pool)
     .await
     .context("Failed to query light measurements")
@@ -255,6 +341,58 @@ async fn light_measurements(
     Ok(Json(measurements))
 }
 
+async fn daily_light_integral(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+    params: Query<DliParams>,
+) -> Result<Json<Vec<DailyLightIntegral>>, ServerError> {
+    const SECS_PER_DAY: i64 = 86400;
+    const DEFAULT_MAX_GAP_SECS: i64 = 7200;
+
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+
+    // always integrate whole days
+    let from = params.from.div_euclid(SECS_PER_DAY) * SECS_PER_DAY;
+    let to = (params.to.div_euclid(SECS_PER_DAY) + 1) * SECS_PER_DAY;
+
+    let samples = sqlx::query_as::<_, LightSample>(
+        r#"
+        SELECT measure_time,
+        label,
+        illuminance,
+        ppfd FROM light_measurements
+        WHERE measure_time BETWEEN $1 AND $2
+        ORDER BY label ASC, measure_time ASC;
+    "#,
+    )
+    .bind(from * MILLIS_PER_SEC)
+    .bind(to * MILLIS_PER_SEC)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query light measurements")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    let dli = daily_light_integrals(
+        &samples,
+        params
+            .ppfd_factor
+            .unwrap_or(LightSource::Sunlight.ppfd_factor()),
+        params.max_gap_secs.unwrap_or(DEFAULT_MAX_GAP_SECS),
+    );
+
+    Ok(Json(dli))
+}
+
 async fn water_level_measurements(
     State(state): State<ServerSubState>,
     extract::Path(grow_id): extract::Path<String>,
@@ -268,22 +406,27 @@ async fn water_level_measurements(
             source,
             code: StatusCode::NOT_FOUND,
         })?;
-    let interval = time_params.interval_ms / 1000;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
 
     let measurements = sqlx::query_as::<_, WaterLevelMeasurement>(
         r#"
         SELECT cast(("measure_time" / $1) as int) * $1 AS time,
         measure_time,
         label,
-        distance FROM water_level_measurements
+        distance,
+        range_status,
+        fill_level,
+        volume,
+        raw_distance FROM water_level_measurements
         WHERE measure_time BETWEEN $2 AND $3
         GROUP BY time, label
         ORDER BY measure_time ASC;
     "#,
     )
     .bind(interval)
-    .bind(time_params.from)
-    .bind(time_params.to)
+    .bind(from)
+    .bind(to)
     .fetch_all(pool)
     .await
     .context("Failed to query water level measurements")
@@ -294,3 +437,319 @@ async fn water_level_measurements(
 
     Ok(Json(measurements))
 }
+
+async fn nutrient_measurements(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+    time_params: Query<TimeParams>,
+) -> Result<Json<Vec<NutrientMeasurement>>, ServerError> {
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
+
+    let measurements = sqlx::query_as::<_, NutrientMeasurement>(
+        r#"
+        SELECT cast(("measure_time" / $1) as int) * $1 AS time,
+        measure_time,
+        label,
+        ph,
+        conductivity,
+        temperature,
+        raw_ph,
+        raw_conductivity,
+        raw_temperature FROM nutrient_measurements
+        WHERE measure_time BETWEEN $2 AND $3
+        GROUP BY time, label
+        ORDER BY measure_time ASC;
+    "#,
+    )
+    .bind(interval)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query nutrient measurements")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(measurements))
+}
+
+async fn power_measurements(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+    time_params: Query<TimeParams>,
+) -> Result<Json<Vec<PowerMeasurement>>, ServerError> {
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
+
+    let measurements = sqlx::query_as::<_, PowerMeasurement>(
+        r#"
+        SELECT cast(("measure_time" / $1) as int) * $1 AS time,
+        measure_time,
+        label,
+        controller,
+        load_active,
+        voltage,
+        current,
+        power,
+        raw_voltage,
+        raw_current,
+        raw_power FROM power_measurements
+        WHERE measure_time BETWEEN $2 AND $3
+        GROUP BY time, label
+        ORDER BY measure_time ASC;
+    "#,
+    )
+    .bind(interval)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query power measurements")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(measurements))
+}
+
+async fn leaf_measurements(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+    time_params: Query<TimeParams>,
+) -> Result<Json<Vec<LeafMeasurement>>, ServerError> {
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
+
+    let measurements = sqlx::query_as::<_, LeafMeasurement>(
+        r#"
+        SELECT cast(("measure_time" / $1) as int) * $1 AS time,
+        measure_time,
+        label,
+        object_temperature,
+        ambient_temperature,
+        raw_object_temperature,
+        raw_ambient_temperature FROM leaf_measurements
+        WHERE measure_time BETWEEN $2 AND $3
+        GROUP BY time, label
+        ORDER BY measure_time ASC;
+    "#,
+    )
+    .bind(interval)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query leaf measurements")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(measurements))
+}
+
+async fn daily_energy(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+    params: Query<EnergyParams>,
+) -> Result<Json<Vec<DailyEnergy>>, ServerError> {
+    const SECS_PER_DAY: i64 = 86400;
+    const DEFAULT_MAX_GAP_SECS: i64 = 3600;
+
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+
+    // always integrate whole days
+    let from = params.from.div_euclid(SECS_PER_DAY) * SECS_PER_DAY;
+    let to = (params.to.div_euclid(SECS_PER_DAY) + 1) * SECS_PER_DAY;
+
+    let samples = sqlx::query_as::<_, PowerSample>(
+        r#"
+        SELECT measure_time,
+        label,
+        controller,
+        load_active,
+        power FROM power_measurements
+        WHERE measure_time BETWEEN $1 AND $2
+        ORDER BY label ASC, measure_time ASC;
+    "#,
+    )
+    .bind(from * MILLIS_PER_SEC)
+    .bind(to * MILLIS_PER_SEC)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query power measurements")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    let energy = daily_energies(
+        &samples,
+        params.max_gap_secs.unwrap_or(DEFAULT_MAX_GAP_SECS),
+    );
+
+    Ok(Json(energy))
+}
+
+async fn models() -> Json<&'static [Registration]> {
+    Json(MODELS)
+}
+
+async fn readings(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+    time_params: Query<TimeParams>,
+    params: Query<ReadingParams>,
+) -> Result<Json<Vec<ReadingValue>>, ServerError> {
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+    let interval = time_params.interval_ms.max(1);
+    let (from, to) = time_params.range_millis();
+
+    let readings = sqlx::query_as::<_, ReadingValue>(
+        r#"
+        SELECT cast(("measure_time" / $1) as int) * $1 AS time,
+        measure_time,
+        label,
+        model,
+        quantity,
+        unit,
+        value,
+        raw_value FROM readings
+        WHERE measure_time BETWEEN $2 AND $3
+        AND ($4 IS NULL OR label = $4)
+        AND ($5 IS NULL OR quantity = $5)
+        GROUP BY time, label, quantity
+        ORDER BY measure_time ASC;
+    "#,
+    )
+    .bind(interval)
+    .bind(from)
+    .bind(to)
+    .bind(&params.label)
+    .bind(&params.quantity)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query readings")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(readings))
+}
+
+async fn sensor_status(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+) -> Result<Json<Vec<SensorStatus>>, ServerError> {
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+
+    let status = sqlx::query_as::<_, SensorStatus>(
+        r#"
+        SELECT kind,
+        label,
+        failures,
+        retries,
+        reinits,
+        consecutive_failures,
+        last_error,
+        last_failure_time,
+        present FROM sensor_status
+        ORDER BY kind ASC, label ASC;
+    "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to query sensor status")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(status))
+}
+
+async fn bus_status(
+    State(state): State<ServerSubState>,
+    extract::Path(grow_id): extract::Path<String>,
+) -> Result<Json<Vec<BusStatus>>, ServerError> {
+    let pools = state.pools.read().await;
+    let pool = pools
+        .get(&grow_id)
+        .with_context(|| format!("Unknown grow ID {grow_id:?}"))
+        .map_err(|source| ServerError {
+            source,
+            code: StatusCode::NOT_FOUND,
+        })?;
+
+    let status = sqlx::query_as::<_, BusStatus>(
+        r#"
+        SELECT path,
+        transactions,
+        errors,
+        nacks,
+        timeouts,
+        arbitration_lost,
+        update_time FROM bus_status
+        ORDER BY path ASC;
+    "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to query bus status")
+    .map_err(|source| ServerError {
+        source,
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+
+    Ok(Json(status))
+}